@@ -0,0 +1,29 @@
+CREATE TABLE webhooks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    url VARCHAR NOT NULL,
+    secret VARCHAR NOT NULL,
+    event_types VARCHAR[] NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT 't',
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+SELECT diesel_manage_updated_at('webhooks');
+
+CREATE TABLE webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    webhook_id UUID NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    event_type VARCHAR NOT NULL,
+    payload JSONB NOT NULL,
+    response_code INTEGER,
+    error VARCHAR,
+    succeeded BOOLEAN NOT NULL DEFAULT 'f',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id);
+CREATE INDEX webhook_deliveries_succeeded_idx ON webhook_deliveries (succeeded);
+
+SELECT diesel_manage_updated_at('webhook_deliveries');
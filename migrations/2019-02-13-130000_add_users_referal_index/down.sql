@@ -0,0 +1 @@
+DROP INDEX IF EXISTS users_referal_idx;
@@ -0,0 +1 @@
+CREATE INDEX users_referal_idx ON users (referal) WHERE referal IS NOT NULL;
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS users_created_at_idx;
+DROP INDEX IF EXISTS users_country_idx;
+DROP INDEX IF EXISTS users_utm_campaign_idx;
+DROP INDEX IF EXISTS users_utm_medium_idx;
+DROP INDEX IF EXISTS users_utm_source_idx;
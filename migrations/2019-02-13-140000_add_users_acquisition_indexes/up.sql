@@ -0,0 +1,5 @@
+CREATE INDEX users_utm_source_idx ON users ((utm_marks ->> 'utm_source'));
+CREATE INDEX users_utm_medium_idx ON users ((utm_marks ->> 'utm_medium'));
+CREATE INDEX users_utm_campaign_idx ON users ((utm_marks ->> 'utm_campaign'));
+CREATE INDEX users_country_idx ON users (country);
+CREATE INDEX users_created_at_idx ON users (created_at);
@@ -0,0 +1,12 @@
+CREATE TABLE login_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    provider VARCHAR NOT NULL,
+    ip VARCHAR,
+    user_agent VARCHAR,
+    succeeded BOOLEAN NOT NULL,
+    failure_reason VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX login_events_user_id_created_at_idx ON login_events (user_id, created_at DESC);
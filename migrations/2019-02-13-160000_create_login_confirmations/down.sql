@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS login_confirmations;
+
+ALTER TABLE login_events DROP COLUMN IF EXISTS country;
+ALTER TABLE login_events DROP COLUMN IF EXISTS device;
@@ -0,0 +1,17 @@
+ALTER TABLE login_events ADD COLUMN device VARCHAR;
+ALTER TABLE login_events ADD COLUMN country VARCHAR;
+
+CREATE TABLE login_confirmations (
+    token VARCHAR PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    provider VARCHAR NOT NULL,
+    ip VARCHAR,
+    user_agent VARCHAR,
+    device VARCHAR,
+    country VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    expires_at TIMESTAMP NOT NULL,
+    confirmed_at TIMESTAMP
+);
+
+CREATE INDEX login_confirmations_user_id_idx ON login_confirmations (user_id);
@@ -0,0 +1,15 @@
+CREATE TABLE user_blocks (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    reason VARCHAR,
+    blocked_by INTEGER REFERENCES users (id) ON DELETE SET NULL,
+    blocked_until TIMESTAMP,
+    unblocked_by INTEGER REFERENCES users (id) ON DELETE SET NULL,
+    unblocked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX user_blocks_user_id_created_at_idx ON user_blocks (user_id, created_at DESC);
+
+-- blocks made before the history existed are permanent and have no reason
+INSERT INTO user_blocks (user_id) SELECT id FROM users WHERE is_blocked = true;
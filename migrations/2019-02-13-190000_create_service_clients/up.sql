@@ -0,0 +1,8 @@
+CREATE TABLE service_clients (
+    client_id VARCHAR PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    secret_hash VARCHAR NOT NULL,
+    role VARCHAR NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
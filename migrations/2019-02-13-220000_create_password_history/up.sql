@@ -0,0 +1,8 @@
+CREATE TABLE password_history (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id INTEGER NOT NULL REFERENCES identities (user_id) ON DELETE CASCADE,
+    password VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX password_history_user_id_created_at_idx ON password_history (user_id, created_at DESC);
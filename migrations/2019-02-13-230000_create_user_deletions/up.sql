@@ -0,0 +1,9 @@
+CREATE TABLE user_deletions (
+    user_id INTEGER PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    token VARCHAR NOT NULL UNIQUE,
+    requested_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    delete_after TIMESTAMP NOT NULL,
+    completed_at TIMESTAMP
+);
+
+CREATE INDEX user_deletions_delete_after_idx ON user_deletions (delete_after) WHERE completed_at IS NULL;
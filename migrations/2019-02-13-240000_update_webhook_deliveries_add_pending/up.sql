@@ -0,0 +1,5 @@
+ALTER TABLE webhook_deliveries ADD COLUMN pending BOOLEAN NOT NULL DEFAULT 't';
+
+UPDATE webhook_deliveries SET pending = 'f';
+
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries (created_at) WHERE pending;
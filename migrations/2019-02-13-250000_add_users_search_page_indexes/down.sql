@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS users_full_name_id_idx;
+DROP INDEX IF EXISTS users_email_id_idx;
+DROP INDEX IF EXISTS users_last_login_at_id_idx;
+DROP INDEX IF EXISTS users_created_at_id_idx;
@@ -0,0 +1,4 @@
+CREATE INDEX users_created_at_id_idx ON users (created_at, id);
+CREATE INDEX users_last_login_at_id_idx ON users (last_login_at, id);
+CREATE INDEX users_email_id_idx ON users (email, id);
+CREATE INDEX users_full_name_id_idx ON users ((COALESCE(first_name || ' ' || last_name, first_name, last_name, '')), id);
@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS set_updated_at ON users;
+SELECT diesel_manage_updated_at('users');
+DROP FUNCTION IF EXISTS users_set_updated_at();
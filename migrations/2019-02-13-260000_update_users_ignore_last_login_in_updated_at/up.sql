@@ -0,0 +1,15 @@
+-- Sign-ins only set last_login_at, they must not change updated_at of the user
+CREATE OR REPLACE FUNCTION users_set_updated_at() RETURNS trigger AS $$
+BEGIN
+    IF (
+        to_jsonb(NEW) - 'last_login_at' - 'updated_at' IS DISTINCT FROM to_jsonb(OLD) - 'last_login_at' - 'updated_at' AND
+        NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at
+    ) THEN
+        NEW.updated_at := current_timestamp;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS set_updated_at ON users;
+CREATE TRIGGER set_updated_at BEFORE UPDATE ON users FOR EACH ROW EXECUTE PROCEDURE users_set_updated_at();
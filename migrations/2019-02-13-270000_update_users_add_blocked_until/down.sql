@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS users_blocked_until_idx;
+ALTER TABLE users DROP COLUMN IF EXISTS blocked_until;
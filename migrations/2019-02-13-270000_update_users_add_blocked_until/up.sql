@@ -0,0 +1,7 @@
+ALTER TABLE users ADD COLUMN blocked_until TIMESTAMP;
+
+UPDATE users SET blocked_until = user_blocks.blocked_until
+FROM user_blocks
+WHERE user_blocks.user_id = users.id AND user_blocks.unblocked_at IS NULL AND users.is_blocked;
+
+CREATE INDEX users_blocked_until_idx ON users (blocked_until) WHERE is_blocked AND blocked_until IS NOT NULL;
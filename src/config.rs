@@ -1,6 +1,7 @@
 //! Config module contains the top-level config for the app.
 use std::collections::HashMap;
 use std::env;
+use std::net::IpAddr;
 
 use stq_http;
 use stq_logging::GrayLogConfig;
@@ -21,6 +22,11 @@ pub struct Config {
     pub google: OAuth,
     pub facebook: OAuth,
     pub tokens: Tokens,
+    pub suspicious_login: SuspiciousLogin,
+    pub password_policy: PasswordPolicy,
+    pub account_deletion: AccountDeletion,
+    pub webhooks: Webhooks,
+    pub user_blocks: UserBlocks,
     pub graylog: Option<GrayLogConfig>,
     pub sentry: Option<SentryConfig>,
     pub testmode: Option<TestmodeConf>,
@@ -36,6 +42,8 @@ pub struct Server {
     pub thread_count: usize,
     pub cache_ttl_sec: u64,
     pub processing_timeout_ms: u32,
+    /// Addresses of proxies, whose `X-Forwarded-For` and `X-Real-IP` headers are trusted
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 /// Http client settings
@@ -50,8 +58,33 @@ pub struct Client {
 /// Json Web Token seettings
 #[derive(Debug, Deserialize, Clone)]
 pub struct JWT {
+    /// Algorithm of issued tokens, keys must be of the matching type
+    pub algorithm: JwtAlgorithm,
+    /// Private key in PEM or DER format to sign tokens issued to users and internal services
     pub secret_key_path: String,
+    /// Public key in PEM or DER format to verify tokens issued to users and internal services
+    pub public_key_path: String,
+    /// Trust user id in `Authorization` header, set by gateway. Tokens from
+    /// `Authorization: Bearer` header are verified regardless of this flag.
+    pub legacy_auth_header: bool,
     pub check_email: bool,
+    /// `iss` claim of issued user tokens
+    pub issuer: String,
+    /// `aud` claim of issued user tokens, omitted if not set
+    pub audience: Option<String>,
+    /// Embed roles of the user into its tokens, so that services don't have to ask for them
+    pub roles_claims: bool,
+}
+
+/// Signing algorithm of json web tokens
+#[derive(Debug, Deserialize, Clone, Copy, Eq, PartialEq)]
+pub enum JwtAlgorithm {
+    /// RSA PKCS#1 v1.5 with SHA-256, RSA keys are in PKCS#1 format
+    RS256,
+    /// ECDSA on P-256 with SHA-256, private key is in PKCS#8 format, public key is SubjectPublicKeyInfo
+    ES256,
+    /// Ed25519, private key is in PKCS#8 format, public key is SubjectPublicKeyInfo
+    EdDSA,
 }
 
 /// Oauth 2.0 basic settings
@@ -72,6 +105,78 @@ pub struct Tokens {
     pub jwt_expiration_s: u64,
     pub email_sending_timeout_s: u64,
     pub refresh_timeout_s: u64,
+    /// Lifetime of tokens issued to superusers for impersonating other users
+    pub impersonation_expiration_s: u64,
+    /// Lifetime of tokens issued to internal services by client credentials
+    pub service_expiration_s: u64,
+}
+
+/// Detection of sign-ins from devices and countries never seen for the user
+#[derive(Debug, Deserialize, Clone)]
+pub struct SuspiciousLogin {
+    pub enabled: bool,
+    pub detect_new_device: bool,
+    pub detect_new_country: bool,
+    /// Number of latest successful sign-ins, whose devices and countries are known
+    pub history_size: i64,
+    /// Sign-ins are not checked until the user has signed in successfully this many times
+    pub min_history_size: i64,
+    /// Issue JWT only after suspicious sign-in is confirmed by email
+    pub require_confirmation: bool,
+    pub confirmation_expiration_s: u64,
+    /// CSV file with `first_ip,last_ip,country` IPv4 ranges
+    pub geoip_path: Option<String>,
+}
+
+/// Rules for passwords, that users choose
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    /// Require a symbol, that is neither letter nor digit
+    pub require_special: bool,
+    /// Substrings, that passwords must not contain, case insensitive
+    pub banned_substrings: Vec<String>,
+    /// Passwords must not contain local part of the user email
+    pub ban_email_local_part: bool,
+    /// File with common passwords, one per line, that are not allowed
+    pub common_passwords_path: Option<String>,
+    /// Number of latest passwords, including the current one, that can't be chosen again, zero disables the check
+    pub history_size: usize,
+}
+
+/// Deletion of accounts requested by users themselves
+#[derive(Debug, Deserialize, Clone)]
+pub struct AccountDeletion {
+    /// Time, the user can cancel deletion within, before the account is anonymized
+    pub grace_period_s: u64,
+    /// Users, that signed in this recently, are not asked to re-enter password
+    pub recent_auth_s: u64,
+    /// Interval of the background job, that anonymizes accounts after grace period
+    pub job_interval_s: u64,
+    /// Number of accounts anonymized by the job at a time
+    pub job_batch_size: i64,
+}
+
+/// Delivery of security events to webhooks
+#[derive(Debug, Deserialize, Clone)]
+pub struct Webhooks {
+    /// Interval of the background job, that sends queued deliveries
+    pub job_interval_s: u64,
+    /// Number of deliveries sent by the job at a time
+    pub job_batch_size: i64,
+}
+
+/// Temporary blocks of users
+#[derive(Debug, Deserialize, Clone)]
+pub struct UserBlocks {
+    /// Interval of the background job, that lifts expired blocks
+    pub job_interval_s: u64,
+    /// Number of users unblocked by the job at a time
+    pub job_batch_size: i64,
 }
 
 /// Testmode settings
@@ -129,6 +234,37 @@ impl Config {
         let mut s = RawConfig::new();
 
         s.set_default("server.processing_timeout_ms", 1000 as i64).unwrap();
+        s.set_default("server.trusted_proxies", Vec::<String>::new()).unwrap();
+        s.set_default("jwt.algorithm", "RS256").unwrap();
+        s.set_default("jwt.legacy_auth_header", true).unwrap();
+        s.set_default("jwt.issuer", "users").unwrap();
+        s.set_default("jwt.roles_claims", false).unwrap();
+        s.set_default("tokens.impersonation_expiration_s", 900 as i64).unwrap();
+        s.set_default("tokens.service_expiration_s", 3600 as i64).unwrap();
+        s.set_default("suspicious_login.enabled", true).unwrap();
+        s.set_default("suspicious_login.detect_new_device", true).unwrap();
+        s.set_default("suspicious_login.detect_new_country", true).unwrap();
+        s.set_default("suspicious_login.history_size", 50 as i64).unwrap();
+        s.set_default("suspicious_login.min_history_size", 1 as i64).unwrap();
+        s.set_default("suspicious_login.require_confirmation", false).unwrap();
+        s.set_default("suspicious_login.confirmation_expiration_s", 3600 as i64).unwrap();
+        s.set_default("password_policy.min_length", 8 as i64).unwrap();
+        s.set_default("password_policy.max_length", 128 as i64).unwrap();
+        s.set_default("password_policy.require_lowercase", false).unwrap();
+        s.set_default("password_policy.require_uppercase", false).unwrap();
+        s.set_default("password_policy.require_digit", false).unwrap();
+        s.set_default("password_policy.require_special", false).unwrap();
+        s.set_default("password_policy.banned_substrings", Vec::<String>::new()).unwrap();
+        s.set_default("password_policy.ban_email_local_part", true).unwrap();
+        s.set_default("password_policy.history_size", 0 as i64).unwrap();
+        s.set_default("account_deletion.grace_period_s", 2592000 as i64).unwrap();
+        s.set_default("account_deletion.recent_auth_s", 300 as i64).unwrap();
+        s.set_default("account_deletion.job_interval_s", 3600 as i64).unwrap();
+        s.set_default("account_deletion.job_batch_size", 100 as i64).unwrap();
+        s.set_default("webhooks.job_interval_s", 5 as i64).unwrap();
+        s.set_default("webhooks.job_batch_size", 50 as i64).unwrap();
+        s.set_default("user_blocks.job_interval_s", 60 as i64).unwrap();
+        s.set_default("user_blocks.job_batch_size", 100 as i64).unwrap();
 
         s.merge(File::with_name("config/base"))?;
 
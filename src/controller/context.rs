@@ -6,6 +6,7 @@ use diesel::pg::Pg;
 use diesel::Connection;
 use futures_cpupool::CpuPool;
 use r2d2::{ManageConnection, Pool};
+use uuid::Uuid;
 
 use stq_http::client::{ClientHandle, TimeLimitedHttpClient};
 use stq_router::RouteParser;
@@ -13,10 +14,14 @@ use stq_types::UserId;
 
 use super::routes::*;
 use config::{ApiMode, Config};
+use models::{JWTPayload, ServicePrincipal};
 use repos::repo_factory::*;
+use services::geoip::GeoIp;
 use services::jwt::profile::{FacebookProfile, GoogleProfile};
+use services::jwt::signer::JwtSigner;
 use services::jwt::{JWTProviderService, JWTProviderServiceImpl};
 use services::mocks::jwt::JWTProviderServiceMock;
+use services::password_policy::PasswordPolicy;
 
 /// Static context for all app
 pub struct StaticContext<T, M, F>
@@ -31,7 +36,12 @@ where
     pub route_parser: Arc<RouteParser<Route>>,
     pub client_handle: ClientHandle,
     pub repo_factory: F,
-    pub jwt_private_key: Vec<u8>,
+    /// Signs and verifies tokens of users and internal services
+    pub jwt_signer: Arc<JwtSigner>,
+    /// GeoIP database to detect country of clients, if configured
+    pub geoip: Option<Arc<GeoIp>>,
+    /// Rules for passwords, that users choose
+    pub password_policy: Arc<PasswordPolicy>,
 }
 
 impl<
@@ -47,7 +57,9 @@ impl<
         client_handle: ClientHandle,
         config: Arc<Config>,
         repo_factory: F,
-        jwt_private_key: Vec<u8>,
+        jwt_signer: Arc<JwtSigner>,
+        geoip: Option<Arc<GeoIp>>,
+        password_policy: Arc<PasswordPolicy>,
     ) -> Self {
         let route_parser = Arc::new(create_route_parser());
         Self {
@@ -57,7 +69,9 @@ impl<
             client_handle,
             config,
             repo_factory,
-            jwt_private_key,
+            jwt_signer,
+            geoip,
+            password_policy,
         }
     }
 
@@ -107,7 +121,9 @@ impl<
             client_handle: self.client_handle.clone(),
             config: self.config.clone(),
             repo_factory: self.repo_factory.clone(),
-            jwt_private_key: self.jwt_private_key.clone(),
+            jwt_signer: self.jwt_signer.clone(),
+            geoip: self.geoip.clone(),
+            password_policy: self.password_policy.clone(),
         }
     }
 }
@@ -117,6 +133,18 @@ impl<
 pub struct DynamicContext {
     pub user_id: Option<UserId>,
     pub correlation_token: String,
+    /// Address of the client, that has made the request
+    pub client_ip: Option<String>,
+    /// User agent of the client, that has made the request
+    pub user_agent: Option<String>,
+    /// Superuser, that has made the request on behalf of the user
+    pub impersonator: Option<UserId>,
+    /// Internal service, that has made the request
+    pub service: Option<ServicePrincipal>,
+    /// Verified payload of user token, the request is authenticated with
+    pub token: Option<JWTPayload>,
+    /// Verified API token, the request is authenticated with
+    pub api_token: Option<Uuid>,
     pub http_client: TimeLimitedHttpClient<ClientHandle>,
     pub google_provider_service: Arc<JWTProviderService<GoogleProfile>>,
     pub facebook_provider_service: Arc<JWTProviderService<FacebookProfile>>,
@@ -127,6 +155,11 @@ impl DynamicContext {
     pub fn new(
         user_id: Option<UserId>,
         correlation_token: String,
+        client_ip: Option<String>,
+        user_agent: Option<String>,
+        impersonator: Option<UserId>,
+        service: Option<ServicePrincipal>,
+        token: Option<JWTPayload>,
         http_client: TimeLimitedHttpClient<ClientHandle>,
         google_provider_service: Arc<JWTProviderService<GoogleProfile>>,
         facebook_provider_service: Arc<JWTProviderService<FacebookProfile>>,
@@ -134,6 +167,12 @@ impl DynamicContext {
         Self {
             user_id,
             correlation_token,
+            client_ip,
+            user_agent,
+            impersonator,
+            service,
+            token,
+            api_token: None,
             http_client,
             google_provider_service,
             facebook_provider_service,
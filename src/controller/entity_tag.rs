@@ -0,0 +1,87 @@
+//! `EntityTagged` serves application and adds `ETag` header to the responses
+//! carrying single user, so that clients could use it in `If-Match` header
+//! for optimistic concurrency control. Tags are computed by handlers and
+//! passed to the wrapper through `EntityTagSink` of the request, response bodies are not touched.
+use std::sync::{Arc, Mutex};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use futures::Future;
+use hyper;
+use hyper::header::{ETag, EntityTag};
+use hyper::server::{Request, Response, Service};
+use r2d2::ManageConnection;
+
+use stq_http::controller::Application;
+
+use super::context::StaticContext;
+use super::ControllerImpl;
+use errors::Error;
+use repos::repo_factory::ReposFactory;
+
+/// Stores entity tag computed by handler for response to specific request
+#[derive(Clone, Default)]
+pub struct EntityTagSink {
+    tag: Arc<Mutex<Option<String>>>,
+}
+
+impl EntityTagSink {
+    pub fn put(&self, tag: String) {
+        if let Ok(mut slot) = self.tag.lock() {
+            *slot = Some(tag);
+        }
+    }
+
+    fn take(&self) -> Option<String> {
+        self.tag.lock().ok().and_then(|mut slot| slot.take())
+    }
+}
+
+pub struct EntityTagged<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    static_context: StaticContext<T, M, F>,
+}
+
+impl<T, M, F> EntityTagged<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    pub fn new(static_context: StaticContext<T, M, F>) -> Self {
+        Self { static_context }
+    }
+}
+
+impl<T, M, F> Service for EntityTagged<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        // Every request gets its own sink, so tags of concurrent requests are never mixed up
+        let entity_tag = EntityTagSink::default();
+        let controller = ControllerImpl::new(self.static_context.clone()).with_entity_tag_sink(entity_tag.clone());
+        let app = Application::<Error>::new(controller);
+
+        Box::new(app.call(req).map(move |mut res| {
+            if let Some(tag) = entity_tag.take() {
+                if res.status().is_success() {
+                    res.headers_mut().set(ETag(EntityTag::strong(tag)));
+                }
+            }
+            res
+        }))
+    }
+}
@@ -0,0 +1,147 @@
+//! `UsersExporter` wraps application and serves users export. Unlike
+//! regular routes, that collect the whole response body before sending it,
+//! export is streamed to the client chunk by chunk as users are read from db.
+use std::io;
+use std::sync::Arc;
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::{Future, Sink, Stream};
+use hyper;
+use hyper::header::ContentType;
+use hyper::server::{Request, Response, Service};
+use hyper::{Body, Chunk, Method, StatusCode};
+use r2d2::ManageConnection;
+use serde_json;
+use tokio_core::reactor::Handle;
+
+use stq_http::errors::ErrorMessageWrapper;
+use stq_http::request_util::parse_body;
+
+use super::authenticate;
+use super::context::StaticContext;
+use super::routes::Route;
+use errors::Error;
+use models::{UsersExport, UsersExportColumns, UsersFileFormat, UsersSearchTerms};
+use repos::repo_factory::ReposFactory;
+use sentry_integration::log_and_capture_error;
+use services::users_export::UsersExportService;
+
+pub struct UsersExporter<S, T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    inner: S,
+    static_context: StaticContext<T, M, F>,
+    handle: Arc<Handle>,
+}
+
+impl<S, T, M, F> UsersExporter<S, T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    pub fn new(inner: S, static_context: StaticContext<T, M, F>, handle: Arc<Handle>) -> Self {
+        Self {
+            inner,
+            static_context,
+            handle,
+        }
+    }
+
+    /// POST /users/export?format=csv&columns=id,email
+    fn export(&self, req: Request) -> Box<Future<Item = Response, Error = hyper::Error>> {
+        let authenticated = authenticate(&self.static_context, &req);
+        let handle = self.handle.clone();
+
+        let (format, columns) = parse_query!(
+            req.query().unwrap_or_default(),
+            "format" => UsersFileFormat, "columns" => UsersExportColumns
+        );
+        let export = UsersExport {
+            format: format.unwrap_or_default(),
+            columns: columns.unwrap_or_default(),
+        };
+        let format = export.format;
+
+        let body = req.body();
+
+        // the whole export is made by authenticated service, as any other route
+        let fut = authenticated
+            .and_then(move |service| {
+                parse_body::<UsersSearchTerms>(body)
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: UsersSearchTerms")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |term| service.export(term, export))
+            })
+            .then(move |res| {
+                let response = match res {
+                    Ok(chunks) => {
+                        let (sender, body) = Body::pair();
+                        let chunks = chunks.then(|chunk| {
+                            Ok(chunk.map(Chunk::from).map_err(|e| {
+                                error!("Users export failed: {}", e);
+                                hyper::Error::Io(io::Error::new(io::ErrorKind::Other, e.to_string()))
+                            }))
+                        });
+                        handle.spawn(sender.send_all(chunks).then(|_| Ok(())));
+
+                        let mut response = Response::new().with_body(body);
+                        response.headers_mut().set_raw("Content-Type", format.content_type());
+                        response.headers_mut().set_raw(
+                            "Content-Disposition",
+                            format!("attachment; filename=\"users.{}\"", format.extension()),
+                        );
+                        response
+                    }
+                    Err(e) => error_response(&e),
+                };
+                Ok(response)
+            });
+
+        Box::new(fut)
+    }
+}
+
+impl<S, T, M, F> Service for UsersExporter<S, T, M, F>
+where
+    S: Service<Request = Request, Response = Response, Error = hyper::Error>,
+    S::Future: 'static,
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        match (req.method(), self.static_context.route_parser.test(req.path())) {
+            (&Method::Post, Some(Route::UsersExport)) => self.export(req),
+            _ => Box::new(self.inner.call(req)),
+        }
+    }
+}
+
+/// Renders error the same way application does for regular routes
+fn error_response(err: &FailureError) -> Response {
+    let wrapper = ErrorMessageWrapper::<Error>::from(err);
+    if wrapper.inner.code == 500 {
+        log_and_capture_error(err);
+    }
+
+    Response::new()
+        .with_status(StatusCode::try_from(wrapper.inner.code).unwrap_or(StatusCode::InternalServerError))
+        .with_header(ContentType::json())
+        .with_body(serde_json::to_string(&wrapper.inner).unwrap_or_default())
+}
@@ -4,40 +4,71 @@
 //! of `Service` layer to http responses
 
 pub mod context;
+pub mod entity_tag;
+pub mod export;
 pub mod routes;
 pub mod utils;
 
+use std::net::IpAddr;
 use std::str::FromStr;
 use std::time::Duration;
 
-use chrono::Utc;
+use chrono::{NaiveDate, Utc};
 use diesel::{connection::AnsiTransactionManager, pg::Pg, Connection};
 use failure::Fail;
 use futures::{future, Future, IntoFuture};
-use hyper::{header::Authorization, server::Request, Delete, Get, Post, Put};
+use hyper::{
+    header::{Authorization, IfMatch, UserAgent},
+    server::Request,
+    Delete, Get, Method, Post, Put,
+};
 use r2d2::ManageConnection;
+use serde_json;
 use validator::Validate;
 
 use stq_http::{
     client::TimeLimitedHttpClient,
     controller::{Controller, ControllerFuture},
     errors::ErrorMessageWrapper,
-    request_util::{self, parse_body, serialize_future, RequestTimeout as RequestTimeoutHeader},
+    request_util::{self, parse_body, read_body, serialize_future, RequestTimeout as RequestTimeoutHeader},
 };
-use stq_static_resources::TokenType;
+
 use stq_types::UserId;
 
 use self::context::{DynamicContext, DynamicContextServices, StaticContext};
+use self::entity_tag::EntityTagSink;
 use self::routes::Route;
 use errors::Error;
 use models;
 use repos::repo_factory::*;
 use sentry_integration::log_and_capture_error;
+use services::account_deletion::AccountDeletionService;
+use services::api_tokens::ApiTokensService;
+use services::impersonation::ImpersonationService;
 use services::jwt::JWTService;
+use services::login_events::LoginEventsService;
+use services::referrals::ReferralsService;
+use services::service_clients::ServiceClientsService;
+use services::types::ServiceFuture;
+use services::user_notes::UserNotesService;
 use services::user_roles::UserRolesService;
 use services::users::UsersService;
+use services::users_import::UsersImportService;
+use services::webhooks::WebhooksService;
 use services::Service;
 
+const FUZZY_SEARCH_DEFAULT_LIMIT: i64 = 10;
+const FUZZY_SEARCH_MAX_LIMIT: i64 = 50;
+const REFERRALS_DEFAULT_COUNT: i64 = 20;
+const REFERRALS_MAX_COUNT: i64 = 100;
+const REFERRALS_STATS_DEFAULT_DAYS: u64 = 30;
+const REFERRALS_DEFAULT_MAX_DEPTH: i32 = 3;
+const REFERRALS_MAX_DEPTH: i32 = 10;
+const LOGIN_EVENTS_DEFAULT_COUNT: i64 = 20;
+const LOGIN_EVENTS_MAX_COUNT: i64 = 100;
+const USERS_SEARCH_PAGE_DEFAULT_COUNT: i64 = 20;
+const USERS_SEARCH_PAGE_MAX_COUNT: i64 = 100;
+
 /// Controller handles route parsing and calling `Service` layer
 pub struct ControllerImpl<T, M, F>
 where
@@ -46,6 +77,7 @@ where
     F: ReposFactory<T>,
 {
     pub static_context: StaticContext<T, M, F>,
+    entity_tag: EntityTagSink,
 }
 
 impl<
@@ -56,7 +88,15 @@ impl<
 {
     /// Create a new controller based on services
     pub fn new(static_context: StaticContext<T, M, F>) -> Self {
-        Self { static_context }
+        Self {
+            static_context,
+            entity_tag: EntityTagSink::default(),
+        }
+    }
+
+    /// Sets sink for entity tag of the response, that is read by `EntityTagged`
+    pub fn with_entity_tag_sink(self, entity_tag: EntityTagSink) -> Self {
+        Self { entity_tag, ..self }
     }
 
     fn get_jwt_token_expiration(&self) -> i64 {
@@ -64,56 +104,84 @@ impl<
 
         Utc::now().timestamp() + jwt_expiration_s as i64
     }
-}
 
-impl<
-        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
-        M: ManageConnection<Connection = T>,
-        F: ReposFactory<T>,
-    > Controller for ControllerImpl<T, M, F>
-{
-    /// Handle a request and get future response
-    fn call(&self, req: Request) -> ControllerFuture {
-        let user_id = get_user_id(&req);
-        let correlation_token = request_util::get_correlation_token(&req);
-
-        let request_timeout = req
-            .headers()
-            .get::<RequestTimeoutHeader>()
-            .and_then(|h| h.0.parse::<u64>().ok())
-            .unwrap_or(self.static_context.config.client.http_timeout_ms)
-            .checked_sub(self.static_context.config.server.processing_timeout_ms as u64)
-            .map(Duration::from_millis)
-            .unwrap_or(Duration::new(0, 0));
-
-        let time_limited_http_client = TimeLimitedHttpClient::new(self.static_context.client_handle.clone(), request_timeout);
-
-        let DynamicContextServices {
-            google_provider_service,
-            facebook_provider_service,
-        } = self.static_context.dynamic_context_services(time_limited_http_client.clone());
-
-        let dynamic_context = DynamicContext::new(
-            user_id,
-            correlation_token,
-            time_limited_http_client,
-            google_provider_service,
-            facebook_provider_service,
-        );
-
-        let service = Service::new(self.static_context.clone(), dynamic_context);
+    fn get_impersonation_token_expiration(&self) -> i64 {
+        let impersonation_expiration_s = self.static_context.config.tokens.impersonation_expiration_s;
+
+        Utc::now().timestamp() + impersonation_expiration_s as i64
+    }
 
+    /// Routes authenticated request to `Service` layer
+    fn handle(&self, service: Service<T, M, F>, req: Request) -> ControllerFuture {
         let token_expiration = self.get_jwt_token_expiration();
 
         let path = req.path().to_string();
 
-        let fut = match (&req.method().clone(), self.static_context.route_parser.test(req.path())) {
+        let if_match = get_if_match(&req);
+        let entity_tag = self.entity_tag.clone();
+
+        match (&req.method().clone(), self.static_context.route_parser.test(req.path())) {
             // GET /users/<user_id>
-            (&Get, Some(Route::User(user_id))) => serialize_future(service.get(user_id)),
+            (&Get, Some(Route::User(user_id))) => serialize_future(with_entity_tag(service.get(user_id), entity_tag)),
 
             // GET /users/current
             (&Get, Some(Route::Current)) => serialize_future(service.current()),
 
+            // GET /users/current/login_events
+            (&Get, Some(Route::CurrentLoginEvents)) => {
+                let count = parse_query!(req.query().unwrap_or_default(), "count" => i64);
+                serialize_future(service.list_login_events(count.unwrap_or(LOGIN_EVENTS_DEFAULT_COUNT).max(1).min(LOGIN_EVENTS_MAX_COUNT)))
+            }
+
+            // GET /users/current/api_tokens
+            (&Get, Some(Route::CurrentApiTokens)) => serialize_future(service.list_api_tokens()),
+
+            // POST /users/current/api_tokens
+            (&Post, Some(Route::CurrentApiTokens)) => serialize_future(
+                parse_body::<models::NewApiTokenPayload>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewApiTokenPayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewApiTokenPayload")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.create_api_token(payload))
+                    }),
+            ),
+
+            // DELETE /users/current/api_tokens/<id>
+            (&Delete, Some(Route::CurrentApiToken { id })) => serialize_future(service.revoke_api_token(id)),
+
+            // POST /users/current/deletion
+            (&Post, Some(Route::CurrentDeletion)) => serialize_future(
+                parse_body::<models::UserDeletionRequest>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: UserDeletionRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.request_account_deletion(payload)),
+            ),
+
+            // POST /users/deletion/cancel
+            (&Post, Some(Route::UserDeletionCancel)) => serialize_future(
+                parse_body::<models::UserDeletionCancel>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: UserDeletionCancel")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |cancel| service.cancel_account_deletion(cancel.token)),
+            ),
+
             // GET /users/by_email
             (&Get, Some(Route::UserByEmail)) => {
                 if let Some(email) = parse_query!(req.query().unwrap_or_default(), "email" => String) {
@@ -139,6 +207,37 @@ impl<
                 }
             }
 
+            // GET /users/search/fuzzy
+            (&Get, Some(Route::UsersSearchFuzzy)) => {
+                let (query, limit) = parse_query!(req.query().unwrap_or_default(), "q" => String, "limit" => i64);
+                match query {
+                    Some(ref query) if !query.trim().is_empty() => serialize_future(service.fuzzy_search(
+                        query.trim().to_string(),
+                        limit.unwrap_or(FUZZY_SEARCH_DEFAULT_LIMIT).max(1).min(FUZZY_SEARCH_MAX_LIMIT),
+                    )),
+                    _ => Box::new(future::err(
+                        format_err!("Parsing query parameters failed, action: fuzzy search users")
+                            .context(Error::Parse)
+                            .into(),
+                    )),
+                }
+            }
+
+            // GET /users/acquisition?group_by=utm_source&from=2019-01-01&to=2019-01-31
+            (&Get, Some(Route::UsersAcquisition)) => {
+                let (group_by, from, to) = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "group_by" => models::AcquisitionGroupBy, "from" => NaiveDate, "to" => NaiveDate
+                );
+                let period = models::DateRange { from, to };
+                match (period.from, period.to) {
+                    (Some(from), Some(to)) if from > to => Box::new(future::err(
+                        Error::Validate(validation_errors!({"period": ["period" => "Period must start before it ends"]})).into(),
+                    )),
+                    _ => serialize_future(service.acquisition_report(group_by.unwrap_or_default(), period)),
+                }
+            }
+
             // GET /users
             (&Get, Some(Route::Users)) => {
                 if let (Some(offset), Some(count)) = parse_query!(req.query().unwrap_or_default(), "offset" => UserId, "count" => i64) {
@@ -191,6 +290,48 @@ impl<
                     }),
             ),
 
+            // POST /users/batch
+            (&Post, Some(Route::UsersBatch)) => serialize_future(
+                parse_body::<models::UsersBatchRequest>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: UsersBatchRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |batch| {
+                        batch
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: UsersBatchRequest")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.get_batch(batch.ids))
+                    }),
+            ),
+
+            // POST /users/import
+            (&Post, Some(Route::UsersImport)) => {
+                let (format, dry_run, batch_size, email_verification) = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "format" => models::UsersFileFormat, "dry_run" => bool, "batch_size" => usize, "email_verification" => bool
+                );
+                let defaults = models::UsersImport::default();
+                let import = models::UsersImport {
+                    format: format.unwrap_or(defaults.format),
+                    dry_run: dry_run.unwrap_or(defaults.dry_run),
+                    batch_size: batch_size.unwrap_or(defaults.batch_size),
+                    email_verification: email_verification.unwrap_or(defaults.email_verification),
+                };
+
+                serialize_future(
+                    read_body(req.body())
+                        .map_err(|e| e.context("Reading body failed, target: users import").context(Error::Parse).into())
+                        .and_then(move |body| service.import(body, import)),
+                )
+            }
+
             // PUT /users/<user_id>
             (&Put, Some(Route::User(user_id))) => serialize_future(
                 parse_body::<models::user::UpdateUser>(req.body())
@@ -207,15 +348,100 @@ impl<
                             .inspect(|_| {
                                 debug!("Validation success");
                             })
-                            .and_then(move |_| service.update(user_id, update_user))
+                            .and_then(move |_| with_entity_tag(service.update(user_id, update_user, if_match), entity_tag))
                     }),
             ),
 
             // POST /users/<user_id>/block
-            (&Post, Some(Route::UserBlock(user_id))) => serialize_future(service.set_block_status(user_id, true)),
+            (&Post, Some(Route::UserBlock(user_id))) => serialize_future(
+                read_body(req.body())
+                    .map_err(|e| e.context("Reading body failed, target: BlockUser").context(Error::Parse).into())
+                    .and_then(|body| {
+                        // empty body blocks the user permanently without reason
+                        if body.trim().is_empty() {
+                            Ok(models::BlockUser::default())
+                        } else {
+                            serde_json::from_str::<models::BlockUser>(&body)
+                                .map_err(|e| e.context("Parsing body failed, target: BlockUser").context(Error::Parse).into())
+                        }
+                    })
+                    .and_then(|payload| {
+                        payload.validate().map(|_| payload).map_err(|e| {
+                            format_err!("Validation failed, target: BlockUser")
+                                .context(Error::Validate(e))
+                                .into()
+                        })
+                    })
+                    .and_then(move |payload| with_entity_tag(service.block(user_id, payload, if_match), entity_tag)),
+            ),
 
             // POST /users/<user_id>/unblock
-            (&Post, Some(Route::UserUnblock(user_id))) => serialize_future(service.set_block_status(user_id, false)),
+            (&Post, Some(Route::UserUnblock(user_id))) => serialize_future(with_entity_tag(service.unblock(user_id, if_match), entity_tag)),
+
+            // POST /users/<user_id>/reactivate
+            (&Post, Some(Route::UserReactivate(user_id))) => serialize_future(service.reactivate(user_id)),
+
+            // GET /users/<user_id>/blocks
+            (&Get, Some(Route::UserBlocks(user_id))) => serialize_future(service.list_blocks(user_id)),
+
+            // GET /users/<user_id>/notes
+            (&Get, Some(Route::UserNotes(user_id))) => serialize_future(service.list_user_notes(user_id)),
+
+            // POST /users/<user_id>/notes
+            (&Post, Some(Route::UserNotes(user_id))) => serialize_future(
+                parse_body::<models::NewUserNotePayload>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewUserNotePayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewUserNotePayload")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.create_user_note(user_id, payload))
+                    }),
+            ),
+
+            // DELETE /users/<user_id>/notes/<id>
+            (&Delete, Some(Route::UserNote { user_id, id })) => serialize_future(service.delete_user_note(user_id, id)),
+
+            // GET /users/<user_id>/referrals
+            (&Get, Some(Route::UserReferrals(user_id))) => {
+                let (offset, count) = parse_query!(req.query().unwrap_or_default(), "offset" => i64, "count" => i64);
+                serialize_future(service.list_referrals(
+                    user_id,
+                    offset.unwrap_or(0).max(0),
+                    count.unwrap_or(REFERRALS_DEFAULT_COUNT).max(1).min(REFERRALS_MAX_COUNT),
+                ))
+            }
+
+            // GET /users/<user_id>/referrals/stats
+            (&Get, Some(Route::UserReferralsStats(user_id))) => {
+                let (days, max_depth) = parse_query!(req.query().unwrap_or_default(), "days" => u64, "max_depth" => i32);
+                serialize_future(service.user_referral_stats(
+                    user_id,
+                    days.unwrap_or(REFERRALS_STATS_DEFAULT_DAYS),
+                    max_depth.unwrap_or(REFERRALS_DEFAULT_MAX_DEPTH).max(1).min(REFERRALS_MAX_DEPTH),
+                ))
+            }
+
+            // GET /referrals/stats
+            (&Get, Some(Route::ReferralsStats)) => {
+                let days = parse_query!(req.query().unwrap_or_default(), "days" => u64);
+                serialize_future(service.referral_stats(days.unwrap_or(REFERRALS_STATS_DEFAULT_DAYS)))
+            }
+
+            // GET /referrals/top
+            (&Get, Some(Route::ReferralsTop)) => {
+                let count = parse_query!(req.query().unwrap_or_default(), "count" => i64);
+                serialize_future(service.top_referrers(count.unwrap_or(REFERRALS_DEFAULT_COUNT).max(1).min(REFERRALS_MAX_COUNT)))
+            }
 
             // DELETE /users/<user_id>
             (&Delete, Some(Route::User(user_id))) => serialize_future(service.deactivate(user_id)),
@@ -252,6 +478,36 @@ impl<
                     }),
             ),
 
+            // GET /users/<user_id>/login_confirmation_token
+            (&Get, Some(Route::GetUserLoginConfirmationToken { user_id })) => {
+                serialize_future(service.get_login_confirmation_token(user_id))
+            }
+
+            // POST /jwt/email/confirm
+            (&Post, Some(Route::JWTEmailConfirm)) => serialize_future(
+                parse_body::<models::LoginConfirmationApply>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: LoginConfirmationApply")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |apply| service.create_token_login_confirmation(apply.token, token_expiration)),
+            ),
+
+            // POST /jwt/impersonate
+            (&Post, Some(Route::JWTImpersonate)) => {
+                let impersonation_expiration = self.get_impersonation_token_expiration();
+                serialize_future(
+                    parse_body::<models::ImpersonateUser>(req.body())
+                        .map_err(|e| {
+                            e.context("Parsing body failed, target: ImpersonateUser")
+                                .context(Error::Parse)
+                                .into()
+                        })
+                        .and_then(move |payload| service.impersonate(payload.user_id, impersonation_expiration)),
+                )
+            }
+
             // POST /jwt/google
             (&Post, Some(Route::JWTGoogle)) => serialize_future(
                 parse_body::<models::jwt::ProviderOauth>(req.body())
@@ -293,14 +549,16 @@ impl<
             ),
 
             (Get, Some(Route::RolesByUserId { user_id })) => serialize_future({ service.get_roles(user_id) }),
-            (Post, Some(Route::Roles)) => {
-                serialize_future({ parse_body::<models::NewUserRole>(req.body()).and_then(move |data| service.create_user_role(data)) })
-            }
-            (Delete, Some(Route::Roles)) => {
-                serialize_future({ parse_body::<models::RemoveUserRole>(req.body()).and_then(move |data| service.delete_user_role(data)) })
+            (Post, Some(Route::Roles)) => serialize_future({
+                parse_body::<models::NewUserRole>(req.body()).and_then(move |data| service.create_user_role(data, if_match))
+            }),
+            (Delete, Some(Route::Roles)) => serialize_future({
+                parse_body::<models::RemoveUserRole>(req.body()).and_then(move |data| service.delete_user_role(data, if_match))
+            }),
+            (Delete, Some(Route::RolesByUserId { user_id })) => {
+                serialize_future({ service.delete_user_role_by_user_id(user_id, if_match) })
             }
-            (Delete, Some(Route::RolesByUserId { user_id })) => serialize_future({ service.delete_user_role_by_user_id(user_id) }),
-            (Delete, Some(Route::RoleById { id })) => serialize_future({ service.delete_user_role_by_id(id) }),
+            (Delete, Some(Route::RoleById { id })) => serialize_future({ service.delete_user_role_by_id(id, if_match) }),
 
             // GET /users/count
             (&Get, Some(Route::UserCount)) => {
@@ -335,7 +593,7 @@ impl<
 
             // POST /users/<user_id>/password_reset_token
             (&Get, Some(Route::GetUserPasswordResetToken { user_id })) => {
-                serialize_future(service.get_existing_reset_token(user_id, TokenType::PasswordReset))
+                serialize_future(service.get_existing_reset_token(user_id, models::ResetTokenType::PasswordReset))
             }
 
             // Post /users/password_reset_token
@@ -376,9 +634,37 @@ impl<
                     }),
             ),
 
+            // POST /users/reactivation_token
+            (&Post, Some(Route::UserReactivationToken)) => serialize_future(
+                parse_body::<models::ResetRequest>(req.body())
+                    .map_err(|e| e.context("Parsing body failed, target: ResetRequest").context(Error::Parse).into())
+                    .and_then(move |reset_req| {
+                        reset_req
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: ResetRequest")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.get_reactivation_token(reset_req.email.to_lowercase(), reset_req.uuid))
+                    }),
+            ),
+
+            // PUT /users/reactivation_token
+            (&Put, Some(Route::UserReactivationToken)) => serialize_future(
+                parse_body::<models::ReactivationApply>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: ReactivationApply")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |reactivation_apply| service.reactivation_apply(reactivation_apply.token)),
+            ),
+
             // POST /users/<user_id>/email_verify_token
             (&Get, Some(Route::GetUserEmalVerifyToken { user_id })) => {
-                serialize_future(service.get_existing_reset_token(user_id, TokenType::EmailVerify))
+                serialize_future(service.get_existing_reset_token(user_id, models::ResetTokenType::EmailVerify))
             }
 
             // Post /users/email_verify_token
@@ -432,6 +718,117 @@ impl<
                 )
             }
 
+            // GET /service_clients
+            (&Get, Some(Route::ServiceClients)) => serialize_future(service.list_service_clients()),
+
+            // POST /service_clients
+            (&Post, Some(Route::ServiceClients)) => serialize_future(
+                parse_body::<models::NewServiceClientPayload>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: NewServiceClientPayload")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| {
+                        payload
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewServiceClientPayload")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.create_service_client(payload))
+                    }),
+            ),
+
+            // DELETE /service_clients/<client_id>
+            (&Delete, Some(Route::ServiceClient { client_id })) => serialize_future(service.deactivate_service_client(client_id)),
+
+            // POST /oauth/token
+            (&Post, Some(Route::OAuthToken)) => serialize_future(
+                parse_body::<models::ClientCredentialsRequest>(req.body())
+                    .map_err(|e| {
+                        e.context("Parsing body failed, target: ClientCredentialsRequest")
+                            .context(Error::Parse)
+                            .into()
+                    })
+                    .and_then(move |payload| service.create_service_token(payload)),
+            ),
+
+            // GET /webhooks
+            (&Get, Some(Route::Webhooks)) => serialize_future(service.list_webhooks()),
+
+            // POST /webhooks
+            (&Post, Some(Route::Webhooks)) => serialize_future(
+                parse_body::<models::NewWebhook>(req.body())
+                    .map_err(|e| e.context("Parsing body failed, target: NewWebhook").context(Error::Parse).into())
+                    .and_then(move |new_webhook| {
+                        new_webhook
+                            .validate()
+                            .map_err(|e| {
+                                format_err!("Validation failed, target: NewWebhook")
+                                    .context(Error::Validate(e))
+                                    .into()
+                            })
+                            .into_future()
+                            .and_then(move |_| service.create_webhook(new_webhook))
+                    }),
+            ),
+
+            // DELETE /webhooks/<id>
+            (&Delete, Some(Route::Webhook { id })) => serialize_future(service.delete_webhook(id)),
+
+            // GET /webhooks/deliveries
+            (&Get, Some(Route::WebhookDeliveries)) => {
+                let (failed_only, count) = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "failed_only" => bool, "count" => i64
+                );
+
+                serialize_future(service.list_webhook_deliveries(failed_only.unwrap_or(false), count.unwrap_or(20)))
+            }
+
+            // POST /webhooks/deliveries/<id>/replay
+            (&Post, Some(Route::WebhookDeliveryReplay { id })) => serialize_future(service.replay_webhook_delivery(id)),
+
+            // POST /users/search/page
+            (&Post, Some(Route::UsersSearchPage)) => {
+                let (count, cursor, sort, direction, with_total_count) = parse_query!(
+                    req.query().unwrap_or_default(),
+                    "count" => i64, "cursor" => String, "sort" => models::UsersSortField,
+                    "direction" => models::SortDirection, "with_total_count" => bool
+                );
+
+                let body = req.body();
+
+                serialize_future(
+                    cursor
+                        .map(|cursor| models::UsersCursor::decode(&cursor).map(Some))
+                        .unwrap_or(Ok(None))
+                        .into_future()
+                        .map(move |cursor| models::UsersSearchPage {
+                            count: count
+                                .unwrap_or(USERS_SEARCH_PAGE_DEFAULT_COUNT)
+                                .max(1)
+                                .min(USERS_SEARCH_PAGE_MAX_COUNT),
+                            sort: sort.unwrap_or_default(),
+                            direction: direction.unwrap_or_default(),
+                            cursor,
+                            with_total_count: with_total_count.unwrap_or(true),
+                        })
+                        .and_then(move |page| {
+                            parse_body::<models::UsersSearchTerms>(body)
+                                .map_err(|e| {
+                                    e.context("Parsing body failed, target: UsersSearchTerms")
+                                        .context(Error::Parse)
+                                        .into()
+                                })
+                                .and_then(move |payload| service.search_page(payload, page))
+                        }),
+                )
+            }
+
             // Fallback
             (m, _) => Box::new(future::err(
                 format_err!("Request to non existing endpoint in users microservice! {:?} {:?}", m, path)
@@ -439,18 +836,260 @@ impl<
                     .into(),
             )),
         }
-        .map_err(|err| {
-            let wrapper = ErrorMessageWrapper::<Error>::from(&err);
-            if wrapper.inner.code == 500 {
-                log_and_capture_error(&err);
-            }
-            err
-        });
+    }
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > Controller for ControllerImpl<T, M, F>
+{
+    /// Handle a request and get future response
+    fn call(&self, req: Request) -> ControllerFuture {
+        let controller = ControllerImpl::new(self.static_context.clone()).with_entity_tag_sink(self.entity_tag.clone());
+
+        let fut = authenticate(&self.static_context, &req)
+            .and_then(move |service| controller.handle(service, req))
+            .map_err(|err| {
+                let wrapper = ErrorMessageWrapper::<Error>::from(&err);
+                if wrapper.inner.code == 500 {
+                    log_and_capture_error(&err);
+                }
+                err
+            });
 
         Box::new(fut)
     }
 }
 
+/// Creates service for the request and authenticates it, every route must be served by authenticated service.
+/// User token is checked against revoked tokens and blocks, API token is looked up by its hash
+/// and restricts the request to its user and scopes, service token is valid only while its service client is active.
+pub fn authenticate<T, M, F>(static_context: &StaticContext<T, M, F>, req: &Request) -> ServiceFuture<Service<T, M, F>>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let service = create_service(static_context, req);
+
+    let api_token = get_bearer_token(req).filter(|token| token.starts_with(models::API_TOKEN_PREFIX));
+    let principal = service.dynamic_context.service.clone();
+    let authenticated: ServiceFuture<Service<T, M, F>> = match (service.dynamic_context.token.clone(), api_token, principal) {
+        (Some(payload), _, _) => Box::new(service.verify_token(payload).map(move |_| service)),
+        (None, Some(api_token), _) => {
+            let scope = static_context
+                .route_parser
+                .test(req.path())
+                .and_then(|route| api_token_scope(req.method(), &route));
+            let method = req.method().to_string();
+            let path = req.path().to_string();
+            Box::new(service.authenticate_api_token(api_token).and_then(move |api_token| {
+                // routes, that are not covered by scopes, are never allowed with API token
+                match scope {
+                    Some((resource, action)) if api_token.scopes().iter().any(|scope| scope.allows(resource, action)) => {
+                        Ok(with_api_token(service, api_token))
+                    }
+                    _ => Err(Error::Forbidden
+                        .context(format!("API token {} is not allowed to {} {}", api_token.id, method, path))
+                        .into()),
+                }
+            }))
+        }
+        (None, None, Some(principal)) => Box::new(service.verify_service_client(principal).map(move |_| service)),
+        (None, None, None) => Box::new(future::ok(service)),
+    };
+
+    // Every request made with impersonation token is recorded in audit log, the request fails if it can not be recorded
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    Box::new(authenticated.and_then(move |service| -> ServiceFuture<Service<T, M, F>> {
+        if service.dynamic_context.impersonator.is_some() {
+            Box::new(service.record_impersonated_request(method, path).map(move |_| service))
+        } else {
+            Box::new(future::ok(service))
+        }
+    }))
+}
+
+/// Creates service with dynamic context of specific request
+pub fn create_service<T, M, F>(static_context: &StaticContext<T, M, F>, req: &Request) -> Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let bearer_token = get_bearer_token(req);
+    let service = bearer_token
+        .as_ref()
+        .and_then(|token| static_context.jwt_signer.verify::<models::ServiceTokenPayload>(token))
+        .filter(|payload| payload.is_service_token())
+        .map(models::ServicePrincipal::from);
+    let token = bearer_token
+        .as_ref()
+        .and_then(|token| static_context.jwt_signer.verify::<models::JWTPayload>(token))
+        .filter(|payload| payload.is_user_token())
+        .filter(|payload| {
+            let jwt_config = &static_context.config.jwt;
+            payload.is_issued_by(&jwt_config.issuer, jwt_config.audience.as_ref().map(|aud| aud.as_str()))
+        });
+    let is_api_token = bearer_token
+        .as_ref()
+        .map(|token| token.starts_with(models::API_TOKEN_PREFIX))
+        .unwrap_or(false);
+    if bearer_token.is_some() && !is_api_token && service.is_none() && token.is_none() {
+        warn!("Bearer token is invalid or expired");
+    }
+
+    // Legacy user id header is trusted only if gateway is configured to strip it from client requests.
+    // Impersonation is known only from the claim of verified token, whatever the auth mode is.
+    let (user_id, impersonator) = match token {
+        Some(ref token) => (Some(token.user_id), token.impersonator),
+        None if static_context.config.jwt.legacy_auth_header => (get_user_id(req), None),
+        None => (None, None),
+    };
+    let correlation_token = request_util::get_correlation_token(req);
+    let client_ip = get_client_ip(req, &static_context.config.server.trusted_proxies);
+    let user_agent = req.headers().get::<UserAgent>().map(|user_agent| user_agent.to_string());
+
+    let request_timeout = req
+        .headers()
+        .get::<RequestTimeoutHeader>()
+        .and_then(|h| h.0.parse::<u64>().ok())
+        .unwrap_or(static_context.config.client.http_timeout_ms)
+        .checked_sub(static_context.config.server.processing_timeout_ms as u64)
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::new(0, 0));
+
+    let time_limited_http_client = TimeLimitedHttpClient::new(static_context.client_handle.clone(), request_timeout);
+
+    let DynamicContextServices {
+        google_provider_service,
+        facebook_provider_service,
+    } = static_context.dynamic_context_services(time_limited_http_client.clone());
+
+    let dynamic_context = DynamicContext::new(
+        user_id,
+        correlation_token,
+        client_ip,
+        user_agent,
+        impersonator,
+        service.clone(),
+        token,
+        time_limited_http_client,
+        google_provider_service,
+        facebook_provider_service,
+    );
+
+    // Internal services are authorized by permissions of their roles
+    let mut static_context = static_context.clone();
+    static_context.repo_factory = static_context.repo_factory.with_service_role(service.map(|service| service.role));
+
+    Service::new(static_context, dynamic_context)
+}
+
+/// Restricts service to the user and scopes of API token, the request is authenticated with
+fn with_api_token<T, M, F>(mut service: Service<T, M, F>, api_token: models::ApiToken) -> Service<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    service.dynamic_context.user_id = Some(api_token.user_id);
+    service.dynamic_context.impersonator = None;
+    service.dynamic_context.api_token = Some(api_token.id);
+    service.static_context.repo_factory = service.static_context.repo_factory.with_api_token_scopes(Some(api_token.scopes()));
+    service
+}
+
+/// Returns resource and action, that API token must be scoped to for the route.
+/// Routes, that manage credentials, roles or accounts, and routes of internal services are never allowed with API token.
+fn api_token_scope(method: &Method, route: &Route) -> Option<(models::Resource, models::Action)> {
+    use models::{Action, Resource};
+
+    match (method, route) {
+        (&Get, &Route::User(_))
+        | (&Get, &Route::Current)
+        | (&Get, &Route::Users)
+        | (&Get, &Route::UserByEmail)
+        | (&Get, &Route::UsersSearchByEmail)
+        | (&Get, &Route::UsersSearchFuzzy)
+        | (&Get, &Route::UsersAcquisition)
+        | (&Get, &Route::UserCount)
+        | (&Post, &Route::UsersBatch)
+        | (&Post, &Route::UsersSearch)
+        | (&Post, &Route::UsersSearchPage) => Some((Resource::Users, Action::Read)),
+        (&Post, &Route::UsersExport) => Some((Resource::Users, Action::Export)),
+        (&Put, &Route::User(_)) => Some((Resource::Users, Action::Update)),
+        (&Post, &Route::UserBlock(_)) | (&Post, &Route::UserUnblock(_)) => Some((Resource::Users, Action::Block)),
+        (&Get, &Route::UserBlocks(_)) => Some((Resource::UserBlocks, Action::Read)),
+        (&Get, &Route::RolesByUserId { .. }) => Some((Resource::UserRoles, Action::Read)),
+        (&Get, &Route::CurrentLoginEvents) => Some((Resource::LoginEvents, Action::Read)),
+        (&Get, &Route::UserNotes(_)) => Some((Resource::UserNotes, Action::Read)),
+        (&Post, &Route::UserNotes(_)) => Some((Resource::UserNotes, Action::Create)),
+        (&Delete, &Route::UserNote { .. }) => Some((Resource::UserNotes, Action::Delete)),
+        (&Get, &Route::UserReferrals(_))
+        | (&Get, &Route::UserReferralsStats(_))
+        | (&Get, &Route::ReferralsStats)
+        | (&Get, &Route::ReferralsTop) => Some((Resource::Referrals, Action::Read)),
+        (&Get, &Route::Webhooks) | (&Get, &Route::WebhookDeliveries) => Some((Resource::Webhooks, Action::Read)),
+        (&Post, &Route::Webhooks) => Some((Resource::Webhooks, Action::Create)),
+        (&Delete, &Route::Webhook { .. }) => Some((Resource::Webhooks, Action::Delete)),
+        (&Post, &Route::WebhookDeliveryReplay { .. }) => Some((Resource::Webhooks, Action::Update)),
+        _ => None,
+    }
+}
+
+/// Passes entity tag of resulting user to `EntityTagged`, that sets `ETag` header of the response
+fn with_entity_tag(fut: ServiceFuture<models::User>, entity_tag: EntityTagSink) -> ServiceFuture<models::User> {
+    Box::new(fut.map(move |user| {
+        entity_tag.put(user.entity_tag());
+        user
+    }))
+}
+
+/// Returns entity tags from `If-Match` header, `None` means that any entity matches
+fn get_if_match(req: &Request) -> Option<Vec<String>> {
+    match req.headers().get::<IfMatch>() {
+        Some(IfMatch::Items(tags)) => Some(tags.iter().map(|tag| tag.tag().to_string()).collect()),
+        Some(IfMatch::Any) | None => None,
+    }
+}
+
+/// Returns address of the client. Forwarding headers are honoured only if the peer is a trusted proxy,
+/// then the right-most untrusted address of `X-Forwarded-For` is taken, `X-Real-IP` is a fallback.
+fn get_client_ip(req: &Request, trusted_proxies: &[IpAddr]) -> Option<String> {
+    let peer = req.remote_addr().map(|addr| addr.ip());
+    let is_trusted = |ip: &IpAddr| trusted_proxies.contains(ip);
+    match peer {
+        Some(ref peer) if is_trusted(peer) => {}
+        _ => return peer.map(|peer| peer.to_string()),
+    }
+
+    let header_values = |name| {
+        req.headers()
+            .get_raw(name)
+            .into_iter()
+            .flat_map(|raw| raw.iter())
+            .filter_map(|value| ::std::str::from_utf8(value).ok())
+            .flat_map(|value| value.split(','))
+            .map(|value| value.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>()
+    };
+
+    // every hop appends address of its peer, so addresses left of the first untrusted one may be forged
+    let forwarded_ip = header_values("X-Forwarded-For")
+        .into_iter()
+        .rev()
+        .take_while(|ip| ip.is_some())
+        .filter_map(|ip| ip)
+        .find(|ip| !is_trusted(ip));
+    let real_ip = || header_values("X-Real-IP").into_iter().filter_map(|ip| ip).next();
+
+    forwarded_ip.or_else(real_ip).or(peer).map(|ip| ip.to_string())
+}
+
 fn get_user_id(req: &Request) -> Option<UserId> {
     req.headers()
         .get::<Authorization<String>>()
@@ -458,3 +1097,56 @@ fn get_user_id(req: &Request) -> Option<UserId> {
         .and_then(|id| i32::from_str(&id).ok())
         .map(UserId)
 }
+
+/// Returns token from `Authorization: Bearer` header. Numeric user id
+/// in the same header is not a bearer token, so it is left to `get_user_id`.
+fn get_bearer_token(req: &Request) -> Option<String> {
+    req.headers().get::<Authorization<String>>().and_then(|auth| {
+        let mut parts = auth.0.splitn(2, ' ');
+        match (parts.next(), parts.next()) {
+            (Some(models::BEARER_TOKEN_TYPE), Some(token)) => Some(token.trim().to_string()),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hyper::header::Authorization;
+    use hyper::server::Request;
+    use hyper::{Get, Put};
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use super::authenticate;
+    use errors::Error;
+    use repos::repo_factory::tests::*;
+
+    #[test]
+    fn test_api_token_out_of_scope() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+
+        // mock token is scoped to read users only
+        let mut req = Request::new(Put, "/users/1".parse().unwrap());
+        req.headers_mut().set(Authorization(format!("Bearer {}", MOCK_API_TOKEN)));
+        let error = match core.run(authenticate(&service.static_context, &req)) {
+            Ok(_) => panic!("API token without update scope is allowed to update user"),
+            Err(error) => error,
+        };
+        match error.causes().filter_map(|cause| cause.downcast_ref::<Error>()).next() {
+            Some(Error::Forbidden) => {}
+            _ => panic!("API token out of scope is not forbidden: {}", error),
+        }
+
+        let mut req = Request::new(Get, "/users/1".parse().unwrap());
+        req.headers_mut().set(Authorization(format!("Bearer {}", MOCK_API_TOKEN)));
+        let service = core.run(authenticate(&service.static_context, &req)).ok().unwrap();
+        assert_eq!(service.dynamic_context.user_id, Some(UserId(1)));
+        assert!(service.dynamic_context.api_token.is_some());
+    }
+}
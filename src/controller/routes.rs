@@ -1,5 +1,6 @@
 use stq_router::RouteParser;
 use stq_types::{RoleId, UserId};
+use uuid::Uuid;
 
 /// List of all routes with params for the app
 #[derive(Clone, Debug, PartialEq)]
@@ -10,13 +11,32 @@ pub enum Route {
     UserDelete(UserId),
     UserBlock(UserId),
     UserUnblock(UserId),
+    UserBlocks(UserId),
+    UserReactivate(UserId),
+    UserNotes(UserId),
+    UserNote { user_id: UserId, id: Uuid },
+    UserReferrals(UserId),
+    UserReferralsStats(UserId),
     UserBySagaId(String),
     UserCount,
     UsersSearch,
+    UsersSearchPage,
     UsersSearchByEmail,
+    UsersSearchFuzzy,
+    UsersExport,
+    UsersImport,
+    UsersAcquisition,
+    UsersBatch,
     UserByEmail,
     Current,
+    CurrentLoginEvents,
+    CurrentApiTokens,
+    CurrentApiToken { id: Uuid },
+    CurrentDeletion,
+    UserDeletionCancel,
     JWTEmail,
+    JWTEmailConfirm,
+    JWTImpersonate,
     JWTGoogle,
     JWTFacebook,
     JWTRefresh,
@@ -26,9 +46,20 @@ pub enum Route {
     RolesByUserId { user_id: UserId },
     PasswordChange,
     UserPasswordResetToken,
+    UserReactivationToken,
     UserEmailVerifyToken,
     GetUserEmalVerifyToken { user_id: UserId },
     GetUserPasswordResetToken { user_id: UserId },
+    GetUserLoginConfirmationToken { user_id: UserId },
+    Webhooks,
+    Webhook { id: Uuid },
+    WebhookDeliveries,
+    WebhookDeliveryReplay { id: Uuid },
+    ReferralsStats,
+    ReferralsTop,
+    ServiceClients,
+    ServiceClient { client_id: String },
+    OAuthToken,
 }
 
 pub fn create_route_parser() -> RouteParser<Route> {
@@ -40,12 +71,31 @@ pub fn create_route_parser() -> RouteParser<Route> {
     // Users Routes
     router.add_route(r"^/users$", || Route::Users);
 
+    // Users batch Route
+    router.add_route(r"^/users/batch$", || Route::UsersBatch);
+
     // User by email Route
     router.add_route(r"^/users/by_email$", || Route::UserByEmail);
 
     // Users Routes
     router.add_route(r"^/users/current$", || Route::Current);
 
+    // Sign-ins history of current user Route
+    router.add_route(r"^/users/current/login_events$", || Route::CurrentLoginEvents);
+
+    // API tokens of current user Routes
+    router.add_route(r"^/users/current/api_tokens$", || Route::CurrentApiTokens);
+    router.add_route_with_params(r"^/users/current/api_tokens/([a-zA-Z0-9-]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::CurrentApiToken { id })
+    });
+
+    // Deletion of current user account Routes
+    router.add_route(r"^/users/current/deletion$", || Route::CurrentDeletion);
+    router.add_route(r"^/users/deletion/cancel$", || Route::UserDeletionCancel);
+
     router.add_route_with_params(r"^/users/(\d+)/delete$", |params| {
         params
             .get(0)
@@ -56,6 +106,12 @@ pub fn create_route_parser() -> RouteParser<Route> {
     // JWT email route
     router.add_route(r"^/jwt/email$", || Route::JWTEmail);
 
+    // JWT email sign-in confirmation route
+    router.add_route(r"^/jwt/email/confirm$", || Route::JWTEmailConfirm);
+
+    // JWT impersonation route
+    router.add_route(r"^/jwt/impersonate$", || Route::JWTImpersonate);
+
     // JWT google route
     router.add_route(r"^/jwt/google$", || Route::JWTGoogle);
 
@@ -92,6 +148,58 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .map(Route::UserUnblock)
     });
 
+    // Users/:id/blocks route
+    router.add_route_with_params(r"^/users/(\d+)/blocks$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(Route::UserBlocks)
+    });
+
+    // Users/:id/reactivate route
+    router.add_route_with_params(r"^/users/(\d+)/reactivate$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(Route::UserReactivate)
+    });
+
+    // Users/:id/notes route
+    router.add_route_with_params(r"^/users/(\d+)/notes$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(Route::UserNotes)
+    });
+
+    // Users/:id/notes/:id route
+    router.add_route_with_params(r"^/users/(\d+)/notes/([a-zA-Z0-9-]+)$", |params| {
+        if let (Some(user_id), Some(id)) = (params.get(0), params.get(1)) {
+            match (user_id.parse().ok(), id.parse().ok()) {
+                (Some(user_id), Some(id)) => Some(Route::UserNote { user_id, id }),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    });
+
+    // Users/:id/referrals route
+    router.add_route_with_params(r"^/users/(\d+)/referrals$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(Route::UserReferrals)
+    });
+
+    // Users/:id/referrals/stats route
+    router.add_route_with_params(r"^/users/(\d+)/referrals/stats$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse::<UserId>().ok())
+            .map(Route::UserReferralsStats)
+    });
+
     // Users/:id route
     router.add_route_with_params(r"^/user_by_saga_id/(.+)$", |params| {
         params
@@ -123,6 +231,9 @@ pub fn create_route_parser() -> RouteParser<Route> {
     // /users/password_reset_token route
     router.add_route(r"^/users/password_reset_token$", || Route::UserPasswordResetToken);
 
+    // /users/reactivation_token route
+    router.add_route(r"^/users/reactivation_token$", || Route::UserReactivationToken);
+
     // Get user password reset token route
     router.add_route_with_params(r"^/users/(\d+)/password_reset_token$", |params| {
         params
@@ -142,11 +253,63 @@ pub fn create_route_parser() -> RouteParser<Route> {
             .map(|user_id| Route::GetUserEmalVerifyToken { user_id })
     });
 
+    // Get token of pending sign-in confirmation of the user route
+    router.add_route_with_params(r"^/users/(\d+)/login_confirmation_token$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|user_id| Route::GetUserLoginConfirmationToken { user_id })
+    });
+
     // Search users
     router.add_route(r"^/users/search$", || Route::UsersSearch);
 
+    // Search users page by page
+    router.add_route(r"^/users/search/page$", || Route::UsersSearchPage);
+
     // Users search by email fuzzy Routes
     router.add_route(r"^/users/search/by_email$", || Route::UsersSearchByEmail);
 
+    // Users export Route
+    router.add_route(r"^/users/export$", || Route::UsersExport);
+
+    // Users import Route
+    router.add_route(r"^/users/import$", || Route::UsersImport);
+
+    // Users acquisition analytics Route
+    router.add_route(r"^/users/acquisition$", || Route::UsersAcquisition);
+
+    // Users fuzzy search ranked by similarity Route
+    router.add_route(r"^/users/search/fuzzy$", || Route::UsersSearchFuzzy);
+
+    // Referral program routes
+    router.add_route(r"^/referrals/stats$", || Route::ReferralsStats);
+    router.add_route(r"^/referrals/top$", || Route::ReferralsTop);
+
+    // Internal services routes
+    router.add_route(r"^/service_clients$", || Route::ServiceClients);
+    router.add_route_with_params(r"^/service_clients/([a-zA-Z0-9-]+)$", |params| {
+        params.get(0).map(|client_id| Route::ServiceClient {
+            client_id: client_id.to_string(),
+        })
+    });
+    router.add_route(r"^/oauth/token$", || Route::OAuthToken);
+
+    // Webhooks routes
+    router.add_route(r"^/webhooks$", || Route::Webhooks);
+    router.add_route(r"^/webhooks/deliveries$", || Route::WebhookDeliveries);
+    router.add_route_with_params(r"^/webhooks/deliveries/([a-zA-Z0-9-]+)/replay$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::WebhookDeliveryReplay { id })
+    });
+    router.add_route_with_params(r"^/webhooks/([a-zA-Z0-9-]+)$", |params| {
+        params
+            .get(0)
+            .and_then(|string_id| string_id.parse().ok())
+            .map(|id| Route::Webhook { id })
+    });
+
     router
 }
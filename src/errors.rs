@@ -22,6 +22,10 @@ pub enum Error {
     InvalidToken,
     #[fail(display = "Invalid time duration")]
     InvalidTime,
+    #[fail(display = "Precondition failed")]
+    PreconditionFailed(serde_json::Value),
+    #[fail(display = "Account is deactivated by administrator")]
+    DeactivatedByAdmin,
 }
 
 impl Codeable for Error {
@@ -31,7 +35,8 @@ impl Codeable for Error {
             Error::Validate(_) => StatusCode::BadRequest,
             Error::Parse => StatusCode::UnprocessableEntity,
             Error::Connection | Error::HttpClient | Error::InvalidTime => StatusCode::InternalServerError,
-            Error::Forbidden | Error::InvalidToken => StatusCode::Forbidden,
+            Error::Forbidden | Error::InvalidToken | Error::DeactivatedByAdmin => StatusCode::Forbidden,
+            Error::PreconditionFailed(_) => StatusCode::PreconditionFailed,
         }
     }
 }
@@ -40,6 +45,7 @@ impl PayloadCarrier for Error {
     fn payload(&self) -> Option<serde_json::Value> {
         match *self {
             Error::Validate(ref e) => serde_json::to_value(e.clone()).ok(),
+            Error::PreconditionFailed(ref current) => Some(current.clone()),
             _ => None,
         }
     }
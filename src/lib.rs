@@ -18,6 +18,7 @@ extern crate diesel;
 extern crate failure;
 extern crate futures;
 extern crate futures_cpupool;
+extern crate hmac;
 extern crate hyper;
 extern crate hyper_tls;
 extern crate jsonwebtoken;
@@ -29,13 +30,16 @@ extern crate r2d2;
 extern crate r2d2_redis;
 extern crate rand;
 extern crate regex;
+extern crate ring;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate sha2;
 extern crate sha3;
 extern crate tokio_core;
 extern crate tokio_signal;
+extern crate untrusted;
 extern crate uuid;
 extern crate validator;
 #[macro_use]
@@ -61,8 +65,6 @@ pub mod schema;
 pub mod sentry_integration;
 pub mod services;
 
-use std::fs::File;
-use std::io::prelude::*;
 use std::process;
 use std::sync::Arc;
 use std::time::Duration;
@@ -74,14 +76,20 @@ use futures_cpupool::CpuPool;
 use hyper::server::Http;
 use r2d2_redis::RedisConnectionManager;
 use stq_cache::cache::{redis::RedisCache, Cache, NullCache, TypedCache};
-use stq_http::controller::Application;
 use tokio_core::reactor::Core;
 
 use config::Config;
 use controller::context::StaticContext;
-use errors::Error;
+use controller::entity_tag::EntityTagged;
+use controller::export::UsersExporter;
 use repos::acl::RolesCacheImpl;
 use repos::repo_factory::ReposFactoryImpl;
+use services::account_deletion::spawn_account_deletion_job;
+use services::geoip::GeoIp;
+use services::jwt::signer::JwtSigner;
+use services::password_policy::PasswordPolicy;
+use services::users::spawn_expired_blocks_job;
+use services::webhooks::spawn_webhook_delivery_job;
 
 /// Starts new web service from provided `Config`
 pub fn start_server(config: Config) {
@@ -136,18 +144,40 @@ pub fn start_server(config: Config) {
 
     let repo_factory = ReposFactoryImpl::new(roles_cache);
 
-    debug!("Reading private key file {}", &config.jwt.secret_key_path);
-    let mut f = File::open(config.jwt.secret_key_path.clone()).unwrap();
-    let mut jwt_private_key: Vec<u8> = Vec::new();
-    f.read_to_end(&mut jwt_private_key).unwrap();
+    debug!(
+        "Reading {:?} keys {} and {}",
+        config.jwt.algorithm, &config.jwt.secret_key_path, &config.jwt.public_key_path
+    );
+    let jwt_signer = Arc::new(JwtSigner::from_config(&config.jwt).unwrap());
+
+    let geoip = config.suspicious_login.geoip_path.as_ref().map(|path| {
+        debug!("Reading GeoIP database {}", path);
+        Arc::new(GeoIp::from_file(path).unwrap())
+    });
+
+    let password_policy = Arc::new(PasswordPolicy::from_config(&config.password_policy).unwrap());
+
+    let context = StaticContext::new(
+        db_pool,
+        cpu_pool,
+        client_handle,
+        Arc::new(config),
+        repo_factory,
+        jwt_signer,
+        geoip,
+        password_policy,
+    );
 
-    let context = StaticContext::new(db_pool, cpu_pool, client_handle, Arc::new(config), repo_factory, jwt_private_key);
+    spawn_account_deletion_job(context.clone(), &handle).unwrap();
+    spawn_webhook_delivery_job(context.clone(), &handle).unwrap();
+    spawn_expired_blocks_job(context.clone(), &handle).unwrap();
 
+    let export_handle = handle.clone();
     let serve = Http::new()
         .serve_addr_handle(&address, &handle, move || {
             // Prepare application
-            let controller = controller::ControllerImpl::new(context.clone());
-            let app = Application::<Error>::new(controller);
+            let app = EntityTagged::new(context.clone());
+            let app = UsersExporter::new(app, context.clone(), export_handle.clone());
 
             Ok(app)
         })
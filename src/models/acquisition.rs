@@ -0,0 +1,95 @@
+//! Models for acquisition analytics built from signup data of users
+use std::str::FromStr;
+
+use diesel::sql_types::{BigInt, Nullable, VarChar};
+use failure::Error as FailureError;
+
+use models::DateRange;
+
+/// Signup attribute users are grouped by in acquisition report
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AcquisitionGroupBy {
+    UtmSource,
+    UtmMedium,
+    UtmCampaign,
+    RefererHost,
+    Country,
+    Provider,
+}
+
+impl Default for AcquisitionGroupBy {
+    fn default() -> Self {
+        AcquisitionGroupBy::UtmSource
+    }
+}
+
+impl FromStr for AcquisitionGroupBy {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utm_source" => Ok(AcquisitionGroupBy::UtmSource),
+            "utm_medium" => Ok(AcquisitionGroupBy::UtmMedium),
+            "utm_campaign" => Ok(AcquisitionGroupBy::UtmCampaign),
+            "referer_host" => Ok(AcquisitionGroupBy::RefererHost),
+            "country" => Ok(AcquisitionGroupBy::Country),
+            "provider" => Ok(AcquisitionGroupBy::Provider),
+            _ => Err(format_err!("Unknown acquisition group {}", s)),
+        }
+    }
+}
+
+impl AcquisitionGroupBy {
+    /// SQL expression of the grouped value. Utm marks are read from JSONB column
+    /// directly, so that expression indexes on `utm_marks` can be used.
+    pub fn sql_expression(&self) -> &'static str {
+        match *self {
+            AcquisitionGroupBy::UtmSource => "users.utm_marks ->> 'utm_source'",
+            AcquisitionGroupBy::UtmMedium => "users.utm_marks ->> 'utm_medium'",
+            AcquisitionGroupBy::UtmCampaign => "users.utm_marks ->> 'utm_campaign'",
+            AcquisitionGroupBy::RefererHost => {
+                "lower(substring(users.referer from '^(?:[a-zA-Z][a-zA-Z0-9+.-]*:)?(?://)?(?:[^@/?#]*@)?([^:/?#]+)'))"
+            }
+            AcquisitionGroupBy::Country => "users.country",
+            AcquisitionGroupBy::Provider => "identities.provider",
+        }
+    }
+}
+
+/// Signups having the same value of grouped attribute, missing values are grouped as `null`
+#[derive(Clone, Debug, Serialize, QueryableByName)]
+pub struct AcquisitionRow {
+    #[sql_type = "Nullable<VarChar>"]
+    pub value: Option<String>,
+    #[sql_type = "BigInt"]
+    pub signups: i64,
+    #[sql_type = "BigInt"]
+    pub verified: i64,
+    #[sql_type = "BigInt"]
+    pub active: i64,
+}
+
+/// Acquisition report for signups made in `period`, both bounds are inclusive
+#[derive(Clone, Debug, Serialize)]
+pub struct AcquisitionReport {
+    pub group_by: AcquisitionGroupBy,
+    pub period: DateRange,
+    pub signups: i64,
+    pub verified: i64,
+    pub active: i64,
+    pub rows: Vec<AcquisitionRow>,
+}
+
+impl AcquisitionReport {
+    pub fn new(group_by: AcquisitionGroupBy, period: DateRange, rows: Vec<AcquisitionRow>) -> Self {
+        Self {
+            group_by,
+            period,
+            signups: rows.iter().map(|row| row.signups).sum(),
+            verified: rows.iter().map(|row| row.verified).sum(),
+            active: rows.iter().map(|row| row.active).sum(),
+            rows,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Models for personal API tokens, that let users call APIs without sharing passwords
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use serde_json;
+use uuid::Uuid;
+use validator::Validate;
+
+use stq_types::UserId;
+
+use models::authorization::{Action, Resource};
+use schema::api_tokens;
+
+/// Prefix of API tokens, that tells them apart from json web tokens
+pub const API_TOKEN_PREFIX: &'static str = "stq_";
+
+/// Pair of resource and action, that API token is restricted to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiTokenScope {
+    pub resource: Resource,
+    pub action: Action,
+}
+
+impl ApiTokenScope {
+    pub fn allows(&self, resource: Resource, action: Action) -> bool {
+        self.resource == resource && (self.action == action || self.action == Action::All)
+    }
+}
+
+/// Personal API token of specific user, the token itself is stored hashed
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scopes: serde_json::Value,
+    /// End of token lifetime, `None` for token without expiry
+    pub expires_at: Option<SystemTime>,
+    pub revoked_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+}
+
+impl ApiToken {
+    /// Checks that token has been neither revoked nor expired by the time
+    pub fn is_active_at(&self, at: SystemTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at.map(|expires_at| expires_at > at).unwrap_or(true)
+    }
+
+    /// Resource and action pairs, that token is restricted to
+    pub fn scopes(&self) -> Vec<ApiTokenScope> {
+        serde_json::from_value(self.scopes.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "api_tokens"]
+pub struct NewApiToken {
+    pub user_id: UserId,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: serde_json::Value,
+    pub expires_at: Option<SystemTime>,
+}
+
+/// Payload for creating API token, token without `expires_at` never expires
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct NewApiTokenPayload {
+    #[validate(length(min = "1", max = "100", message = "Name must be between 1 and 100 symbols"))]
+    pub name: String,
+    #[validate(length(min = "1", message = "Token must have at least one scope"))]
+    pub scopes: Vec<ApiTokenScope>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Newly created API token, the token itself is shown only once
+#[derive(Clone, Debug, Serialize)]
+pub struct CreatedApiToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+}
@@ -0,0 +1,77 @@
+//! Models for audit log of privileged actions
+use std::fmt;
+use std::io::Write;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::VarChar;
+use serde_json;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use schema::audit_log;
+
+/// Privileged actions recorded in audit log
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, FromSqlRow, AsExpression)]
+#[sql_type = "VarChar"]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    UsersExported,
+    UserImpersonated,
+    ImpersonatedRequest,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            AuditAction::UsersExported => "users_exported",
+            AuditAction::UserImpersonated => "user_impersonated",
+            AuditAction::ImpersonatedRequest => "impersonated_request",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql<VarChar, Pg> for AuditAction {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<VarChar, Pg> for AuditAction {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"users_exported" => Ok(AuditAction::UsersExported),
+            b"user_impersonated" => Ok(AuditAction::UserImpersonated),
+            b"impersonated_request" => Ok(AuditAction::ImpersonatedRequest),
+            _ => Err("Unrecognized audit action".into()),
+        }
+    }
+}
+
+/// Record of privileged action made by specific user
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub user_id: Option<UserId>,
+    pub action: AuditAction,
+    pub payload: serde_json::Value,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditLogEntry {
+    pub user_id: Option<UserId>,
+    pub action: AuditAction,
+    pub payload: serde_json::Value,
+}
@@ -4,14 +4,18 @@ use std::fmt;
 // All gives all permissions.
 // Index - list resources, Read - read resource with id,
 // Write - Update or delete resource with id.
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     All,
     Read,
     Create,
     Update,
     Delete,
+    /// Delete user, created by failed saga, by its saga id
+    DeleteBySaga,
     Block,
+    Export,
 }
 
 impl fmt::Display for Action {
@@ -22,7 +26,9 @@ impl fmt::Display for Action {
             Action::Create => write!(f, "create"),
             Action::Update => write!(f, "update"),
             Action::Delete => write!(f, "delete"),
+            Action::DeleteBySaga => write!(f, "delete_by_saga"),
             Action::Block => write!(f, "block"),
+            Action::Export => write!(f, "export"),
         }
     }
 }
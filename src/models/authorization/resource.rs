@@ -1,10 +1,19 @@
 //! Enum for resources available in ACLs
 use std::fmt;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Resource {
     Users,
     UserRoles,
+    Webhooks,
+    AuditLog,
+    Referrals,
+    LoginEvents,
+    UserBlocks,
+    UserNotes,
+    ServiceClients,
+    ApiTokens,
 }
 
 impl fmt::Display for Resource {
@@ -12,6 +21,14 @@ impl fmt::Display for Resource {
         match *self {
             Resource::Users => write!(f, "users"),
             Resource::UserRoles => write!(f, "user roles"),
+            Resource::Webhooks => write!(f, "webhooks"),
+            Resource::AuditLog => write!(f, "audit log"),
+            Resource::Referrals => write!(f, "referrals"),
+            Resource::LoginEvents => write!(f, "login events"),
+            Resource::UserBlocks => write!(f, "user blocks"),
+            Resource::UserNotes => write!(f, "user notes"),
+            Resource::ServiceClients => write!(f, "service clients"),
+            Resource::ApiTokens => write!(f, "api tokens"),
         }
     }
 }
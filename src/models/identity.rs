@@ -1,5 +1,6 @@
 //! Models for working with identities
 use std::fmt;
+use std::time::SystemTime;
 
 use uuid::Uuid;
 use validator::Validate;
@@ -7,7 +8,7 @@ use validator::Validate;
 use stq_static_resources::Provider;
 use stq_types::UserId;
 
-use schema::identities;
+use schema::{identities, password_history};
 
 /// Payload for creating identity for users
 #[derive(Debug, Serialize, Deserialize, Validate, Queryable, Insertable, Clone)]
@@ -27,7 +28,7 @@ pub struct Identity {
 pub struct NewIdentity {
     #[validate(email(code = "not_valid", message = "Invalid email format"))]
     pub email: String,
-    #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
+    /// Checked by `PasswordPolicy`
     pub password: Option<String>,
     pub provider: Provider,
     pub saga_id: String,
@@ -43,7 +44,7 @@ pub struct EmailIdentity {
 #[derive(Clone, Debug, Serialize, Deserialize, Validate)]
 pub struct ChangeIdentityPassword {
     pub old_password: String,
-    #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
+    /// Checked by `PasswordPolicy`
     pub new_password: String,
 }
 
@@ -72,3 +73,19 @@ impl fmt::Display for EmailIdentity {
         write!(f, "EmailIdentity {{ email: \"{}\", password: \"******\" }}", self.email)
     }
 }
+
+/// Hash of password, that identity had before
+#[derive(Clone, Debug, Queryable)]
+pub struct PasswordHistoryEntry {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub password: String,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "password_history"]
+pub struct NewPasswordHistoryEntry {
+    pub user_id: UserId,
+    pub password: String,
+}
@@ -1,7 +1,12 @@
 //! Models for managing Json Web Token
 
+use chrono::Utc;
+use uuid::Uuid;
+
 use stq_static_resources::Provider;
-use stq_types::{Alpha3, UserId};
+use stq_types::{Alpha3, UserId, UsersRole};
+
+use models::SERVICE_TOKEN_AUDIENCE;
 
 /// Json Web Token created by provider user status
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -30,18 +35,109 @@ pub struct JWTPayload {
     pub user_id: UserId,
     pub exp: i64,
     pub provider: Provider,
+    /// Superuser, that acts on behalf of the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonator: Option<UserId>,
+    /// Issuer of the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// Intended audience of the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    /// Time the token was issued at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    /// Time the user signed in with credentials, kept on refresh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+    /// Subject of the token, same as `user_id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    /// Unique id of the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Roles of the user at the time the token was issued
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<UsersRole>>,
+    /// Version of the user roles, embedded roles are stale if it is behind `User::roles_version`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles_version: Option<i32>,
 }
 
 impl JWTPayload {
     pub fn new(id: UserId, exp_arg: i64, provider_arg: Provider) -> Self {
+        let now = Utc::now().timestamp();
         Self {
             user_id: id,
             exp: exp_arg,
             provider: provider_arg,
+            impersonator: None,
+            iss: None,
+            aud: None,
+            iat: Some(now),
+            auth_time: Some(now),
+            sub: Some(id.to_string()),
+            jti: Some(Uuid::new_v4().to_string()),
+            roles: None,
+            roles_version: None,
+        }
+    }
+
+    /// Sets issuer and audience claims
+    pub fn with_issuer(self, iss: String, aud: Option<String>) -> Self {
+        Self {
+            iss: Some(iss),
+            aud,
+            ..self
+        }
+    }
+
+    /// Checks that the token was issued by `issuer` for `audience`, the way `with_issuer` sets them
+    pub fn is_issued_by(&self, issuer: &str, audience: Option<&str>) -> bool {
+        self.iss.as_ref().map(|iss| iss.as_str()) == Some(issuer) && self.aud.as_ref().map(|aud| aud.as_str()) == audience
+    }
+
+    /// Checks that the token was not issued to internal service
+    pub fn is_user_token(&self) -> bool {
+        self.aud.as_ref().map(|aud| aud != SERVICE_TOKEN_AUDIENCE).unwrap_or(true)
+    }
+
+    /// Sets time the user signed in, `None` if it is unknown
+    pub fn with_auth_time(self, auth_time: Option<i64>) -> Self {
+        Self { auth_time, ..self }
+    }
+
+    /// Embeds roles of the user and their version
+    pub fn with_roles(self, roles: Vec<UsersRole>, roles_version: i32) -> Self {
+        Self {
+            roles: Some(roles),
+            roles_version: Some(roles_version),
+            ..self
         }
     }
 }
 
+/// Payload for impersonating specific user
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImpersonateUser {
+    pub user_id: UserId,
+}
+
+/// Payload of audit log entry recorded for every impersonation token issued
+#[derive(Debug, Serialize)]
+pub struct ImpersonationAudit {
+    pub user_id: UserId,
+    pub exp: i64,
+}
+
+/// Payload of audit log entry recorded for every request made with impersonation token
+#[derive(Debug, Serialize)]
+pub struct ImpersonatedRequestAudit {
+    pub user_id: Option<UserId>,
+    pub method: String,
+    pub path: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct NewUserAdditionalData {
     pub referal: Option<UserId>,
@@ -0,0 +1,141 @@
+//! Models for history of users sign-ins
+use std::time::SystemTime;
+
+use base64::encode;
+use uuid::Uuid;
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use schema::{login_confirmations, login_events};
+
+/// Sign-in attempt of specific user
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct LoginEvent {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub succeeded: bool,
+    pub failure_reason: Option<String>,
+    pub created_at: SystemTime,
+    /// Fingerprint of the device, the user has signed in from
+    pub device: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "login_events"]
+pub struct NewLoginEvent {
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub succeeded: bool,
+    pub failure_reason: Option<String>,
+    pub device: Option<String>,
+    pub country: Option<String>,
+}
+
+/// Client, that is signing in
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LoginClient {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub device: Option<String>,
+    pub country: Option<String>,
+}
+
+impl NewLoginEvent {
+    pub fn new(user_id: UserId, provider: Provider, client: LoginClient, failure_reason: Option<String>) -> Self {
+        Self {
+            user_id,
+            provider,
+            ip: client.ip,
+            user_agent: client.user_agent,
+            succeeded: failure_reason.is_none(),
+            failure_reason,
+            device: client.device,
+            country: client.country,
+        }
+    }
+}
+
+/// Reasons to consider sign-in suspicious
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuspiciousLoginReason {
+    NewDevice,
+    NewCountry,
+}
+
+/// Data of `suspicious_login` security event
+#[derive(Clone, Debug, Serialize)]
+pub struct SuspiciousLogin {
+    pub provider: Provider,
+    pub client: LoginClient,
+    pub reasons: Vec<SuspiciousLoginReason>,
+    /// Sign-in must be confirmed by token, that is sent by email, never by webhooks
+    pub confirmation_required: bool,
+}
+
+/// Suspicious sign-in waiting for confirmation by email
+#[derive(Clone, Debug, Queryable)]
+pub struct LoginConfirmation {
+    pub token: String,
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub device: Option<String>,
+    pub country: Option<String>,
+    pub created_at: SystemTime,
+    pub expires_at: SystemTime,
+    pub confirmed_at: Option<SystemTime>,
+}
+
+impl LoginConfirmation {
+    pub fn client(&self) -> LoginClient {
+        LoginClient {
+            ip: self.ip.clone(),
+            user_agent: self.user_agent.clone(),
+            device: self.device.clone(),
+            country: self.country.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "login_confirmations"]
+pub struct NewLoginConfirmation {
+    pub token: String,
+    pub user_id: UserId,
+    pub provider: Provider,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub device: Option<String>,
+    pub country: Option<String>,
+    pub expires_at: SystemTime,
+}
+
+impl NewLoginConfirmation {
+    pub fn new(user_id: UserId, provider: Provider, client: LoginClient, expires_at: SystemTime) -> Self {
+        Self {
+            token: encode(&Uuid::new_v4().to_string()),
+            user_id,
+            provider,
+            ip: client.ip,
+            user_agent: client.user_agent,
+            device: client.device,
+            country: client.country,
+            expires_at,
+        }
+    }
+}
+
+/// Request to issue JWT for confirmed suspicious sign-in
+#[derive(Clone, Debug, Deserialize)]
+pub struct LoginConfirmationApply {
+    pub token: String,
+}
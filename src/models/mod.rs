@@ -1,19 +1,45 @@
 //! Models contains all structures that are used in different
 //! modules of the app
 
+pub mod acquisition;
+pub mod api_token;
+pub mod audit_log;
 pub mod authorization;
 pub mod identity;
 pub mod jwt;
+pub mod login_event;
+pub mod referral;
 pub mod reset_token;
+pub mod service_client;
 pub mod user;
+pub mod user_block;
+pub mod user_deletion;
+pub mod user_export;
+pub mod user_import;
+pub mod user_note;
 pub mod user_role;
+pub mod user_search;
+pub mod webhook;
 
+pub use self::acquisition::*;
+pub use self::api_token::*;
+pub use self::audit_log::*;
 pub use self::authorization::*;
 pub use self::identity::*;
 pub use self::jwt::*;
+pub use self::login_event::*;
+pub use self::referral::*;
 pub use self::reset_token::*;
+pub use self::service_client::*;
 pub use self::user::*;
+pub use self::user_block::*;
+pub use self::user_deletion::*;
+pub use self::user_export::*;
+pub use self::user_import::*;
+pub use self::user_note::*;
 pub use self::user_role::*;
+pub use self::user_search::*;
+pub use self::webhook::*;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SagaCreateProfile {
@@ -0,0 +1,70 @@
+//! Models for referral program statistics
+use std::time::SystemTime;
+
+use chrono::NaiveDate;
+use diesel::sql_types::{BigInt, Date, Integer, VarChar};
+
+use stq_types::UserId;
+
+/// User registered by referral link of another user. Only public profile
+/// fields are exposed, because referrer may read referrals of its own.
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct Referral {
+    pub id: UserId,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub email_verified: bool,
+    pub created_at: SystemTime,
+}
+
+/// Number of referrals at specific depth, direct referrals have depth 1
+#[derive(Clone, Debug, Serialize, QueryableByName)]
+pub struct ReferralDepthCount {
+    #[sql_type = "Integer"]
+    pub depth: i32,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+/// Number of referred signups at specific day
+#[derive(Clone, Debug, Serialize, QueryableByName)]
+pub struct ReferralSignups {
+    #[sql_type = "Date"]
+    pub day: NaiveDate,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+/// Number of referred users with verified and unverified emails
+#[derive(Clone, Debug, Default, Serialize, QueryableByName)]
+pub struct ReferralVerification {
+    #[sql_type = "BigInt"]
+    pub verified: i64,
+    #[sql_type = "BigInt"]
+    pub unverified: i64,
+}
+
+/// Referral statistics of specific referrer or, if referrer is missing, of the whole program
+#[derive(Clone, Debug, Serialize)]
+pub struct ReferralStats {
+    pub referrer: Option<UserId>,
+    pub total: i64,
+    pub verified: i64,
+    pub unverified: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by_depth: Option<Vec<ReferralDepthCount>>,
+    pub signups_per_day: Vec<ReferralSignups>,
+}
+
+/// Referrer along with the number of users it has referred
+#[derive(Clone, Debug, Serialize, QueryableByName)]
+pub struct TopReferrer {
+    #[sql_type = "Integer"]
+    pub user_id: UserId,
+    #[sql_type = "VarChar"]
+    pub email: String,
+    #[sql_type = "BigInt"]
+    pub referrals: i64,
+    #[sql_type = "BigInt"]
+    pub verified_referrals: i64,
+}
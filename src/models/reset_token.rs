@@ -1,29 +1,79 @@
-//! Models for password reset
+//! Models for password reset and account reactivation
 use std::fmt;
+use std::io::Write;
 use std::time::SystemTime;
 
 use base64::encode;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::VarChar;
 use uuid::Uuid;
 use validator::Validate;
 
-use stq_static_resources::TokenType;
-
 use models::user::User;
 use schema::reset_tokens;
 
+/// Purpose of reset token. Mirrors `TokenType` of `stq_static_resources`, which has no reactivation tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, FromSqlRow, AsExpression)]
+#[sql_type = "VarChar"]
+#[serde(rename_all = "snake_case")]
+pub enum ResetTokenType {
+    EmailVerify,
+    PasswordReset,
+    Reactivation,
+    Undefined,
+}
+
+impl ResetTokenType {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ResetTokenType::EmailVerify => "email_verify",
+            ResetTokenType::PasswordReset => "password_reset",
+            ResetTokenType::Reactivation => "reactivation",
+            ResetTokenType::Undefined => "undefined",
+        }
+    }
+}
+
+impl fmt::Display for ResetTokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql<VarChar, Pg> for ResetTokenType {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<VarChar, Pg> for ResetTokenType {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"email_verify" => Ok(ResetTokenType::EmailVerify),
+            b"password_reset" => Ok(ResetTokenType::PasswordReset),
+            b"reactivation" => Ok(ResetTokenType::Reactivation),
+            b"undefined" => Ok(ResetTokenType::Undefined),
+            _ => Err("Unrecognized reset token type".into()),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Queryable, Insertable, Debug)]
 #[table_name = "reset_tokens"]
 pub struct ResetToken {
     pub token: String,
     pub email: String,
     pub created_at: SystemTime,
-    pub token_type: TokenType,
+    pub token_type: ResetTokenType,
     pub uuid: Uuid,
     pub updated_at: SystemTime,
 }
 
 impl ResetToken {
-    pub fn new(email: String, token_type: TokenType, uuid: Option<Uuid>) -> ResetToken {
+    pub fn new(email: String, token_type: ResetTokenType, uuid: Option<Uuid>) -> ResetToken {
         let uuid = uuid.unwrap_or(Uuid::new_v4());
         let token = encode(&Uuid::new_v4().to_string());
         ResetToken {
@@ -53,9 +103,15 @@ pub struct VerifyRequest {
 #[derive(Serialize, Deserialize, Validate, Debug)]
 pub struct ResetApply {
     pub token: String,
-    #[validate(length(min = "8", max = "30", message = "Password should be between 8 and 30 symbols"))]
+    /// Checked by `PasswordPolicy`
     pub password: String,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReactivationApply {
+    pub token: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResetApplyToken {
     pub email: String,
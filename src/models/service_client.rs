@@ -0,0 +1,160 @@
+//! Models for internal services, authenticated with client credentials
+use std::fmt;
+use std::io::Write;
+use std::time::SystemTime;
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::VarChar;
+use validator::Validate;
+
+use schema::service_clients;
+
+pub const CLIENT_CREDENTIALS_GRANT_TYPE: &'static str = "client_credentials";
+pub const BEARER_TOKEN_TYPE: &'static str = "Bearer";
+/// `typ` claim of tokens issued to internal services
+pub const SERVICE_TOKEN_TYPE: &'static str = "service";
+/// `aud` claim of tokens issued to internal services, user tokens must never have it
+pub const SERVICE_TOKEN_AUDIENCE: &'static str = "services";
+
+/// Roles of internal services, each role has its own set of permissions
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, FromSqlRow, AsExpression)]
+#[sql_type = "VarChar"]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceRole {
+    Saga,
+    Orders,
+    Stores,
+}
+
+impl ServiceRole {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ServiceRole::Saga => "saga",
+            ServiceRole::Orders => "orders",
+            ServiceRole::Stores => "stores",
+        }
+    }
+}
+
+impl fmt::Display for ServiceRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql<VarChar, Pg> for ServiceRole {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<VarChar, Pg> for ServiceRole {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"saga" => Ok(ServiceRole::Saga),
+            b"orders" => Ok(ServiceRole::Orders),
+            b"stores" => Ok(ServiceRole::Stores),
+            _ => Err("Unrecognized service role".into()),
+        }
+    }
+}
+
+/// Registered internal service, secret is stored hashed
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct ServiceClient {
+    pub client_id: String,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub secret_hash: String,
+    pub role: ServiceRole,
+    pub is_active: bool,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "service_clients"]
+pub struct NewServiceClient {
+    pub client_id: String,
+    pub name: String,
+    pub secret_hash: String,
+    pub role: ServiceRole,
+}
+
+/// Payload for registering internal service
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct NewServiceClientPayload {
+    #[validate(length(min = "1", max = "100", message = "Name must be between 1 and 100 symbols"))]
+    pub name: String,
+    pub role: ServiceRole,
+}
+
+/// Registered internal service with its secret, the secret is shown only once
+#[derive(Clone, Debug, Serialize)]
+pub struct CreatedServiceClient {
+    pub client_id: String,
+    pub client_secret: String,
+    pub name: String,
+    pub role: ServiceRole,
+}
+
+/// OAuth 2.0 access token request with `client_credentials` grant
+#[derive(Clone, Debug, Deserialize)]
+pub struct ClientCredentialsRequest {
+    pub grant_type: String,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+/// OAuth 2.0 access token response
+#[derive(Clone, Debug, Serialize)]
+pub struct ServiceAccessToken {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+}
+
+/// Json web token payload of internal service
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ServiceTokenPayload {
+    pub client_id: String,
+    pub role: ServiceRole,
+    pub exp: i64,
+    pub aud: String,
+    pub typ: String,
+}
+
+impl ServiceTokenPayload {
+    pub fn new(client_id: String, role: ServiceRole, exp: i64) -> Self {
+        Self {
+            client_id,
+            role,
+            exp,
+            aud: SERVICE_TOKEN_AUDIENCE.to_string(),
+            typ: SERVICE_TOKEN_TYPE.to_string(),
+        }
+    }
+
+    /// Checks that the token was issued to internal service, not to user
+    pub fn is_service_token(&self) -> bool {
+        self.aud == SERVICE_TOKEN_AUDIENCE && self.typ == SERVICE_TOKEN_TYPE
+    }
+}
+
+/// Internal service, that has made the request
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServicePrincipal {
+    pub client_id: String,
+    pub role: ServiceRole,
+}
+
+impl From<ServiceTokenPayload> for ServicePrincipal {
+    fn from(payload: ServiceTokenPayload) -> Self {
+        Self {
+            client_id: payload.client_id,
+            role: payload.role,
+        }
+    }
+}
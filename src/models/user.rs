@@ -2,14 +2,16 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use chrono::NaiveDate;
 use regex::Regex;
+use serde_json;
+use sha2::{Digest, Sha256};
 use validator::{Validate, ValidationError};
 
 use stq_static_resources::Gender;
-use stq_types::{Alpha3, EmarsysId, UserId};
+use stq_types::{Alpha3, EmarsysId, UserId, UsersRole};
 
 use models::NewIdentity;
 use schema::users;
@@ -30,7 +32,8 @@ pub fn validate_phone(phone: &str) -> Result<(), ValidationError> {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Queryable, QueryableByName, Clone, PartialEq)]
+#[table_name = "users"]
 pub struct User {
     pub id: UserId,
     pub email: String,
@@ -55,6 +58,45 @@ pub struct User {
     pub country: Option<Alpha3>,
     pub referer: Option<String>,
     pub revoke_before: SystemTime,
+    /// Incremented on every change of user roles, so that tokens with stale roles can be detected
+    pub roles_version: i32,
+    /// End of temporary block, `None` if the user is not blocked or blocked permanently
+    pub blocked_until: Option<SystemTime>,
+    /// Time of the last revocation of user tokens, tokens issued before it are rejected
+    pub tokens_revoked_at: Option<SystemTime>,
+}
+
+impl User {
+    /// Opaque entity tag of current user state. Bookkeeping fields, that change on
+    /// sign in and revocation of tokens, are left out, so the tag changes only on edits.
+    pub fn entity_tag(&self) -> String {
+        let mut state = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = state.as_object_mut() {
+            for field in &["last_login_at", "updated_at", "revoke_before", "tokens_revoked_at"] {
+                fields.remove(*field);
+            }
+        }
+        let mut hasher = Sha256::default();
+        hasher.input(state.to_string().as_bytes());
+        hasher.result().iter().take(16).map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Tells if token issued at `iat` and expiring at `exp` has been revoked. Tokens issued before
+    /// revocation are rejected whatever their lifetime is. Tokens without `iat` and revocations made
+    /// before `tokens_revoked_at` was recorded fall back to comparing `exp` with `revoke_before`.
+    pub fn is_token_revoked(&self, iat: Option<i64>, exp: i64) -> bool {
+        match (self.tokens_revoked_at, iat) {
+            (Some(tokens_revoked_at), Some(iat)) => {
+                // token issued within the second of revocation is the one issued in place of revoked ones
+                let tokens_revoked_at = tokens_revoked_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+                iat < tokens_revoked_at.as_secs() as i64
+            }
+            _ => {
+                let revoke_before = self.revoke_before.duration_since(UNIX_EPOCH).unwrap_or_default();
+                exp < revoke_before.as_secs() as i64
+            }
+        }
+    }
 }
 
 /// Payload for creating users
@@ -133,13 +175,48 @@ impl From<NewIdentity> for NewUser {
 }
 
 /// Payload for searching for user
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct UsersSearchTerms {
     pub email: Option<String>,
     pub phone: Option<String>,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub is_blocked: Option<bool>,
+    pub created_at: Option<TimeRange>,
+    pub last_login_at: Option<TimeRange>,
+    pub email_verified: Option<bool>,
+    pub phone_verified: Option<bool>,
+    pub is_active: Option<bool>,
+    pub country: Option<Vec<Alpha3>>,
+    pub gender: Option<Gender>,
+    pub birthdate: Option<DateRange>,
+    pub age: Option<AgeRange>,
+    pub referal: Option<UserId>,
+    /// Users having specific role
+    pub role: Option<UsersRole>,
+    /// Users whose utm marks contain all of these key/value pairs
+    pub utm_marks: Option<HashMap<String, String>>,
+}
+
+/// Range of time, both bounds are optional and inclusive
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub from: Option<SystemTime>,
+    pub to: Option<SystemTime>,
+}
+
+/// Range of dates, both bounds are optional and inclusive
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Range of full years of age, both bounds are optional and inclusive
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AgeRange {
+    pub min: Option<u32>,
+    pub max: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -147,3 +224,19 @@ pub struct UserSearchResults {
     pub total_count: u32,
     pub users: Vec<User>,
 }
+
+/// Payload for fetching users by ids
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UsersBatchRequest {
+    #[validate(length(min = "1", max = "100", message = "Batch should contain between 1 and 100 ids"))]
+    pub ids: Vec<UserId>,
+}
+
+/// Users found by ids, ids of users which were not found or are not
+/// allowed to be read are reported separately
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UsersBatchResults {
+    pub users: HashMap<UserId, User>,
+    pub missing: Vec<UserId>,
+    pub forbidden: Vec<UserId>,
+}
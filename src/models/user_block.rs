@@ -0,0 +1,73 @@
+//! Models for history of users blocks
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use serde_json;
+use uuid::Uuid;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use stq_types::UserId;
+
+use schema::user_blocks;
+
+/// Block of specific user, active until it is lifted or until `blocked_until`
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct UserBlock {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub reason: Option<String>,
+    pub blocked_by: Option<UserId>,
+    /// End of temporary block, `None` for permanent one
+    pub blocked_until: Option<SystemTime>,
+    /// User, that has lifted the block, `None` if it has expired or is still active
+    pub unblocked_by: Option<UserId>,
+    pub unblocked_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+}
+
+impl UserBlock {
+    /// Checks that block has been neither lifted nor expired by the time
+    pub fn is_active_at(&self, at: SystemTime) -> bool {
+        self.unblocked_at.is_none() && self.blocked_until.map(|until| until > at).unwrap_or(true)
+    }
+
+    /// `email.blocked` error with reason and end of the block in params
+    pub fn validation_errors(&self) -> ValidationErrors {
+        let mut params = HashMap::new();
+        params.insert(Cow::from("reason"), serde_json::to_value(&self.reason).unwrap_or_default());
+        params.insert(
+            Cow::from("blocked_until"),
+            serde_json::to_value(self.blocked_until.map(|until| DateTime::<Utc>::from(until).to_rfc3339())).unwrap_or_default(),
+        );
+
+        let mut errors = ValidationErrors::new();
+        errors.add(
+            "email",
+            ValidationError {
+                code: Cow::from("blocked"),
+                message: Some(Cow::from("Email is blocked")),
+                params,
+            },
+        );
+        errors
+    }
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "user_blocks"]
+pub struct NewUserBlock {
+    pub user_id: UserId,
+    pub reason: Option<String>,
+    pub blocked_by: Option<UserId>,
+    pub blocked_until: Option<SystemTime>,
+}
+
+/// Payload for blocking user, block without `blocked_until` is permanent
+#[derive(Clone, Debug, Default, Deserialize, Validate)]
+pub struct BlockUser {
+    #[validate(length(min = "1", max = "1000", message = "Reason must be between 1 and 1000 symbols"))]
+    pub reason: Option<String>,
+    pub blocked_until: Option<DateTime<Utc>>,
+}
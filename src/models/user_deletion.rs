@@ -0,0 +1,50 @@
+//! Models for deletion of accounts requested by users themselves
+use std::time::SystemTime;
+
+use base64::encode;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use schema::user_deletions;
+
+/// Deletion of account, the user can cancel it by the token until `delete_after`
+#[derive(Clone, Debug, Serialize, Queryable, QueryableByName)]
+#[table_name = "user_deletions"]
+pub struct UserDeletion {
+    pub user_id: UserId,
+    pub token: String,
+    pub requested_at: SystemTime,
+    pub delete_after: SystemTime,
+    pub completed_at: Option<SystemTime>,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "user_deletions"]
+pub struct NewUserDeletion {
+    pub user_id: UserId,
+    pub token: String,
+    pub delete_after: SystemTime,
+}
+
+impl NewUserDeletion {
+    pub fn new(user_id: UserId, delete_after: SystemTime) -> Self {
+        Self {
+            user_id,
+            token: encode(&Uuid::new_v4().to_string()),
+            delete_after,
+        }
+    }
+}
+
+/// Payload for deleting own account, password is not required right after sign-in
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserDeletionRequest {
+    pub password: Option<String>,
+}
+
+/// Payload for cancelling deletion of own account
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserDeletionCancel {
+    pub token: String,
+}
@@ -0,0 +1,249 @@
+//! Models for exporting users search results as CSV or JSON Lines
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use failure::Error as FailureError;
+use serde_json;
+
+use models::{User, UsersSearchTerms};
+
+/// Format of users export and import files
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsersFileFormat {
+    Csv,
+    Jsonl,
+}
+
+impl Default for UsersFileFormat {
+    fn default() -> Self {
+        UsersFileFormat::Csv
+    }
+}
+
+impl FromStr for UsersFileFormat {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(UsersFileFormat::Csv),
+            "jsonl" => Ok(UsersFileFormat::Jsonl),
+            _ => Err(format_err!("Unknown export format {}", s)),
+        }
+    }
+}
+
+impl UsersFileFormat {
+    pub fn content_type(&self) -> &'static str {
+        match *self {
+            UsersFileFormat::Csv => "text/csv; charset=utf-8",
+            UsersFileFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            UsersFileFormat::Csv => "csv",
+            UsersFileFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// User fields available for export
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsersExportColumn {
+    Id,
+    Email,
+    EmailVerified,
+    Phone,
+    PhoneVerified,
+    IsActive,
+    FirstName,
+    LastName,
+    MiddleName,
+    Gender,
+    Birthdate,
+    LastLoginAt,
+    CreatedAt,
+    UpdatedAt,
+    Avatar,
+    IsBlocked,
+    EmarsysId,
+    Referal,
+    UtmMarks,
+    Country,
+    Referer,
+}
+
+impl UsersExportColumn {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            UsersExportColumn::Id => "id",
+            UsersExportColumn::Email => "email",
+            UsersExportColumn::EmailVerified => "email_verified",
+            UsersExportColumn::Phone => "phone",
+            UsersExportColumn::PhoneVerified => "phone_verified",
+            UsersExportColumn::IsActive => "is_active",
+            UsersExportColumn::FirstName => "first_name",
+            UsersExportColumn::LastName => "last_name",
+            UsersExportColumn::MiddleName => "middle_name",
+            UsersExportColumn::Gender => "gender",
+            UsersExportColumn::Birthdate => "birthdate",
+            UsersExportColumn::LastLoginAt => "last_login_at",
+            UsersExportColumn::CreatedAt => "created_at",
+            UsersExportColumn::UpdatedAt => "updated_at",
+            UsersExportColumn::Avatar => "avatar",
+            UsersExportColumn::IsBlocked => "is_blocked",
+            UsersExportColumn::EmarsysId => "emarsys_id",
+            UsersExportColumn::Referal => "referal",
+            UsersExportColumn::UtmMarks => "utm_marks",
+            UsersExportColumn::Country => "country",
+            UsersExportColumn::Referer => "referer",
+        }
+    }
+
+    /// Value of the field for specific user, timestamps are formatted as RFC 3339
+    pub fn value(&self, user: &User) -> serde_json::Value {
+        let value = match *self {
+            UsersExportColumn::Id => serde_json::to_value(user.id),
+            UsersExportColumn::Email => serde_json::to_value(&user.email),
+            UsersExportColumn::EmailVerified => serde_json::to_value(user.email_verified),
+            UsersExportColumn::Phone => serde_json::to_value(&user.phone),
+            UsersExportColumn::PhoneVerified => serde_json::to_value(user.phone_verified),
+            UsersExportColumn::IsActive => serde_json::to_value(user.is_active),
+            UsersExportColumn::FirstName => serde_json::to_value(&user.first_name),
+            UsersExportColumn::LastName => serde_json::to_value(&user.last_name),
+            UsersExportColumn::MiddleName => serde_json::to_value(&user.middle_name),
+            UsersExportColumn::Gender => serde_json::to_value(&user.gender),
+            UsersExportColumn::Birthdate => serde_json::to_value(&user.birthdate),
+            UsersExportColumn::LastLoginAt => serde_json::to_value(rfc3339(user.last_login_at)),
+            UsersExportColumn::CreatedAt => serde_json::to_value(rfc3339(user.created_at)),
+            UsersExportColumn::UpdatedAt => serde_json::to_value(rfc3339(user.updated_at)),
+            UsersExportColumn::Avatar => serde_json::to_value(&user.avatar),
+            UsersExportColumn::IsBlocked => serde_json::to_value(user.is_blocked),
+            UsersExportColumn::EmarsysId => serde_json::to_value(&user.emarsys_id),
+            UsersExportColumn::Referal => serde_json::to_value(&user.referal),
+            UsersExportColumn::UtmMarks => serde_json::to_value(&user.utm_marks),
+            UsersExportColumn::Country => serde_json::to_value(&user.country),
+            UsersExportColumn::Referer => serde_json::to_value(&user.referer),
+        };
+        value.unwrap_or(serde_json::Value::Null)
+    }
+}
+
+impl FromStr for UsersExportColumn {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.to_string())).map_err(|_| format_err!("Unknown export column {}", s))
+    }
+}
+
+/// Comma separated list of exported columns
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UsersExportColumns(pub Vec<UsersExportColumn>);
+
+impl Default for UsersExportColumns {
+    fn default() -> Self {
+        UsersExportColumns(vec![
+            UsersExportColumn::Id,
+            UsersExportColumn::Email,
+            UsersExportColumn::FirstName,
+            UsersExportColumn::LastName,
+            UsersExportColumn::Phone,
+            UsersExportColumn::CreatedAt,
+        ])
+    }
+}
+
+impl FromStr for UsersExportColumns {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let columns = s
+            .split(',')
+            .filter(|column| !column.is_empty())
+            .map(UsersExportColumn::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if columns.is_empty() {
+            return Err(format_err!("Export columns must not be empty"));
+        }
+        Ok(UsersExportColumns(columns))
+    }
+}
+
+/// Parameters of users export
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct UsersExport {
+    pub format: UsersFileFormat,
+    pub columns: UsersExportColumns,
+}
+
+impl UsersExport {
+    /// First line of export, only CSV has one
+    pub fn header(&self) -> Option<String> {
+        match self.format {
+            UsersFileFormat::Csv => Some(csv_line(self.columns.0.iter().map(|column| column.as_str().to_string()))),
+            UsersFileFormat::Jsonl => None,
+        }
+    }
+
+    /// Line of export for specific user, including trailing newline
+    pub fn row(&self, user: &User) -> String {
+        match self.format {
+            UsersFileFormat::Csv => csv_line(self.columns.0.iter().map(|column| match column.value(user) {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(s) => escape_formula(s),
+                value => value.to_string(),
+            })),
+            UsersFileFormat::Jsonl => {
+                let object = self
+                    .columns
+                    .0
+                    .iter()
+                    .map(|column| (column.as_str().to_string(), column.value(user)))
+                    .collect::<serde_json::Map<_, _>>();
+                format!("{}\n", serde_json::Value::Object(object))
+            }
+        }
+    }
+}
+
+/// Payload of audit log entry recorded for every started export
+#[derive(Debug, Serialize)]
+pub struct UsersExportAudit<'a> {
+    pub terms: &'a UsersSearchTerms,
+    pub format: UsersFileFormat,
+    pub columns: &'a UsersExportColumns,
+    pub rows: usize,
+    pub completed: bool,
+}
+
+fn csv_line<I: Iterator<Item = String>>(fields: I) -> String {
+    let line = fields.map(|field| csv_field(&field)).collect::<Vec<_>>().join(",");
+    format!("{}\r\n", line)
+}
+
+/// Quotes field if it contains separators, quotes or line breaks
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Prefixes text, that spreadsheets would evaluate as a formula, with a quote
+fn escape_formula(field: String) -> String {
+    if field.starts_with(|c| c == '=' || c == '+' || c == '-' || c == '@') {
+        format!("'{}", field)
+    } else {
+        field
+    }
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
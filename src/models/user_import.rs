@@ -0,0 +1,100 @@
+//! Models for bulk import of users from CSV or JSON Lines files
+use validator::ValidationErrors;
+
+use stq_types::UserId;
+
+use models::UsersFileFormat;
+
+/// Parameters of users import
+#[derive(Clone, Debug)]
+pub struct UsersImport {
+    pub format: UsersFileFormat,
+    /// Only validate rows without creating users
+    pub dry_run: bool,
+    /// Number of rows created in one transaction
+    pub batch_size: usize,
+    /// Issue email verification tokens for created users, tokens are not included in the report
+    pub email_verification: bool,
+}
+
+impl Default for UsersImport {
+    fn default() -> Self {
+        Self {
+            format: UsersFileFormat::default(),
+            dry_run: false,
+            batch_size: 100,
+            email_verification: false,
+        }
+    }
+}
+
+/// Status of imported row
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsersImportRowStatus {
+    /// Row is valid, but user was not created because of dry run
+    Valid,
+    /// Row is invalid and was skipped
+    Invalid,
+    /// User was created
+    Created,
+    /// Row is valid, but user creation failed
+    Failed,
+}
+
+/// Result of importing specific row, rows are numbered from 1 not counting CSV header
+#[derive(Clone, Debug, Serialize)]
+pub struct UsersImportRowResult {
+    pub row: usize,
+    pub email: Option<String>,
+    pub status: UsersImportRowStatus,
+    pub user_id: Option<UserId>,
+    pub errors: Option<ValidationErrors>,
+}
+
+impl UsersImportRowResult {
+    pub fn new(row: usize, email: Option<String>, status: UsersImportRowStatus) -> Self {
+        Self {
+            row,
+            email,
+            status,
+            user_id: None,
+            errors: None,
+        }
+    }
+}
+
+/// Report of users import
+#[derive(Clone, Debug, Serialize)]
+pub struct UsersImportReport {
+    pub dry_run: bool,
+    pub total: usize,
+    pub valid: usize,
+    pub invalid: usize,
+    pub created: usize,
+    pub failed: usize,
+    pub rows: Vec<UsersImportRowResult>,
+}
+
+impl UsersImportReport {
+    pub fn new(dry_run: bool, rows: Vec<UsersImportRowResult>) -> Self {
+        let (invalid, created, failed) = {
+            let count = |status| rows.iter().filter(|row| row.status == status).count();
+            (
+                count(UsersImportRowStatus::Invalid),
+                count(UsersImportRowStatus::Created),
+                count(UsersImportRowStatus::Failed),
+            )
+        };
+
+        Self {
+            dry_run,
+            total: rows.len(),
+            valid: rows.len() - invalid,
+            invalid,
+            created,
+            failed,
+            rows,
+        }
+    }
+}
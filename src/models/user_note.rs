@@ -0,0 +1,34 @@
+//! Models for internal notes of support staff on users accounts
+use std::time::SystemTime;
+
+use uuid::Uuid;
+use validator::Validate;
+
+use stq_types::UserId;
+
+use schema::user_notes;
+
+/// Note on specific user, visible only to moderators and superusers
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct UserNote {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub author_id: Option<UserId>,
+    pub text: String,
+    pub created_at: SystemTime,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "user_notes"]
+pub struct NewUserNote {
+    pub user_id: UserId,
+    pub author_id: Option<UserId>,
+    pub text: String,
+}
+
+/// Payload for creating note on user
+#[derive(Clone, Debug, Deserialize, Validate)]
+pub struct NewUserNotePayload {
+    #[validate(length(min = "1", max = "10000", message = "Note must be between 1 and 10000 symbols"))]
+    pub text: String,
+}
@@ -0,0 +1,164 @@
+//! Models for cursor based pagination of users search
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use chrono::NaiveDateTime;
+use failure::Error as FailureError;
+use failure::Fail;
+use serde_json;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::User;
+
+/// Fields users search can be sorted by
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsersSortField {
+    Id,
+    CreatedAt,
+    LastLoginAt,
+    Email,
+    Name,
+}
+
+impl Default for UsersSortField {
+    fn default() -> Self {
+        UsersSortField::Id
+    }
+}
+
+impl FromStr for UsersSortField {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(UsersSortField::Id),
+            "created_at" => Ok(UsersSortField::CreatedAt),
+            "last_login_at" => Ok(UsersSortField::LastLoginAt),
+            "email" => Ok(UsersSortField::Email),
+            "name" => Ok(UsersSortField::Name),
+            _ => Err(format_err!("Unknown sort field {}", s)),
+        }
+    }
+}
+
+/// Sort direction
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl Default for SortDirection {
+    fn default() -> Self {
+        SortDirection::Asc
+    }
+}
+
+impl FromStr for SortDirection {
+    type Err = FailureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(SortDirection::Asc),
+            "desc" => Ok(SortDirection::Desc),
+            _ => Err(format_err!("Unknown sort direction {}", s)),
+        }
+    }
+}
+
+/// Position in users search, points to the last user of the previous page
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UsersCursor {
+    pub sort: UsersSortField,
+    pub direction: SortDirection,
+    pub value: serde_json::Value,
+    pub id: UserId,
+}
+
+impl UsersCursor {
+    /// Creates cursor pointing to specific user
+    pub fn new(sort: UsersSortField, direction: SortDirection, user: &User) -> Self {
+        let value = match sort {
+            UsersSortField::Id => serde_json::Value::Null,
+            UsersSortField::CreatedAt => time_value(user.created_at),
+            UsersSortField::LastLoginAt => time_value(user.last_login_at),
+            UsersSortField::Email => serde_json::Value::String(user.email.clone()),
+            UsersSortField::Name => serde_json::Value::String(full_name(user)),
+        };
+
+        Self {
+            sort,
+            direction,
+            value,
+            id: user.id,
+        }
+    }
+
+    /// Returns cursor value for timestamp fields
+    pub fn time(&self) -> Result<NaiveDateTime, FailureError> {
+        serde_json::from_value(self.value.clone()).map_err(|e| e.context(Error::Parse).into())
+    }
+
+    /// Returns cursor value for text fields
+    pub fn text(&self) -> Result<String, FailureError> {
+        serde_json::from_value(self.value.clone()).map_err(|e| e.context(Error::Parse).into())
+    }
+
+    /// Encodes cursor to opaque url safe string
+    pub fn encode(&self) -> String {
+        encode_config(&serde_json::to_vec(self).unwrap_or_default(), URL_SAFE_NO_PAD)
+    }
+
+    /// Decodes cursor from opaque string
+    pub fn decode(cursor: &str) -> Result<Self, FailureError> {
+        decode_config(cursor, URL_SAFE_NO_PAD)
+            .map_err(FailureError::from)
+            .and_then(|bytes| serde_json::from_slice(&bytes).map_err(FailureError::from))
+            .map_err(|e| e.context(format!("Invalid cursor {}", cursor)).context(Error::Parse).into())
+    }
+}
+
+/// Page of users search. If cursor is present, sort field and direction are taken from it
+#[derive(Clone, Debug, Default)]
+pub struct UsersSearchPage {
+    pub count: i64,
+    pub sort: UsersSortField,
+    pub direction: SortDirection,
+    pub cursor: Option<UsersCursor>,
+    pub with_total_count: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UsersSearchPageResults {
+    pub users: Vec<User>,
+    pub next_cursor: Option<String>,
+    pub total_count: Option<u32>,
+}
+
+/// User found by fuzzy search along with its best trigram similarity to the query
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UserFuzzyMatch {
+    pub user: User,
+    pub similarity: f32,
+}
+
+/// Name users are sorted by, matches `concat_ws(' ', first_name, last_name)` in db
+pub fn full_name(user: &User) -> String {
+    user.first_name
+        .iter()
+        .chain(user.last_name.iter())
+        .cloned()
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn time_value(time: SystemTime) -> serde_json::Value {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let naive = NaiveDateTime::from_timestamp(duration.as_secs() as i64, duration.subsec_nanos());
+    serde_json::to_value(naive).unwrap_or_default()
+}
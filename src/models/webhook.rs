@@ -0,0 +1,163 @@
+//! Models for security events webhooks
+use std::fmt;
+use std::io::Write;
+use std::time::SystemTime;
+
+use chrono::Utc;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::VarChar;
+use serde_json;
+use uuid::Uuid;
+use validator::Validate;
+
+use stq_types::UserId;
+
+use schema::{webhook_deliveries, webhooks};
+
+/// Security events delivered to webhook subscribers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, FromSqlRow, AsExpression)]
+#[sql_type = "VarChar"]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityEventType {
+    PasswordChanged,
+    PasswordReset,
+    IdentityLinked,
+    TokensRevoked,
+    AccountBlocked,
+    SuspiciousLogin,
+    AccountDeletionRequested,
+    AccountDeletionCancelled,
+    AccountDeleted,
+    AccountReactivated,
+}
+
+impl SecurityEventType {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SecurityEventType::PasswordChanged => "password_changed",
+            SecurityEventType::PasswordReset => "password_reset",
+            SecurityEventType::IdentityLinked => "identity_linked",
+            SecurityEventType::TokensRevoked => "tokens_revoked",
+            SecurityEventType::AccountBlocked => "account_blocked",
+            SecurityEventType::SuspiciousLogin => "suspicious_login",
+            SecurityEventType::AccountDeletionRequested => "account_deletion_requested",
+            SecurityEventType::AccountDeletionCancelled => "account_deletion_cancelled",
+            SecurityEventType::AccountDeleted => "account_deleted",
+            SecurityEventType::AccountReactivated => "account_reactivated",
+        }
+    }
+}
+
+impl fmt::Display for SecurityEventType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql<VarChar, Pg> for SecurityEventType {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<VarChar, Pg> for SecurityEventType {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"password_changed" => Ok(SecurityEventType::PasswordChanged),
+            b"password_reset" => Ok(SecurityEventType::PasswordReset),
+            b"identity_linked" => Ok(SecurityEventType::IdentityLinked),
+            b"tokens_revoked" => Ok(SecurityEventType::TokensRevoked),
+            b"account_blocked" => Ok(SecurityEventType::AccountBlocked),
+            b"suspicious_login" => Ok(SecurityEventType::SuspiciousLogin),
+            b"account_deletion_requested" => Ok(SecurityEventType::AccountDeletionRequested),
+            b"account_deletion_cancelled" => Ok(SecurityEventType::AccountDeletionCancelled),
+            b"account_deleted" => Ok(SecurityEventType::AccountDeleted),
+            b"account_reactivated" => Ok(SecurityEventType::AccountReactivated),
+            _ => Err("Unrecognized security event type".into()),
+        }
+    }
+}
+
+/// Webhook subscribed to security events
+#[derive(Clone, Debug, Serialize, Queryable)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub event_types: Vec<SecurityEventType>,
+    pub is_active: bool,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+/// Payload for creating webhooks
+#[derive(Clone, Deserialize, Insertable, Validate)]
+#[table_name = "webhooks"]
+pub struct NewWebhook {
+    #[validate(url(code = "not_valid", message = "Invalid url format"))]
+    pub url: String,
+    #[validate(length(min = "16", message = "Secret should be at least 16 symbols"))]
+    pub secret: String,
+    pub event_types: Vec<SecurityEventType>,
+}
+
+impl fmt::Debug for NewWebhook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "NewWebhook {{ url: \"{}\", secret: \"******\", event_types: {:?} }}",
+            self.url, self.event_types
+        )
+    }
+}
+
+/// Delivery of security event to specific webhook
+#[derive(Clone, Debug, Serialize, Queryable, QueryableByName)]
+#[table_name = "webhook_deliveries"]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    pub event_type: SecurityEventType,
+    pub payload: serde_json::Value,
+    pub response_code: Option<i32>,
+    pub error: Option<String>,
+    pub succeeded: bool,
+    pub attempts: i32,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+    pub pending: bool,
+}
+
+#[derive(Clone, Debug, Insertable)]
+#[table_name = "webhook_deliveries"]
+pub struct NewWebhookDelivery {
+    pub webhook_id: Uuid,
+    pub event_type: SecurityEventType,
+    pub payload: serde_json::Value,
+}
+
+/// Security event body sent to webhooks
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SecurityEvent {
+    pub id: Uuid,
+    pub event_type: SecurityEventType,
+    pub user_id: UserId,
+    pub occurred_at: i64,
+    pub data: Option<serde_json::Value>,
+}
+
+impl SecurityEvent {
+    pub fn new(event_type: SecurityEventType, user_id: UserId, data: Option<serde_json::Value>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type,
+            user_id,
+            occurred_at: Utc::now().timestamp(),
+            data,
+        }
+    }
+}
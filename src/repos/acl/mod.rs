@@ -18,6 +18,7 @@ use stq_types::{UserId, UsersRole};
 
 use super::legacy_acl::{Acl, CheckScope};
 use models::authorization::*;
+use models::{ApiTokenScope, ServiceRole};
 
 pub fn check<T>(
     acl: &Acl<Resource, Action, Scope, FailureError, T>,
@@ -55,8 +56,18 @@ impl ApplicationAcl {
                 permission!(Resource::Users, Action::Create),
                 permission!(Resource::Users, Action::Block),
                 permission!(Resource::Users, Action::Delete),
+                permission!(Resource::Users, Action::DeleteBySaga),
                 permission!(Resource::Users, Action::Update),
+                permission!(Resource::Users, Action::Export),
                 permission!(Resource::UserRoles),
+                permission!(Resource::Webhooks),
+                permission!(Resource::AuditLog),
+                permission!(Resource::Referrals),
+                permission!(Resource::LoginEvents),
+                permission!(Resource::UserBlocks),
+                permission!(Resource::UserNotes),
+                permission!(Resource::ServiceClients),
+                permission!(Resource::ApiTokens),
             ],
         );
         hash.insert(
@@ -65,6 +76,9 @@ impl ApplicationAcl {
                 permission!(Resource::Users, Action::Read, Scope::Owned),
                 permission!(Resource::Users, Action::Update, Scope::Owned),
                 permission!(Resource::UserRoles, Action::Read, Scope::Owned),
+                permission!(Resource::Referrals, Action::Read, Scope::Owned),
+                permission!(Resource::LoginEvents, Action::Read, Scope::Owned),
+                permission!(Resource::ApiTokens, Action::All, Scope::Owned),
             ],
         );
         hash.insert(
@@ -73,6 +87,8 @@ impl ApplicationAcl {
                 permission!(Resource::Users, Action::Read),
                 permission!(Resource::Users, Action::Block),
                 permission!(Resource::UserRoles, Action::Read),
+                permission!(Resource::UserBlocks),
+                permission!(Resource::UserNotes),
             ],
         );
 
@@ -111,13 +127,91 @@ impl<T> Acl<Resource, Action, Scope, FailureError, T> for ApplicationAcl {
     }
 }
 
+/// ScopedAcl restricts permissions of the user to scopes of API token, the request is authenticated with.
+/// Action is allowed only if both the token and roles of the user allow it.
+pub struct ScopedAcl<T> {
+    inner: Box<Acl<Resource, Action, Scope, FailureError, T>>,
+    scopes: Vec<ApiTokenScope>,
+}
+
+impl<T> ScopedAcl<T> {
+    pub fn new(inner: Box<Acl<Resource, Action, Scope, FailureError, T>>, scopes: Vec<ApiTokenScope>) -> Self {
+        ScopedAcl { inner, scopes }
+    }
+}
+
+impl<T> Acl<Resource, Action, Scope, FailureError, T> for ScopedAcl<T> {
+    fn allows(
+        &self,
+        resource: Resource,
+        action: Action,
+        scope_checker: &CheckScope<Scope, T>,
+        obj: Option<&T>,
+    ) -> Result<bool, FailureError> {
+        if self.scopes.iter().any(|scope| scope.allows(resource, action)) {
+            self.inner.allows(resource, action, scope_checker, obj)
+        } else {
+            error!("Denied request with API token to do {} on {}.", action, resource);
+            Ok(false)
+        }
+    }
+}
+
+/// ServiceAcl contains permissions of internal services, authenticated with client credentials.
+/// Services act on their own behalf, so permissions with `Owned` scope are never granted to them.
+#[derive(Clone)]
+pub struct ServiceAcl {
+    acls: Rc<HashMap<ServiceRole, Vec<Permission>>>,
+    role: ServiceRole,
+}
+
+impl ServiceAcl {
+    pub fn new(role: ServiceRole) -> Self {
+        let mut hash = ::std::collections::HashMap::new();
+        hash.insert(ServiceRole::Saga, vec![permission!(Resource::Users, Action::DeleteBySaga)]);
+        hash.insert(ServiceRole::Orders, vec![permission!(Resource::Users, Action::Read)]);
+        hash.insert(ServiceRole::Stores, vec![permission!(Resource::Users, Action::Read)]);
+
+        ServiceAcl { acls: Rc::new(hash), role }
+    }
+}
+
+impl<T> Acl<Resource, Action, Scope, FailureError, T> for ServiceAcl {
+    fn allows(
+        &self,
+        resource: Resource,
+        action: Action,
+        _scope_checker: &CheckScope<Scope, T>,
+        _obj: Option<&T>,
+    ) -> Result<bool, FailureError> {
+        let allowed = self
+            .acls
+            .get(&self.role)
+            .map(|permissions| {
+                permissions.iter().any(|permission| {
+                    (permission.resource == resource)
+                        && ((permission.action == action) || (permission.action == Action::All))
+                        && (permission.scope == Scope::All)
+                })
+            })
+            .unwrap_or(false);
+
+        if !allowed {
+            error!("Denied request from service {} to do {} on {}.", self.role, action, resource);
+        }
+        Ok(allowed)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::SystemTime;
 
+    use uuid::Uuid;
+
     use stq_types::{RoleId, UserId, UsersRole};
 
-    use repos::legacy_acl::{Acl, CheckScope};
+    use repos::legacy_acl::{Acl, CheckScope, UnauthorizedACL};
 
     use models::*;
     use repos::*;
@@ -147,6 +241,9 @@ mod tests {
             referer: None,
             utm_marks: None,
             revoke_before: SystemTime::now(),
+            roles_version: 0,
+            blocked_until: None,
+            tokens_revoked_at: None,
         }
     }
 
@@ -183,6 +280,24 @@ mod tests {
         }
     }
 
+    impl CheckScope<Scope, UserNote> for ScopeChecker {
+        fn is_in_scope(&self, user_id: UserId, scope: &Scope, obj: Option<&UserNote>) -> bool {
+            match *scope {
+                Scope::All => true,
+                Scope::Owned => obj.map(|note| note.author_id == Some(user_id)).unwrap_or(false),
+            }
+        }
+    }
+
+    impl CheckScope<Scope, UserId> for ScopeChecker {
+        fn is_in_scope(&self, user_id: UserId, scope: &Scope, obj: Option<&UserId>) -> bool {
+            match *scope {
+                Scope::All => true,
+                Scope::Owned => obj.map(|referrer| *referrer == user_id).unwrap_or(false),
+            }
+        }
+    }
+
     #[test]
     fn test_super_user_for_users() {
         let acl = ApplicationAcl::new(vec![UsersRole::Superuser], UserId(1232));
@@ -405,4 +520,155 @@ mod tests {
             "ACL does not allow read actions on all user roles for moderator."
         );
     }
+
+    #[test]
+    fn test_ordinary_user_for_referrals() {
+        let user_id = UserId(2);
+        let acl = ApplicationAcl::new(vec![UsersRole::User], user_id);
+        let s = ScopeChecker::default();
+
+        assert_eq!(
+            acl.allows(Resource::Referrals, Action::Read, &s, Some(&user_id)).unwrap(),
+            true,
+            "ACL does not allow read action on own referrals for ordinary_user."
+        );
+        assert_eq!(
+            acl.allows(Resource::Referrals, Action::Read, &s, Some(&UserId(3))).unwrap(),
+            false,
+            "ACL allows read action on referrals of another user for ordinary_user."
+        );
+        assert_eq!(
+            acl.allows(Resource::Referrals, Action::Read, &s, None::<&UserId>).unwrap(),
+            false,
+            "ACL allows read action on all referrals for ordinary_user."
+        );
+    }
+
+    #[test]
+    fn test_user_notes() {
+        let s = ScopeChecker::default();
+        let note = UserNote {
+            id: Uuid::new_v4(),
+            user_id: UserId(2),
+            author_id: Some(UserId(3)),
+            text: "Fraud suspicion".to_string(),
+            created_at: SystemTime::now(),
+        };
+
+        let acl = ApplicationAcl::new(vec![UsersRole::Moderator], UserId(3));
+        assert_eq!(
+            acl.allows(Resource::UserNotes, Action::Read, &s, Some(&note)).unwrap(),
+            true,
+            "ACL does not allow read action on user notes for moderator."
+        );
+        assert_eq!(
+            acl.allows(Resource::UserNotes, Action::Create, &s, None::<&UserNote>).unwrap(),
+            true,
+            "ACL does not allow create action on user notes for moderator."
+        );
+
+        let acl = ApplicationAcl::new(vec![UsersRole::Superuser], UserId(1));
+        assert_eq!(
+            acl.allows(Resource::UserNotes, Action::Delete, &s, Some(&note)).unwrap(),
+            true,
+            "ACL does not allow delete action on user notes for superuser."
+        );
+
+        let acl = ApplicationAcl::new(vec![UsersRole::User], UserId(2));
+        assert_eq!(
+            acl.allows(Resource::UserNotes, Action::Read, &s, Some(&note)).unwrap(),
+            false,
+            "ACL allows read action on notes about themself for ordinary_user."
+        );
+    }
+
+    #[test]
+    fn test_service_roles_for_users() {
+        let s = ScopeChecker::default();
+        let resource = create_user(UserId(2));
+
+        let acl = ServiceAcl::new(ServiceRole::Saga);
+        assert_eq!(
+            acl.allows(Resource::Users, Action::DeleteBySaga, &s, None::<&User>).unwrap(),
+            true,
+            "ACL does not allow delete_by_saga action on users for saga."
+        );
+        assert_eq!(
+            acl.allows(Resource::Users, Action::Delete, &s, Some(&resource)).unwrap(),
+            false,
+            "ACL allows delete action on users for saga."
+        );
+        assert_eq!(
+            acl.allows(Resource::Users, Action::Block, &s, Some(&resource)).unwrap(),
+            false,
+            "ACL allows block action on users for saga."
+        );
+        assert_eq!(
+            acl.allows(Resource::Users, Action::Read, &s, Some(&resource)).unwrap(),
+            false,
+            "ACL allows read action on users for saga."
+        );
+        assert_eq!(
+            acl.allows(Resource::UserRoles, Action::Create, &s, None::<&UserRole>).unwrap(),
+            false,
+            "ACL allows create action on user roles for saga."
+        );
+        assert_eq!(
+            acl.allows(Resource::Webhooks, Action::Read, &s, None::<&User>).unwrap(),
+            false,
+            "ACL allows read action on webhooks for saga."
+        );
+
+        let acl = ServiceAcl::new(ServiceRole::Orders);
+        assert_eq!(
+            acl.allows(Resource::Users, Action::Read, &s, Some(&resource)).unwrap(),
+            true,
+            "ACL does not allow read action on users for orders."
+        );
+        assert_eq!(
+            acl.allows(Resource::Users, Action::Delete, &s, None::<&User>).unwrap(),
+            false,
+            "ACL allows delete action on users for orders."
+        );
+    }
+
+    #[test]
+    fn test_anonymous_for_users() {
+        let s = ScopeChecker::default();
+        let acl = UnauthorizedACL::default();
+        assert_eq!(
+            acl.allows(Resource::Users, Action::DeleteBySaga, &s, None::<&User>).unwrap(),
+            false,
+            "ACL allows delete_by_saga action on users for anonymous."
+        );
+    }
+
+    #[test]
+    fn test_api_token_scopes() {
+        let s = ScopeChecker::default();
+        let user_id = UserId(2);
+        let resource = create_user(user_id);
+        let scopes = vec![ApiTokenScope {
+            resource: Resource::Users,
+            action: Action::Read,
+        }];
+
+        let acl = ScopedAcl::new(Box::new(ApplicationAcl::new(vec![UsersRole::User], user_id)), scopes.clone());
+        assert_eq!(
+            acl.allows(Resource::Users, Action::Read, &s, Some(&resource)).unwrap(),
+            true,
+            "ACL does not allow read action on user within token scopes."
+        );
+        assert_eq!(
+            acl.allows(Resource::Users, Action::Update, &s, Some(&resource)).unwrap(),
+            false,
+            "ACL allows update action on user out of token scopes."
+        );
+        assert_eq!(
+            acl.allows(Resource::Users, Action::Read, &s, Some(&create_user(UserId(3))))
+                .unwrap(),
+            false,
+            "ACL allows read action on other user, that is denied by role of the user."
+        );
+    }
 }
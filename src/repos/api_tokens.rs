@@ -0,0 +1,155 @@
+//! Repo for api_tokens table. API tokens are personal long-lived tokens,
+//! that users create to call APIs from scripts.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{ApiToken, NewApiToken};
+use repos::legacy_acl::*;
+use schema::api_tokens::dsl::*;
+
+/// API tokens repository
+pub trait ApiTokensRepo {
+    /// Creates new API token
+    fn create(&self, payload: NewApiToken) -> RepoResult<ApiToken>;
+
+    /// Returns API token by hash of the token
+    fn find_by_hash(&self, token_hash_arg: String) -> RepoResult<Option<ApiToken>>;
+
+    /// Returns API tokens of the user, newest first
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<ApiToken>>;
+
+    /// Revokes specific API token of the user, returns `None` if there is no such active token
+    fn revoke(&self, user_id_arg: UserId, id_arg: Uuid) -> RepoResult<Option<ApiToken>>;
+
+    /// Deletes all API tokens of the user, both active and revoked
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()>;
+}
+
+/// Implementation of ApiTokensRepo trait
+pub struct ApiTokensRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, ApiToken>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ApiTokensRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, ApiToken>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ApiTokensRepo for ApiTokensRepoImpl<'a, T> {
+    /// Creates new API token
+    fn create(&self, payload: NewApiToken) -> RepoResult<ApiToken> {
+        diesel::insert_into(api_tokens)
+            .values(&payload)
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|api_token: ApiToken| {
+                acl::check(&*self.acl, Resource::ApiTokens, Action::Create, self, Some(&api_token))?;
+                Ok(api_token)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new API token of user {} error occured", payload.user_id))
+                    .into()
+            })
+    }
+
+    /// Returns API token by hash of the token
+    fn find_by_hash(&self, token_hash_arg: String) -> RepoResult<Option<ApiToken>> {
+        api_tokens
+            .filter(token_hash.eq(token_hash_arg))
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|api_token: Option<ApiToken>| {
+                if let Some(ref api_token) = api_token {
+                    acl::check(&*self.acl, Resource::ApiTokens, Action::Read, self, Some(api_token))?;
+                }
+                Ok(api_token)
+            })
+            .map_err(|e: FailureError| e.context("Find API token by hash error occured").into())
+    }
+
+    /// Returns API tokens of the user, newest first
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<ApiToken>> {
+        api_tokens
+            .filter(user_id.eq(user_id_arg))
+            .order(created_at.desc())
+            .get_results(self.db_conn)
+            .map_err(From::from)
+            .and_then(|results: Vec<ApiToken>| {
+                for api_token in &results {
+                    acl::check(&*self.acl, Resource::ApiTokens, Action::Read, self, Some(api_token))?;
+                }
+                Ok(results)
+            })
+            .map_err(|e: FailureError| e.context(format!("List API tokens of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Revokes specific API token of the user, returns `None` if there is no such active token
+    fn revoke(&self, user_id_arg: UserId, id_arg: Uuid) -> RepoResult<Option<ApiToken>> {
+        let filtered = api_tokens
+            .filter(id.eq(id_arg))
+            .filter(user_id.eq(user_id_arg))
+            .filter(revoked_at.is_null());
+
+        filtered
+            .clone()
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|api_token: Option<ApiToken>| match api_token {
+                Some(ref api_token) => {
+                    acl::check(&*self.acl, Resource::ApiTokens, Action::Delete, self, Some(api_token))?;
+                    diesel::update(filtered)
+                        .set(revoked_at.eq(Some(SystemTime::now())))
+                        .get_result(self.db_conn)
+                        .optional()
+                        .map_err(From::from)
+                }
+                None => Ok(None),
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Revoke API token {} of user {} error occured", id_arg, user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Deletes all API tokens of the user, both active and revoked
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()> {
+        acl::check(&*self.acl, Resource::ApiTokens, Action::Delete, self, None)
+            .and_then(|_| {
+                diesel::delete(api_tokens.filter(user_id.eq(user_id_arg)))
+                    .execute(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map(|_| ())
+            .map_err(|e: FailureError| e.context(format!("Delete API tokens of user {} error occured", user_id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, ApiToken>
+    for ApiTokensRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&ApiToken>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|api_token| api_token.user_id == user_id_arg).unwrap_or(false),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Repo for audit_log table. Audit log is a record of privileged
+//! actions made by administrators.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{AuditLogEntry, NewAuditLogEntry};
+use repos::legacy_acl::*;
+use schema::audit_log::dsl::*;
+
+/// Audit log repository, responsible for recording privileged actions
+pub trait AuditLogRepo {
+    /// Records new action
+    fn create(&self, payload: NewAuditLogEntry) -> RepoResult<AuditLogEntry>;
+}
+
+/// Implementation of AuditLogRepo trait
+pub struct AuditLogRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> AuditLogRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> AuditLogRepo for AuditLogRepoImpl<'a, T> {
+    /// Records new action
+    fn create(&self, payload: NewAuditLogEntry) -> RepoResult<AuditLogEntry> {
+        acl::check(&*self.acl, Resource::AuditLog, Action::Create, self, None)
+            .and_then(|_| {
+                diesel::insert_into(audit_log)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new audit log entry {:?} error occured", payload))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, AuditLogEntry>
+    for AuditLogRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&AuditLogEntry>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|entry| entry.user_id == Some(user_id_arg)).unwrap_or(false),
+        }
+    }
+}
@@ -6,6 +6,7 @@ use diesel::prelude::*;
 use diesel::query_dsl::LoadQuery;
 use diesel::query_dsl::RunQueryDsl;
 use diesel::select;
+use diesel::sql_types::VarChar;
 use diesel::Connection;
 use failure::Error as FailureError;
 use failure::Fail;
@@ -52,6 +53,19 @@ pub trait IdentitiesRepo {
 
     // Get by user email
     fn get_by_email(&self, email_arg: String) -> RepoResult<Identity>;
+
+    /// Find identity created by specific saga
+    fn find_by_saga_id(&self, saga_id_arg: String) -> RepoResult<Option<Identity>>;
+
+    /// Find identity of specific user
+    fn find_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Option<Identity>>;
+
+    /// Locks saga id till the end of current transaction, so that concurrent retries
+    /// of the same saga are serialized and see the identity created by each other
+    fn lock_saga_id(&self, saga_id_arg: &str) -> RepoResult<()>;
+
+    /// Deletes identities of specific user, so that the user can not sign in anymore
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()>;
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> IdentitiesRepoImpl<'a, T> {
@@ -186,4 +200,44 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
                 .into()
         })
     }
+
+    /// Find identity created by specific saga
+    fn find_by_saga_id(&self, saga_id_arg: String) -> RepoResult<Option<Identity>> {
+        let query = identities.filter(saga_id.eq(&saga_id_arg));
+
+        query.first::<Identity>(self.db_conn).optional().map_err(|e| {
+            e.context(format!("Find identity by saga id {} error occurred.", saga_id_arg))
+                .into()
+        })
+    }
+
+    /// Find identity of specific user
+    fn find_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Option<Identity>> {
+        let query = identities.filter(user_id.eq(user_id_arg));
+
+        query
+            .first::<Identity>(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Find identity of user {} error occurred.", user_id_arg)).into())
+    }
+
+    /// Locks saga id till the end of current transaction, so that concurrent retries
+    /// of the same saga are serialized and see the identity created by each other
+    fn lock_saga_id(&self, saga_id_arg: &str) -> RepoResult<()> {
+        diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind::<VarChar, _>(saga_id_arg)
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Lock saga id {} error occurred.", saga_id_arg)).into())
+    }
+
+    /// Deletes identities of specific user, so that the user can not sign in anymore
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()> {
+        let filter = identities.filter(user_id.eq(user_id_arg));
+
+        diesel::delete(filter).execute(self.db_conn).map(|_| ()).map_err(|e| {
+            e.context(format!("Delete identities of user {} error occurred.", user_id_arg))
+                .into()
+        })
+    }
 }
@@ -0,0 +1,102 @@
+//! Repo for login_confirmations table. Suspicious sign-ins wait there
+//! until the user confirms them by email.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::types::RepoResult;
+use models::{LoginConfirmation, NewLoginConfirmation};
+use schema::login_confirmations::dsl::*;
+
+/// Login confirmations repository
+pub trait LoginConfirmationsRepo {
+    /// Creates confirmation of sign-in
+    fn create(&self, payload: NewLoginConfirmation) -> RepoResult<LoginConfirmation>;
+
+    /// Marks confirmation with the token as confirmed, unless it has expired or
+    /// has been already confirmed. Returns `None` if there is no such confirmation.
+    fn confirm(&self, token_arg: String) -> RepoResult<Option<LoginConfirmation>>;
+
+    /// Returns the latest confirmation of the user, that is neither expired nor confirmed
+    fn find_pending(&self, user_id_arg: UserId) -> RepoResult<Option<LoginConfirmation>>;
+
+    /// Deletes all confirmations of the user
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()>;
+}
+
+/// Implementation of LoginConfirmationsRepo trait
+pub struct LoginConfirmationsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LoginConfirmationsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LoginConfirmationsRepo
+    for LoginConfirmationsRepoImpl<'a, T>
+{
+    /// Creates confirmation of sign-in
+    fn create(&self, payload: NewLoginConfirmation) -> RepoResult<LoginConfirmation> {
+        diesel::insert_into(login_confirmations)
+            .values(&payload)
+            .get_result(self.db_conn)
+            .map_err(|e| {
+                e.context(format!("Create login confirmation for user {} error occured", payload.user_id))
+                    .into()
+            })
+    }
+
+    /// Marks confirmation with the token as confirmed, unless it has expired or
+    /// has been already confirmed. Returns `None` if there is no such confirmation.
+    fn confirm(&self, token_arg: String) -> RepoResult<Option<LoginConfirmation>> {
+        let now = SystemTime::now();
+        let filter = login_confirmations
+            .filter(token.eq(token_arg))
+            .filter(confirmed_at.is_null())
+            .filter(expires_at.gt(now));
+
+        diesel::update(filter)
+            .set(confirmed_at.eq(now))
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Confirm login error occured").into())
+    }
+
+    /// Returns the latest confirmation of the user, that is neither expired nor confirmed
+    fn find_pending(&self, user_id_arg: UserId) -> RepoResult<Option<LoginConfirmation>> {
+        login_confirmations
+            .filter(user_id.eq(user_id_arg))
+            .filter(confirmed_at.is_null())
+            .filter(expires_at.gt(SystemTime::now()))
+            .order(created_at.desc())
+            .first(self.db_conn)
+            .optional()
+            .map_err(|e| {
+                e.context(format!("Find pending login confirmation of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Deletes all confirmations of the user
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()> {
+        diesel::delete(login_confirmations.filter(user_id.eq(user_id_arg)))
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| {
+                e.context(format!("Delete login confirmations of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+}
@@ -0,0 +1,128 @@
+//! Repo for login_events table. Login events are the history of
+//! successful and failed sign-ins of users.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{LoginEvent, NewLoginEvent};
+use repos::legacy_acl::*;
+use schema::login_events::dsl::*;
+
+/// Login events repository
+pub trait LoginEventsRepo {
+    /// Records new sign-in attempt
+    fn create(&self, payload: NewLoginEvent) -> RepoResult<LoginEvent>;
+
+    /// Returns latest sign-in attempts of the user, newest first
+    fn list_for_user(&self, user_id_arg: UserId, count: i64) -> RepoResult<Vec<LoginEvent>>;
+
+    /// Returns latest successful sign-ins of the user, newest first
+    fn list_succeeded_for_user(&self, user_id_arg: UserId, count: i64) -> RepoResult<Vec<LoginEvent>>;
+
+    /// Deletes all sign-in attempts of the user
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()>;
+}
+
+/// Implementation of LoginEventsRepo trait
+pub struct LoginEventsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, LoginEvent>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LoginEventsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, LoginEvent>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> LoginEventsRepo
+    for LoginEventsRepoImpl<'a, T>
+{
+    /// Records new sign-in attempt
+    fn create(&self, payload: NewLoginEvent) -> RepoResult<LoginEvent> {
+        acl::check(&*self.acl, Resource::LoginEvents, Action::Create, self, None)
+            .and_then(|_| {
+                diesel::insert_into(login_events)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create a new login event {:?} error occured", payload)).into())
+    }
+
+    /// Returns latest sign-in attempts of the user, newest first
+    fn list_for_user(&self, user_id_arg: UserId, count: i64) -> RepoResult<Vec<LoginEvent>> {
+        let query = login_events.filter(user_id.eq(user_id_arg)).order(created_at.desc()).limit(count);
+
+        query
+            .get_results(self.db_conn)
+            .map_err(From::from)
+            .and_then(|events: Vec<LoginEvent>| {
+                for event in &events {
+                    acl::check(&*self.acl, Resource::LoginEvents, Action::Read, self, Some(event))?;
+                }
+                Ok(events)
+            })
+            .map_err(|e: FailureError| e.context(format!("List login events of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Returns latest successful sign-ins of the user, newest first
+    fn list_succeeded_for_user(&self, user_id_arg: UserId, count: i64) -> RepoResult<Vec<LoginEvent>> {
+        let query = login_events
+            .filter(user_id.eq(user_id_arg))
+            .filter(succeeded.eq(true))
+            .order(created_at.desc())
+            .limit(count);
+
+        query
+            .get_results(self.db_conn)
+            .map_err(From::from)
+            .and_then(|events: Vec<LoginEvent>| {
+                for event in &events {
+                    acl::check(&*self.acl, Resource::LoginEvents, Action::Read, self, Some(event))?;
+                }
+                Ok(events)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List successful login events of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Deletes all sign-in attempts of the user
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()> {
+        acl::check(&*self.acl, Resource::LoginEvents, Action::Delete, self, None)
+            .and_then(|_| {
+                diesel::delete(login_events.filter(user_id.eq(user_id_arg)))
+                    .execute(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map(|_| ())
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete login events of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, LoginEvent>
+    for LoginEventsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&LoginEvent>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|event| event.user_id == user_id_arg).unwrap_or(false),
+        }
+    }
+}
@@ -2,17 +2,39 @@
 
 #[macro_use]
 pub mod acl;
+pub mod api_tokens;
+pub mod audit_log;
 pub mod identities;
+pub mod login_confirmations;
+pub mod login_events;
+pub mod password_history;
+pub mod referrals;
 pub mod repo_factory;
 pub mod reset_token;
+pub mod service_clients;
 pub mod types;
+pub mod user_blocks;
+pub mod user_deletions;
+pub mod user_notes;
 pub mod user_roles;
 pub mod users;
+pub mod webhooks;
 
 pub use self::acl::*;
+pub use self::api_tokens::*;
+pub use self::audit_log::*;
 pub use self::identities::*;
+pub use self::login_confirmations::*;
+pub use self::login_events::*;
+pub use self::password_history::*;
+pub use self::referrals::*;
 pub use self::repo_factory::*;
 pub use self::reset_token::*;
+pub use self::service_clients::*;
 pub use self::types::*;
+pub use self::user_blocks::*;
+pub use self::user_deletions::*;
+pub use self::user_notes::*;
 pub use self::user_roles::*;
 pub use self::users::*;
+pub use self::webhooks::*;
@@ -0,0 +1,87 @@
+//! Repo for password_history table. Hashes of previous passwords of identities
+//! are kept there to prevent their reuse.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use super::types::RepoResult;
+use models::{NewPasswordHistoryEntry, PasswordHistoryEntry};
+use schema::password_history::dsl::*;
+
+/// Password history repository
+pub trait PasswordHistoryRepo {
+    /// Adds hash of previous password of identity
+    fn add(&self, payload: NewPasswordHistoryEntry) -> RepoResult<PasswordHistoryEntry>;
+
+    /// Returns `count` latest previous passwords of identity, latest first
+    fn list_latest(&self, user_id_arg: UserId, count: i64) -> RepoResult<Vec<PasswordHistoryEntry>>;
+
+    /// Deletes all previous passwords of identity except `count` latest ones
+    fn prune(&self, user_id_arg: UserId, count: i64) -> RepoResult<()>;
+}
+
+/// Implementation of PasswordHistoryRepo trait
+pub struct PasswordHistoryRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> PasswordHistoryRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> PasswordHistoryRepo
+    for PasswordHistoryRepoImpl<'a, T>
+{
+    /// Adds hash of previous password of identity
+    fn add(&self, payload: NewPasswordHistoryEntry) -> RepoResult<PasswordHistoryEntry> {
+        diesel::insert_into(password_history)
+            .values(&payload)
+            .get_result(self.db_conn)
+            .map_err(|e| {
+                e.context(format!("Add password history of user {} error occured", payload.user_id))
+                    .into()
+            })
+    }
+
+    /// Returns `count` latest previous passwords of identity, latest first
+    fn list_latest(&self, user_id_arg: UserId, count: i64) -> RepoResult<Vec<PasswordHistoryEntry>> {
+        password_history
+            .filter(user_id.eq(user_id_arg))
+            .order((created_at.desc(), id.desc()))
+            .limit(count)
+            .get_results(self.db_conn)
+            .map_err(|e| {
+                e.context(format!("List password history of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Deletes all previous passwords of identity except `count` latest ones
+    fn prune(&self, user_id_arg: UserId, count: i64) -> RepoResult<()> {
+        let kept_ids = password_history
+            .filter(user_id.eq(user_id_arg))
+            .order((created_at.desc(), id.desc()))
+            .limit(count)
+            .select(id)
+            .get_results::<Uuid>(self.db_conn)
+            .map_err(|e| e.context(format!("List password history of user {} error occured", user_id_arg)))?;
+
+        diesel::delete(password_history.filter(user_id.eq(user_id_arg)).filter(id.ne_all(kept_ids)))
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| {
+                e.context(format!("Prune password history of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+}
@@ -0,0 +1,149 @@
+//! Repo for referral program statistics. Referrals are users, whose
+//! `referal` column points to the user that has invited them.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::{BigInt, Integer, Nullable, Timestamp};
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{Referral, ReferralDepthCount, ReferralSignups, ReferralStats, TopReferrer};
+use repos::legacy_acl::*;
+use schema::users::dsl::*;
+
+/// Referrals repository, acl object is the id of the referrer
+pub trait ReferralsRepo {
+    /// Returns direct referrals of the user, newest first
+    fn list(&self, referrer: UserId, offset: i64, count: i64) -> RepoResult<Vec<Referral>>;
+
+    /// Returns statistics of the referrer or, if referrer is missing, of the whole program.
+    /// Referrals are counted by depth only for specific referrer, down to `max_depth` level.
+    fn stats(&self, referrer: Option<UserId>, since: SystemTime, max_depth: Option<i32>) -> RepoResult<ReferralStats>;
+
+    /// Returns users with the most referrals
+    fn top_referrers(&self, count: i64) -> RepoResult<Vec<TopReferrer>>;
+}
+
+/// Implementation of ReferralsRepo trait
+pub struct ReferralsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserId>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ReferralsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, UserId>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ReferralsRepo for ReferralsRepoImpl<'a, T> {
+    /// Returns direct referrals of the user, newest first
+    fn list(&self, referrer: UserId, offset: i64, count: i64) -> RepoResult<Vec<Referral>> {
+        let query = users
+            .select((id, first_name, last_name, email_verified, created_at))
+            .filter(referal.eq(referrer))
+            .order((created_at.desc(), id.desc()))
+            .offset(offset)
+            .limit(count);
+
+        acl::check(&*self.acl, Resource::Referrals, Action::Read, self, Some(&referrer))
+            .and_then(|_| query.get_results(self.db_conn).map_err(From::from))
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "List referrals of user {}, offset {}, count {} error occured",
+                    referrer, offset, count
+                ))
+                .into()
+            })
+    }
+
+    /// Returns statistics of the referrer or, if referrer is missing, of the whole program.
+    /// Referrals are counted by depth only for specific referrer, down to `max_depth` level.
+    fn stats(&self, referrer: Option<UserId>, since: SystemTime, max_depth: Option<i32>) -> RepoResult<ReferralStats> {
+        let referred = || {
+            let mut query = users.filter(referal.is_not_null()).into_boxed();
+            if let Some(referrer) = referrer {
+                query = query.filter(referal.eq(referrer));
+            }
+            query
+        };
+
+        acl::check(&*self.acl, Resource::Referrals, Action::Read, self, referrer.as_ref())
+            .and_then(|_| {
+                let total: i64 = referred().count().get_result(self.db_conn)?;
+                let verified: i64 = referred().filter(email_verified.eq(true)).count().get_result(self.db_conn)?;
+
+                let signups_per_day = diesel::sql_query(
+                    "SELECT created_at::date AS day, COUNT(*) AS count FROM users \
+                     WHERE referal IS NOT NULL AND ($1::integer IS NULL OR referal = $1) AND created_at >= $2 \
+                     GROUP BY day ORDER BY day",
+                )
+                .bind::<Nullable<Integer>, _>(referrer)
+                .bind::<Timestamp, _>(since)
+                .load::<ReferralSignups>(self.db_conn)?;
+
+                let by_depth = match (referrer, max_depth) {
+                    (Some(referrer), Some(max_depth)) => Some(
+                        diesel::sql_query(
+                            "WITH RECURSIVE tree (id, depth) AS ( \
+                             SELECT id, 1 FROM users WHERE referal = $1 \
+                             UNION \
+                             SELECT users.id, tree.depth + 1 FROM users JOIN tree ON users.referal = tree.id WHERE tree.depth < $2 \
+                             ) SELECT depth, COUNT(*) AS count FROM tree GROUP BY depth ORDER BY depth",
+                        )
+                        .bind::<Integer, _>(referrer)
+                        .bind::<Integer, _>(max_depth)
+                        .load::<ReferralDepthCount>(self.db_conn)?,
+                    ),
+                    _ => None,
+                };
+
+                Ok(ReferralStats {
+                    referrer,
+                    total,
+                    verified,
+                    unverified: total - verified,
+                    by_depth,
+                    signups_per_day,
+                })
+            })
+            .map_err(|e: FailureError| e.context(format!("Referral stats of user {:?} error occured", referrer)).into())
+    }
+
+    /// Returns users with the most referrals
+    fn top_referrers(&self, count: i64) -> RepoResult<Vec<TopReferrer>> {
+        let query = diesel::sql_query(
+            "SELECT referrers.id AS user_id, referrers.email, COUNT(*) AS referrals, \
+             COUNT(*) FILTER (WHERE users.email_verified) AS verified_referrals \
+             FROM users JOIN users AS referrers ON users.referal = referrers.id \
+             GROUP BY referrers.id, referrers.email ORDER BY referrals DESC, referrers.id LIMIT $1",
+        )
+        .bind::<BigInt, _>(count);
+
+        acl::check(&*self.acl, Resource::Referrals, Action::Read, self, None)
+            .and_then(|_| query.load(self.db_conn).map_err(From::from))
+            .map_err(|e: FailureError| e.context(format!("Top {} referrers error occured", count)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, UserId>
+    for ReferralsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&UserId>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|referrer| *referrer == user_id_arg).unwrap_or(false),
+        }
+    }
+}
@@ -21,6 +21,32 @@ pub trait ReposFactory<C: Connection<Backend = Pg, TransactionManager = AnsiTran
     fn create_reset_token_repo<'a>(&self, db_conn: &'a C) -> Box<ResetTokenRepo + 'a>;
     fn create_user_roles_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserRolesRepo + 'a>;
     fn create_user_roles_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserRolesRepo + 'a>;
+    fn create_webhooks_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WebhooksRepo + 'a>;
+    fn create_webhooks_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<WebhooksRepo + 'a>;
+    fn create_webhook_deliveries_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WebhookDeliveriesRepo + 'a>;
+    fn create_webhook_deliveries_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<WebhookDeliveriesRepo + 'a>;
+    fn create_audit_log_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<AuditLogRepo + 'a>;
+    fn create_audit_log_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<AuditLogRepo + 'a>;
+    fn create_referrals_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ReferralsRepo + 'a>;
+    fn create_login_events_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<LoginEventsRepo + 'a>;
+    fn create_login_events_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<LoginEventsRepo + 'a>;
+    fn create_login_confirmations_repo<'a>(&self, db_conn: &'a C) -> Box<LoginConfirmationsRepo + 'a>;
+    fn create_password_history_repo<'a>(&self, db_conn: &'a C) -> Box<PasswordHistoryRepo + 'a>;
+    fn create_user_blocks_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserBlocksRepo + 'a>;
+    fn create_user_blocks_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserBlocksRepo + 'a>;
+    fn create_user_deletions_repo<'a>(&self, db_conn: &'a C) -> Box<UserDeletionsRepo + 'a>;
+    fn create_user_notes_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserNotesRepo + 'a>;
+    fn create_user_notes_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserNotesRepo + 'a>;
+    fn create_service_clients_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ServiceClientsRepo + 'a>;
+    fn create_service_clients_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ServiceClientsRepo + 'a>;
+    /// Returns factory, whose repos check permissions of internal service with `role`
+    /// instead of permissions of the user. `None` means that request is not made by internal service.
+    fn with_service_role(&self, role: Option<ServiceRole>) -> Self;
+    fn create_api_tokens_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ApiTokensRepo + 'a>;
+    fn create_api_tokens_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ApiTokensRepo + 'a>;
+    /// Returns factory, whose repos restrict permissions of the user to scopes of API token.
+    /// `None` means that request is not authenticated with API token.
+    fn with_api_token_scopes(&self, scopes: Option<Vec<ApiTokenScope>>) -> Self;
 }
 
 pub struct ReposFactoryImpl<C1>
@@ -28,6 +54,8 @@ where
     C1: Cache<Vec<UsersRole>>,
 {
     roles_cache: Arc<RolesCacheImpl<C1>>,
+    service_role: Option<ServiceRole>,
+    api_token_scopes: Option<Vec<ApiTokenScope>>,
 }
 
 impl<C1> Clone for ReposFactoryImpl<C1>
@@ -37,6 +65,8 @@ where
     fn clone(&self) -> Self {
         Self {
             roles_cache: self.roles_cache.clone(),
+            service_role: self.service_role,
+            api_token_scopes: self.api_token_scopes.clone(),
         }
     }
 }
@@ -48,6 +78,8 @@ where
     pub fn new(roles_cache: RolesCacheImpl<C1>) -> Self {
         Self {
             roles_cache: Arc::new(roles_cache),
+            service_role: None,
+            api_token_scopes: None,
         }
     }
 
@@ -67,11 +99,18 @@ where
         db_conn: &'a C,
         user_id: Option<UserId>,
     ) -> Box<Acl<Resource, Action, Scope, FailureError, T>> {
+        if let Some(role) = self.service_role {
+            return Box::new(ServiceAcl::new(role)) as Box<Acl<Resource, Action, Scope, FailureError, T>>;
+        }
         user_id.map_or(
             Box::new(UnauthorizedACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, T>>,
             |id| {
                 let roles = self.get_roles(id, db_conn);
-                (Box::new(ApplicationAcl::new(roles, id)) as Box<Acl<Resource, Action, Scope, FailureError, T>>)
+                let acl = Box::new(ApplicationAcl::new(roles, id)) as Box<Acl<Resource, Action, Scope, FailureError, T>>;
+                match self.api_token_scopes {
+                    Some(ref scopes) => Box::new(ScopedAcl::new(acl, scopes.clone())) as Box<Acl<Resource, Action, Scope, FailureError, T>>,
+                    None => acl,
+                }
             },
         )
     }
@@ -114,6 +153,133 @@ where
         let acl = self.get_acl(db_conn, user_id);
         Box::new(UserRolesRepoImpl::new(db_conn, acl, self.roles_cache.clone())) as Box<UserRolesRepo>
     }
+
+    fn create_webhooks_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WebhooksRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(WebhooksRepoImpl::new(db_conn, acl)) as Box<WebhooksRepo>
+    }
+
+    fn create_webhooks_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<WebhooksRepo + 'a> {
+        Box::new(WebhooksRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, Webhook>>,
+        )) as Box<WebhooksRepo>
+    }
+
+    fn create_webhook_deliveries_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<WebhookDeliveriesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(WebhookDeliveriesRepoImpl::new(db_conn, acl)) as Box<WebhookDeliveriesRepo>
+    }
+
+    fn create_webhook_deliveries_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<WebhookDeliveriesRepo + 'a> {
+        Box::new(WebhookDeliveriesRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>,
+        )) as Box<WebhookDeliveriesRepo>
+    }
+
+    fn create_audit_log_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<AuditLogRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(AuditLogRepoImpl::new(db_conn, acl)) as Box<AuditLogRepo>
+    }
+
+    fn create_audit_log_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<AuditLogRepo + 'a> {
+        Box::new(AuditLogRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, AuditLogEntry>>,
+        )) as Box<AuditLogRepo>
+    }
+
+    fn create_referrals_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ReferralsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ReferralsRepoImpl::new(db_conn, acl)) as Box<ReferralsRepo>
+    }
+
+    fn create_login_events_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<LoginEventsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(LoginEventsRepoImpl::new(db_conn, acl)) as Box<LoginEventsRepo>
+    }
+
+    fn create_login_events_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<LoginEventsRepo + 'a> {
+        Box::new(LoginEventsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, LoginEvent>>,
+        )) as Box<LoginEventsRepo>
+    }
+
+    fn create_login_confirmations_repo<'a>(&self, db_conn: &'a C) -> Box<LoginConfirmationsRepo + 'a> {
+        Box::new(LoginConfirmationsRepoImpl::new(db_conn)) as Box<LoginConfirmationsRepo>
+    }
+
+    fn create_password_history_repo<'a>(&self, db_conn: &'a C) -> Box<PasswordHistoryRepo + 'a> {
+        Box::new(PasswordHistoryRepoImpl::new(db_conn)) as Box<PasswordHistoryRepo>
+    }
+
+    fn create_user_blocks_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserBlocksRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(UserBlocksRepoImpl::new(db_conn, acl)) as Box<UserBlocksRepo>
+    }
+
+    fn create_user_blocks_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserBlocksRepo + 'a> {
+        Box::new(UserBlocksRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, UserBlock>>,
+        )) as Box<UserBlocksRepo>
+    }
+
+    fn create_user_deletions_repo<'a>(&self, db_conn: &'a C) -> Box<UserDeletionsRepo + 'a> {
+        Box::new(UserDeletionsRepoImpl::new(db_conn)) as Box<UserDeletionsRepo>
+    }
+
+    fn create_user_notes_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<UserNotesRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(UserNotesRepoImpl::new(db_conn, acl)) as Box<UserNotesRepo>
+    }
+
+    fn create_user_notes_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<UserNotesRepo + 'a> {
+        Box::new(UserNotesRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, UserNote>>,
+        )) as Box<UserNotesRepo>
+    }
+
+    fn create_service_clients_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ServiceClientsRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ServiceClientsRepoImpl::new(db_conn, acl)) as Box<ServiceClientsRepo>
+    }
+
+    fn create_service_clients_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ServiceClientsRepo + 'a> {
+        Box::new(ServiceClientsRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, ServiceClient>>,
+        )) as Box<ServiceClientsRepo>
+    }
+
+    fn with_service_role(&self, role: Option<ServiceRole>) -> Self {
+        Self {
+            service_role: role,
+            ..self.clone()
+        }
+    }
+
+    fn create_api_tokens_repo<'a>(&self, db_conn: &'a C, user_id: Option<UserId>) -> Box<ApiTokensRepo + 'a> {
+        let acl = self.get_acl(db_conn, user_id);
+        Box::new(ApiTokensRepoImpl::new(db_conn, acl)) as Box<ApiTokensRepo>
+    }
+
+    fn create_api_tokens_repo_with_sys_acl<'a>(&self, db_conn: &'a C) -> Box<ApiTokensRepo + 'a> {
+        Box::new(ApiTokensRepoImpl::new(
+            db_conn,
+            Box::new(SystemACL::default()) as Box<Acl<Resource, Action, Scope, FailureError, ApiToken>>,
+        )) as Box<ApiTokensRepo>
+    }
+
+    fn with_api_token_scopes(&self, scopes: Option<Vec<ApiTokenScope>>) -> Self {
+        Self {
+            api_token_scopes: scopes,
+            ..self.clone()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -132,8 +298,6 @@ pub mod tests {
 
     use std::error::Error;
     use std::fmt;
-    use std::fs::File;
-    use std::io::prelude::*;
     use std::sync::Arc;
     use std::time::{Duration, SystemTime};
 
@@ -153,26 +317,40 @@ pub mod tests {
     use futures::Stream;
     use futures_cpupool::CpuPool;
     use r2d2::ManageConnection;
+    use serde_json;
     use sha3::{Digest, Sha3_256};
     use tokio_core::reactor::Handle;
     use uuid::Uuid;
 
     use stq_http::client::TimeLimitedHttpClient;
-    use stq_static_resources::{Provider, TokenType};
+    use stq_static_resources::Provider;
     use stq_types::{RoleId, UserId, UsersRole};
 
     use config::Config;
     use controller::context::{DynamicContext, StaticContext};
     use models::*;
+    use repos::api_tokens::ApiTokensRepo;
+    use repos::audit_log::AuditLogRepo;
     use repos::identities::IdentitiesRepo;
+    use repos::login_confirmations::LoginConfirmationsRepo;
+    use repos::login_events::LoginEventsRepo;
+    use repos::password_history::PasswordHistoryRepo;
+    use repos::referrals::ReferralsRepo;
     use repos::repo_factory::ReposFactory;
     use repos::reset_token::ResetTokenRepo;
+    use repos::service_clients::ServiceClientsRepo;
     use repos::types::RepoResult;
+    use repos::user_blocks::UserBlocksRepo;
+    use repos::user_deletions::UserDeletionsRepo;
+    use repos::user_notes::UserNotesRepo;
     use repos::user_roles::UserRolesRepo;
     use repos::users::UsersRepo;
+    use repos::webhooks::{WebhookDeliveriesRepo, WebhooksRepo};
     use services::jwt::profile::{FacebookProfile, GoogleProfile};
+    use services::jwt::signer::JwtSigner;
     use services::jwt::JWTProviderService;
     use services::mocks::jwt::JWTProviderServiceMock;
+    use services::password_policy::PasswordPolicy;
     use services::Service;
 
     #[derive(Default, Copy, Clone)]
@@ -202,6 +380,94 @@ pub mod tests {
         fn create_user_roles_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<UserRolesRepo + 'a> {
             Box::new(UserRolesRepoMock::default()) as Box<UserRolesRepo>
         }
+
+        fn create_webhooks_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<WebhooksRepo + 'a> {
+            Box::new(WebhooksRepoMock::default()) as Box<WebhooksRepo>
+        }
+
+        fn create_webhooks_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<WebhooksRepo + 'a> {
+            Box::new(WebhooksRepoMock::default()) as Box<WebhooksRepo>
+        }
+
+        fn create_webhook_deliveries_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<WebhookDeliveriesRepo + 'a> {
+            Box::new(WebhookDeliveriesRepoMock::default()) as Box<WebhookDeliveriesRepo>
+        }
+
+        fn create_webhook_deliveries_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<WebhookDeliveriesRepo + 'a> {
+            Box::new(WebhookDeliveriesRepoMock::default()) as Box<WebhookDeliveriesRepo>
+        }
+
+        fn create_audit_log_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<AuditLogRepo + 'a> {
+            Box::new(AuditLogRepoMock::default()) as Box<AuditLogRepo>
+        }
+
+        fn create_audit_log_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<AuditLogRepo + 'a> {
+            Box::new(AuditLogRepoMock::default()) as Box<AuditLogRepo>
+        }
+
+        fn create_referrals_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<ReferralsRepo + 'a> {
+            Box::new(ReferralsRepoMock::default()) as Box<ReferralsRepo>
+        }
+
+        fn create_login_events_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<LoginEventsRepo + 'a> {
+            Box::new(LoginEventsRepoMock::default()) as Box<LoginEventsRepo>
+        }
+
+        fn create_login_events_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<LoginEventsRepo + 'a> {
+            Box::new(LoginEventsRepoMock::default()) as Box<LoginEventsRepo>
+        }
+
+        fn create_login_confirmations_repo<'a>(&self, _db_conn: &'a C) -> Box<LoginConfirmationsRepo + 'a> {
+            Box::new(LoginConfirmationsRepoMock::default()) as Box<LoginConfirmationsRepo>
+        }
+
+        fn create_password_history_repo<'a>(&self, _db_conn: &'a C) -> Box<PasswordHistoryRepo + 'a> {
+            Box::new(PasswordHistoryRepoMock::default()) as Box<PasswordHistoryRepo>
+        }
+
+        fn create_user_blocks_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<UserBlocksRepo + 'a> {
+            Box::new(UserBlocksRepoMock::default()) as Box<UserBlocksRepo>
+        }
+
+        fn create_user_blocks_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<UserBlocksRepo + 'a> {
+            Box::new(UserBlocksRepoMock::default()) as Box<UserBlocksRepo>
+        }
+
+        fn create_user_deletions_repo<'a>(&self, _db_conn: &'a C) -> Box<UserDeletionsRepo + 'a> {
+            Box::new(UserDeletionsRepoMock::default()) as Box<UserDeletionsRepo>
+        }
+
+        fn create_user_notes_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<UserNotesRepo + 'a> {
+            Box::new(UserNotesRepoMock::default()) as Box<UserNotesRepo>
+        }
+
+        fn create_user_notes_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<UserNotesRepo + 'a> {
+            Box::new(UserNotesRepoMock::default()) as Box<UserNotesRepo>
+        }
+
+        fn create_service_clients_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<ServiceClientsRepo + 'a> {
+            Box::new(ServiceClientsRepoMock::default()) as Box<ServiceClientsRepo>
+        }
+
+        fn create_service_clients_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<ServiceClientsRepo + 'a> {
+            Box::new(ServiceClientsRepoMock::default()) as Box<ServiceClientsRepo>
+        }
+
+        fn with_service_role(&self, _role: Option<ServiceRole>) -> Self {
+            *self
+        }
+
+        fn create_api_tokens_repo<'a>(&self, _db_conn: &'a C, _user_id: Option<UserId>) -> Box<ApiTokensRepo + 'a> {
+            Box::new(ApiTokensRepoMock::default()) as Box<ApiTokensRepo>
+        }
+
+        fn create_api_tokens_repo_with_sys_acl<'a>(&self, _db_conn: &'a C) -> Box<ApiTokensRepo + 'a> {
+            Box::new(ApiTokensRepoMock::default()) as Box<ApiTokensRepo>
+        }
+
+        fn with_api_token_scopes(&self, _scopes: Option<Vec<ApiTokenScope>>) -> Self {
+            *self
+        }
     }
 
     #[derive(Clone, Default)]
@@ -213,15 +479,57 @@ pub mod tests {
         }
 
         fn find(&self, user_id: UserId) -> RepoResult<Option<User>> {
-            let user = create_user(user_id, MOCK_EMAIL.to_string());
+            let mut user = create_user(user_id, mock_user_email(user_id));
+            user.is_active = user_id != UserId(MOCK_DEACTIVATED_USER_ID) && user_id != UserId(MOCK_DELETED_USER_ID);
+            if user_id == UserId(MOCK_TOKENS_REVOKED_USER_ID) {
+                user.tokens_revoked_at = Some(SystemTime::now() - Duration::from_secs(600));
+            }
+            Ok(Some(user))
+        }
+
+        fn find_many(&self, user_ids: Vec<UserId>) -> RepoResult<UsersBatchResults> {
+            let mut result = UsersBatchResults::default();
+            for user_id in user_ids {
+                if user_id == UserId(MOCK_MISSING_USER_ID) {
+                    result.missing.push(user_id);
+                } else {
+                    result.users.insert(user_id, create_user(user_id, MOCK_EMAIL.to_string()));
+                }
+            }
+            Ok(result)
+        }
+
+        fn find_for_update(&self, user_id: UserId) -> RepoResult<Option<User>> {
+            let mut user = create_user(user_id, MOCK_EMAIL.to_string());
+            user.is_active = user_id != UserId(MOCK_DEACTIVATED_USER_ID) && user_id != UserId(MOCK_DELETED_USER_ID);
             Ok(Some(user))
         }
 
+        fn touch(&self, user_id: UserId) -> RepoResult<User> {
+            let user = create_user(user_id, MOCK_EMAIL.to_string());
+            Ok(user)
+        }
+
+        fn bump_roles_version(&self, user_id: UserId) -> RepoResult<User> {
+            let user = create_user(user_id, MOCK_EMAIL.to_string());
+            Ok(User { roles_version: 1, ..user })
+        }
+
+        fn set_last_login(&self, user_id: UserId, at: SystemTime) -> RepoResult<User> {
+            let user = create_user(user_id, MOCK_EMAIL.to_string());
+            Ok(User { last_login_at: at, ..user })
+        }
+
         fn email_exists(&self, email_arg: String) -> RepoResult<bool> {
             Ok(email_arg == MOCK_EMAIL.to_string())
         }
 
         fn find_by_email(&self, email_arg: String) -> RepoResult<Option<User>> {
+            if email_arg == MOCK_DEACTIVATED_EMAIL {
+                let mut user = create_user(UserId(MOCK_DEACTIVATED_USER_ID), email_arg);
+                user.is_active = false;
+                return Ok(Some(user));
+            }
             let user = create_user(UserId(1), email_arg);
             Ok(Some(user))
         }
@@ -251,6 +559,16 @@ pub mod tests {
             Ok(user)
         }
 
+        fn activate(&self, user_id: UserId) -> RepoResult<User> {
+            Ok(create_user(user_id, mock_user_email(user_id)))
+        }
+
+        fn anonymize(&self, user_id: UserId) -> RepoResult<User> {
+            let mut user = create_user(user_id, format!("deleted-{}@deleted.invalid", user_id));
+            user.is_active = false;
+            Ok(user)
+        }
+
         fn delete_by_saga_id(&self, _saga_id_arg: String) -> RepoResult<User> {
             let user = create_user(UserId(1), MOCK_EMAIL.to_string());
             Ok(user)
@@ -273,14 +591,72 @@ pub mod tests {
                 users,
             })
         }
-        fn set_block_status(&self, user_id_arg: UserId, _is_blocked_arg: bool) -> RepoResult<User> {
-            let user = create_user(user_id_arg, MOCK_EMAIL.to_string());
+
+        fn set_block_status(&self, user_id_arg: UserId, is_blocked_arg: bool, blocked_until_arg: Option<SystemTime>) -> RepoResult<User> {
+            let mut user = create_user(user_id_arg, MOCK_EMAIL.to_string());
+            user.is_blocked = is_blocked_arg;
+            user.blocked_until = blocked_until_arg;
             Ok(user)
         }
+
+        fn lift_expired_blocks(&self, _count: i64) -> RepoResult<Vec<User>> {
+            Ok(vec![])
+        }
+
+        fn search_page(&self, _term: UsersSearchTerms, page: UsersSearchPage) -> RepoResult<UsersSearchPageResults> {
+            let from_id = page.cursor.as_ref().map(|cursor| cursor.id.0 + 1).unwrap_or(2);
+            let users = (from_id..from_id + page.count as i32)
+                .map(|i| create_user(UserId(i), MOCK_EMAIL.to_string()))
+                .collect::<Vec<User>>();
+            let next_cursor = users.last().map(|user| UsersCursor::new(page.sort, page.direction, user).encode());
+            Ok(UsersSearchPageResults {
+                total_count: if page.with_total_count { Some(users.len() as u32) } else { None },
+                next_cursor,
+                users,
+            })
+        }
+
         fn fuzzy_search_by_email(&self, _term_email: String) -> RepoResult<Vec<User>> {
             let user = create_user(UserId(1), MOCK_EMAIL.to_string());
             Ok(vec![user])
         }
+
+        fn fuzzy_search(&self, _query: String, limit: i64) -> RepoResult<Vec<UserFuzzyMatch>> {
+            let matches = (1..limit as i32 + 1)
+                .map(|i| UserFuzzyMatch {
+                    user: create_user(UserId(i), MOCK_EMAIL.to_string()),
+                    similarity: 1.0 / i as f32,
+                })
+                .collect();
+            Ok(matches)
+        }
+
+        fn acquisition_stats(&self, _group_by: AcquisitionGroupBy, _period: DateRange) -> RepoResult<Vec<AcquisitionRow>> {
+            Ok(vec![
+                AcquisitionRow {
+                    value: Some("google".to_string()),
+                    signups: 2,
+                    verified: 2,
+                    active: 1,
+                },
+                AcquisitionRow {
+                    value: None,
+                    signups: 1,
+                    verified: 0,
+                    active: 1,
+                },
+            ])
+        }
+
+        fn declare_export_cursor(&self, _term: UsersSearchTerms) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn fetch_export_cursor(&self, _count: i64) -> RepoResult<Vec<User>> {
+            // single page of users, that is shorter than any page requested
+            Ok(vec![create_user(UserId(1), MOCK_EMAIL.to_string())])
+        }
+
         fn revoke_tokens(&self, _user_id_arg: UserId, _revoke_before_: SystemTime) -> RepoResult<()> {
             Ok(())
         }
@@ -291,7 +667,7 @@ pub mod tests {
 
     impl IdentitiesRepo for IdentitiesRepoMock {
         fn email_exists(&self, email_arg: String) -> RepoResult<bool> {
-            Ok(email_arg == MOCK_EMAIL.to_string())
+            Ok(email_arg == MOCK_EMAIL.to_string() || email_arg == MOCK_DEACTIVATED_EMAIL.to_string())
         }
 
         fn email_provider_exists(&self, email_arg: String, provider_arg: Provider) -> RepoResult<bool> {
@@ -351,6 +727,38 @@ pub mod tests {
             );
             Ok(ident)
         }
+
+        fn find_by_saga_id(&self, saga_id_arg: String) -> RepoResult<Option<Identity>> {
+            if saga_id_arg == MOCK_COMPLETED_SAGA_ID.to_string() {
+                Ok(Some(create_identity(
+                    MOCK_EMAIL.to_string(),
+                    Some(password_create(MOCK_PASSWORD.to_string())),
+                    UserId(1),
+                    Provider::Email,
+                    saga_id_arg,
+                )))
+            } else {
+                Ok(None)
+            }
+        }
+
+        fn find_by_user_id(&self, user_id: UserId) -> RepoResult<Option<Identity>> {
+            Ok(Some(create_identity(
+                MOCK_EMAIL.to_string(),
+                Some(password_create(MOCK_PASSWORD.to_string())),
+                user_id,
+                Provider::Email,
+                MOCK_SAGA_ID.to_string(),
+            )))
+        }
+
+        fn lock_saga_id(&self, _saga_id_arg: &str) -> RepoResult<()> {
+            Ok(())
+        }
+
+        fn delete_by_user_id(&self, _user_id_arg: UserId) -> RepoResult<()> {
+            Ok(())
+        }
     }
 
     #[derive(Clone, Default)]
@@ -358,39 +766,50 @@ pub mod tests {
 
     impl ResetTokenRepo for ResetTokenRepoMock {
         /// Create token for user
-        fn upsert(&self, _email_arg: String, _token_type_arg: TokenType, _uuid_: Option<Uuid>) -> RepoResult<ResetToken> {
-            let token = create_reset_token(MOCK_TOKEN.to_string(), MOCK_EMAIL.to_string());
+        fn upsert(&self, _email_arg: String, token_type_arg: ResetTokenType, _uuid_: Option<Uuid>) -> RepoResult<ResetToken> {
+            let token = create_reset_token(MOCK_TOKEN.to_string(), reset_token_email(token_type_arg), token_type_arg);
 
             Ok(token)
         }
 
         /// Find by token
-        fn find_by_token(&self, _token_arg: String, _token_type_arg: TokenType) -> RepoResult<ResetToken> {
-            let token = create_reset_token(MOCK_TOKEN.to_string(), MOCK_EMAIL.to_string());
+        fn find_by_token(&self, token_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken> {
+            if token_arg != MOCK_TOKEN {
+                return Err(::errors::Error::NotFound.into());
+            }
+            let token = create_reset_token(MOCK_TOKEN.to_string(), reset_token_email(token_type_arg), token_type_arg);
 
             Ok(token)
         }
 
         /// Find by email
-        fn find_by_email(&self, _email_arg: String, _token_type_arg: TokenType) -> RepoResult<Option<ResetToken>> {
-            let token = create_reset_token(MOCK_TOKEN.to_string(), MOCK_EMAIL.to_string());
+        fn find_by_email(&self, _email_arg: String, token_type_arg: ResetTokenType) -> RepoResult<Option<ResetToken>> {
+            if token_type_arg == ResetTokenType::Reactivation {
+                return Ok(None);
+            }
+            let token = create_reset_token(MOCK_TOKEN.to_string(), MOCK_EMAIL.to_string(), token_type_arg);
 
             Ok(Some(token))
         }
 
         /// Delete by token
-        fn delete_by_token(&self, _token_arg: String, _token_type_arg: TokenType) -> RepoResult<ResetToken> {
-            let token = create_reset_token(MOCK_TOKEN.to_string(), MOCK_EMAIL.to_string());
+        fn delete_by_token(&self, _token_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken> {
+            let token = create_reset_token(MOCK_TOKEN.to_string(), reset_token_email(token_type_arg), token_type_arg);
 
             Ok(token)
         }
 
         /// Delete by email
-        fn delete_by_email(&self, _email_arg: String, _token_type_arg: TokenType) -> RepoResult<ResetToken> {
-            let token = create_reset_token(MOCK_TOKEN.to_string(), MOCK_EMAIL.to_string());
+        fn delete_by_email(&self, _email_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken> {
+            let token = create_reset_token(MOCK_TOKEN.to_string(), MOCK_EMAIL.to_string(), token_type_arg);
 
             Ok(token)
         }
+
+        /// Delete tokens of all types by email
+        fn delete_all_by_email(&self, _email_arg: String) -> RepoResult<()> {
+            Ok(())
+        }
     }
 
     #[derive(Clone, Default)]
@@ -449,6 +868,537 @@ pub mod tests {
         }
     }
 
+    #[derive(Clone, Default)]
+    pub struct WebhooksRepoMock;
+
+    impl WebhooksRepo for WebhooksRepoMock {
+        fn list(&self) -> RepoResult<Vec<Webhook>> {
+            Ok(vec![])
+        }
+
+        fn list_subscribed(&self, _event_type: SecurityEventType) -> RepoResult<Vec<Webhook>> {
+            Ok(vec![])
+        }
+
+        fn find(&self, id: Uuid) -> RepoResult<Option<Webhook>> {
+            Ok(Some(create_webhook(id)))
+        }
+
+        fn create(&self, payload: NewWebhook) -> RepoResult<Webhook> {
+            Ok(Webhook {
+                url: payload.url,
+                secret: payload.secret,
+                event_types: payload.event_types,
+                ..create_webhook(Uuid::new_v4())
+            })
+        }
+
+        fn delete(&self, id: Uuid) -> RepoResult<Webhook> {
+            Ok(create_webhook(id))
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct WebhookDeliveriesRepoMock;
+
+    impl WebhookDeliveriesRepo for WebhookDeliveriesRepoMock {
+        fn list(&self, _failed_only: bool, _count: i64) -> RepoResult<Vec<WebhookDelivery>> {
+            Ok(vec![])
+        }
+
+        fn find(&self, id: Uuid) -> RepoResult<Option<WebhookDelivery>> {
+            if id.is_nil() {
+                return Ok(None);
+            }
+            Ok(Some(create_webhook_delivery(id)))
+        }
+
+        fn create(&self, payload: NewWebhookDelivery) -> RepoResult<WebhookDelivery> {
+            Ok(WebhookDelivery {
+                webhook_id: payload.webhook_id,
+                event_type: payload.event_type,
+                payload: payload.payload,
+                ..create_webhook_delivery(Uuid::new_v4())
+            })
+        }
+
+        fn claim_pending(&self, _count: i64) -> RepoResult<Vec<WebhookDelivery>> {
+            Ok(vec![])
+        }
+
+        fn record_attempt(&self, id: Uuid, response_code: Option<i32>, error: Option<String>) -> RepoResult<WebhookDelivery> {
+            Ok(WebhookDelivery {
+                response_code,
+                succeeded: error.is_none(),
+                error,
+                ..create_webhook_delivery(id)
+            })
+        }
+
+        fn requeue(&self, id: Uuid) -> RepoResult<WebhookDelivery> {
+            Ok(WebhookDelivery {
+                pending: true,
+                response_code: None,
+                error: None,
+                ..create_webhook_delivery(id)
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct AuditLogRepoMock;
+
+    impl AuditLogRepo for AuditLogRepoMock {
+        fn create(&self, payload: NewAuditLogEntry) -> RepoResult<AuditLogEntry> {
+            Ok(AuditLogEntry {
+                id: Uuid::new_v4(),
+                user_id: payload.user_id,
+                action: payload.action,
+                payload: payload.payload,
+                created_at: SystemTime::now(),
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct ReferralsRepoMock;
+
+    impl ReferralsRepo for ReferralsRepoMock {
+        fn list(&self, _referrer: UserId, offset: i64, count: i64) -> RepoResult<Vec<Referral>> {
+            Ok((offset..offset + count)
+                .map(|n| Referral {
+                    id: UserId(n as i32 + 2),
+                    first_name: None,
+                    last_name: None,
+                    email_verified: n % 2 == 0,
+                    created_at: SystemTime::now(),
+                })
+                .collect())
+        }
+
+        fn stats(&self, referrer: Option<UserId>, _since: SystemTime, max_depth: Option<i32>) -> RepoResult<ReferralStats> {
+            Ok(ReferralStats {
+                referrer,
+                total: 3,
+                verified: 2,
+                unverified: 1,
+                by_depth: referrer
+                    .and(max_depth)
+                    .map(|max_depth| (1..max_depth + 1).map(|depth| ReferralDepthCount { depth, count: 1 }).collect()),
+                signups_per_day: vec![],
+            })
+        }
+
+        fn top_referrers(&self, count: i64) -> RepoResult<Vec<TopReferrer>> {
+            Ok((0..count)
+                .map(|n| TopReferrer {
+                    user_id: UserId(n as i32 + 1),
+                    email: MOCK_EMAIL.to_string(),
+                    referrals: count - n,
+                    verified_referrals: 0,
+                })
+                .collect())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct LoginEventsRepoMock;
+
+    impl LoginEventsRepo for LoginEventsRepoMock {
+        fn create(&self, payload: NewLoginEvent) -> RepoResult<LoginEvent> {
+            Ok(LoginEvent {
+                id: Uuid::new_v4(),
+                user_id: payload.user_id,
+                provider: payload.provider,
+                ip: payload.ip,
+                user_agent: payload.user_agent,
+                succeeded: payload.succeeded,
+                failure_reason: payload.failure_reason,
+                created_at: SystemTime::now(),
+                device: payload.device,
+                country: payload.country,
+            })
+        }
+
+        fn list_for_user(&self, user_id: UserId, count: i64) -> RepoResult<Vec<LoginEvent>> {
+            Ok((0..count).map(|_| create_login_event(user_id)).collect())
+        }
+
+        fn list_succeeded_for_user(&self, user_id: UserId, count: i64) -> RepoResult<Vec<LoginEvent>> {
+            Ok((0..count).map(|_| create_login_event(user_id)).collect())
+        }
+
+        fn delete_by_user_id(&self, _user_id: UserId) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct LoginConfirmationsRepoMock;
+
+    impl LoginConfirmationsRepo for LoginConfirmationsRepoMock {
+        fn create(&self, payload: NewLoginConfirmation) -> RepoResult<LoginConfirmation> {
+            Ok(LoginConfirmation {
+                token: payload.token,
+                user_id: payload.user_id,
+                provider: payload.provider,
+                ip: payload.ip,
+                user_agent: payload.user_agent,
+                device: payload.device,
+                country: payload.country,
+                created_at: SystemTime::now(),
+                expires_at: payload.expires_at,
+                confirmed_at: None,
+            })
+        }
+
+        fn confirm(&self, token: String) -> RepoResult<Option<LoginConfirmation>> {
+            if token != MOCK_TOKEN {
+                return Ok(None);
+            }
+            Ok(Some(LoginConfirmation {
+                token,
+                user_id: UserId(1),
+                provider: Provider::Email,
+                ip: Some("127.0.0.1".to_string()),
+                user_agent: None,
+                device: None,
+                country: None,
+                created_at: SystemTime::now(),
+                expires_at: SystemTime::now() + Duration::from_secs(3600),
+                confirmed_at: Some(SystemTime::now()),
+            }))
+        }
+
+        fn find_pending(&self, user_id: UserId) -> RepoResult<Option<LoginConfirmation>> {
+            Ok(Some(LoginConfirmation {
+                token: MOCK_TOKEN.to_string(),
+                user_id,
+                provider: Provider::Email,
+                ip: Some("127.0.0.1".to_string()),
+                user_agent: None,
+                device: None,
+                country: None,
+                created_at: SystemTime::now(),
+                expires_at: SystemTime::now() + Duration::from_secs(3600),
+                confirmed_at: None,
+            }))
+        }
+
+        fn delete_by_user_id(&self, _user_id: UserId) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct UserDeletionsRepoMock;
+
+    impl UserDeletionsRepo for UserDeletionsRepoMock {
+        fn create(&self, payload: NewUserDeletion) -> RepoResult<UserDeletion> {
+            Ok(UserDeletion {
+                user_id: payload.user_id,
+                token: payload.token,
+                requested_at: SystemTime::now(),
+                delete_after: payload.delete_after,
+                completed_at: None,
+            })
+        }
+
+        fn cancel(&self, token: String) -> RepoResult<Option<UserDeletion>> {
+            if token != MOCK_TOKEN {
+                return Ok(None);
+            }
+            Ok(Some(UserDeletion {
+                user_id: UserId(1),
+                token,
+                requested_at: SystemTime::now(),
+                delete_after: SystemTime::now() + Duration::from_secs(3600),
+                completed_at: None,
+            }))
+        }
+
+        fn cancel_by_user_id(&self, user_id: UserId) -> RepoResult<Option<UserDeletion>> {
+            Ok(self
+                .find_by_user_id(user_id)?
+                .filter(|user_deletion| user_deletion.completed_at.is_none()))
+        }
+
+        fn find_by_user_id(&self, user_id: UserId) -> RepoResult<Option<UserDeletion>> {
+            // deactivated user has requested deletion, deleted user has been anonymized
+            let completed_at = if user_id == UserId(MOCK_DEACTIVATED_USER_ID) {
+                None
+            } else if user_id == UserId(MOCK_DELETED_USER_ID) {
+                Some(SystemTime::now())
+            } else {
+                return Ok(None);
+            };
+            Ok(Some(UserDeletion {
+                user_id,
+                token: MOCK_TOKEN.to_string(),
+                requested_at: SystemTime::now() - Duration::from_secs(7200),
+                delete_after: SystemTime::now() + Duration::from_secs(3600),
+                completed_at,
+            }))
+        }
+
+        fn list_due(&self, count: i64) -> RepoResult<Vec<UserDeletion>> {
+            Ok((0..count.min(2))
+                .map(|n| UserDeletion {
+                    user_id: UserId(n as i32 + 2),
+                    token: MOCK_TOKEN.to_string(),
+                    requested_at: SystemTime::now() - Duration::from_secs(7200),
+                    delete_after: SystemTime::now() - Duration::from_secs(3600),
+                    completed_at: None,
+                })
+                .collect())
+        }
+
+        fn complete(&self, user_id: UserId) -> RepoResult<UserDeletion> {
+            Ok(UserDeletion {
+                user_id,
+                token: MOCK_TOKEN.to_string(),
+                requested_at: SystemTime::now() - Duration::from_secs(7200),
+                delete_after: SystemTime::now() - Duration::from_secs(3600),
+                completed_at: Some(SystemTime::now()),
+            })
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct PasswordHistoryRepoMock;
+
+    impl PasswordHistoryRepo for PasswordHistoryRepoMock {
+        fn add(&self, payload: NewPasswordHistoryEntry) -> RepoResult<PasswordHistoryEntry> {
+            Ok(PasswordHistoryEntry {
+                id: Uuid::new_v4(),
+                user_id: payload.user_id,
+                password: payload.password,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn list_latest(&self, user_id: UserId, count: i64) -> RepoResult<Vec<PasswordHistoryEntry>> {
+            Ok((0..count)
+                .map(|n| PasswordHistoryEntry {
+                    id: Uuid::new_v4(),
+                    user_id,
+                    password: password_create(format!("{}{}", MOCK_PASSWORD, n)),
+                    created_at: SystemTime::now(),
+                })
+                .collect())
+        }
+
+        fn prune(&self, _user_id: UserId, _count: i64) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct UserBlocksRepoMock;
+
+    impl UserBlocksRepo for UserBlocksRepoMock {
+        fn create(&self, payload: NewUserBlock) -> RepoResult<UserBlock> {
+            Ok(UserBlock {
+                id: Uuid::new_v4(),
+                user_id: payload.user_id,
+                reason: payload.reason,
+                blocked_by: payload.blocked_by,
+                blocked_until: payload.blocked_until,
+                unblocked_by: None,
+                unblocked_at: None,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn find_current(&self, _user_id: UserId) -> RepoResult<Option<UserBlock>> {
+            Ok(None)
+        }
+
+        fn lift(&self, _user_id: UserId, _unblocked_by: Option<UserId>) -> RepoResult<Vec<UserBlock>> {
+            Ok(vec![])
+        }
+
+        fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<UserBlock>> {
+            Ok(vec![UserBlock {
+                id: Uuid::new_v4(),
+                user_id,
+                reason: Some("Spam".to_string()),
+                blocked_by: Some(UserId(1)),
+                blocked_until: Some(SystemTime::now() - Duration::from_secs(3600)),
+                unblocked_by: None,
+                unblocked_at: None,
+                created_at: SystemTime::now() - Duration::from_secs(7200),
+            }])
+        }
+
+        fn clear_reasons(&self, _user_id: UserId) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct ApiTokensRepoMock;
+
+    impl ApiTokensRepo for ApiTokensRepoMock {
+        fn create(&self, payload: NewApiToken) -> RepoResult<ApiToken> {
+            Ok(ApiToken {
+                id: Uuid::new_v4(),
+                user_id: payload.user_id,
+                name: payload.name,
+                token_hash: payload.token_hash,
+                scopes: payload.scopes,
+                expires_at: payload.expires_at,
+                revoked_at: None,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn find_by_hash(&self, token_hash: String) -> RepoResult<Option<ApiToken>> {
+            if token_hash != MOCK_API_TOKEN_HASH {
+                return Ok(None);
+            }
+            Ok(Some(create_api_token(UserId(1), Uuid::new_v4())))
+        }
+
+        fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<ApiToken>> {
+            Ok(vec![create_api_token(user_id, Uuid::new_v4())])
+        }
+
+        fn revoke(&self, user_id: UserId, id: Uuid) -> RepoResult<Option<ApiToken>> {
+            let mut api_token = create_api_token(user_id, id);
+            api_token.revoked_at = Some(SystemTime::now());
+            Ok(Some(api_token))
+        }
+
+        fn delete_by_user_id(&self, _user_id: UserId) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    pub fn create_api_token(user_id: UserId, id: Uuid) -> ApiToken {
+        ApiToken {
+            id,
+            user_id,
+            name: "script".to_string(),
+            token_hash: MOCK_API_TOKEN_HASH.to_string(),
+            scopes: serde_json::to_value(vec![ApiTokenScope {
+                resource: Resource::Users,
+                action: Action::Read,
+            }])
+            .unwrap(),
+            expires_at: None,
+            revoked_at: None,
+            created_at: SystemTime::now(),
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct ServiceClientsRepoMock;
+
+    impl ServiceClientsRepo for ServiceClientsRepoMock {
+        fn create(&self, payload: NewServiceClient) -> RepoResult<ServiceClient> {
+            Ok(ServiceClient {
+                client_id: payload.client_id,
+                name: payload.name,
+                secret_hash: payload.secret_hash,
+                role: payload.role,
+                is_active: true,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn find(&self, client_id: String) -> RepoResult<Option<ServiceClient>> {
+            if client_id != MOCK_CLIENT_ID {
+                return Ok(None);
+            }
+            Ok(Some(create_service_client(client_id)))
+        }
+
+        fn list(&self) -> RepoResult<Vec<ServiceClient>> {
+            Ok(vec![create_service_client(MOCK_CLIENT_ID.to_string())])
+        }
+
+        fn deactivate(&self, client_id: String) -> RepoResult<Option<ServiceClient>> {
+            Ok(self.find(client_id)?.map(|client| ServiceClient {
+                is_active: false,
+                ..client
+            }))
+        }
+    }
+
+    pub fn create_service_client(client_id: String) -> ServiceClient {
+        ServiceClient {
+            client_id,
+            name: "saga".to_string(),
+            secret_hash: password_create(MOCK_PASSWORD.to_string()),
+            role: ServiceRole::Saga,
+            is_active: true,
+            created_at: SystemTime::now(),
+        }
+    }
+
+    #[derive(Clone, Default)]
+    pub struct UserNotesRepoMock;
+
+    impl UserNotesRepo for UserNotesRepoMock {
+        fn create(&self, payload: NewUserNote) -> RepoResult<UserNote> {
+            Ok(UserNote {
+                id: Uuid::new_v4(),
+                user_id: payload.user_id,
+                author_id: payload.author_id,
+                text: payload.text,
+                created_at: SystemTime::now(),
+            })
+        }
+
+        fn list_for_user(&self, user_id: UserId) -> RepoResult<Vec<UserNote>> {
+            Ok(vec![UserNote {
+                id: Uuid::new_v4(),
+                user_id,
+                author_id: Some(UserId(1)),
+                text: "Fraud suspicion".to_string(),
+                created_at: SystemTime::now(),
+            }])
+        }
+
+        fn delete(&self, user_id: UserId, id: Uuid) -> RepoResult<Option<UserNote>> {
+            Ok(Some(UserNote {
+                id,
+                user_id,
+                author_id: Some(UserId(1)),
+                text: "Fraud suspicion".to_string(),
+                created_at: SystemTime::now(),
+            }))
+        }
+
+        fn delete_by_user_id(&self, _user_id: UserId) -> RepoResult<()> {
+            Ok(())
+        }
+    }
+
+    pub fn mock_user_email(user_id: UserId) -> String {
+        if user_id == UserId(MOCK_DEACTIVATED_USER_ID) {
+            MOCK_DEACTIVATED_EMAIL.to_string()
+        } else {
+            MOCK_EMAIL.to_string()
+        }
+    }
+
+    pub fn create_login_event(user_id: UserId) -> LoginEvent {
+        LoginEvent {
+            id: Uuid::new_v4(),
+            user_id,
+            provider: Provider::Email,
+            ip: Some("127.0.0.1".to_string()),
+            user_agent: None,
+            succeeded: true,
+            failure_reason: None,
+            created_at: SystemTime::now(),
+            device: None,
+            country: None,
+        }
+    }
+
     pub fn create_service(
         user_id: Option<UserId>,
         handle: Arc<Handle>,
@@ -462,9 +1412,8 @@ pub mod tests {
         let client_handle = client.handle();
         let client_stream = client.stream();
         handle.spawn(client_stream.for_each(|_| Ok(())));
-        let mut f = File::open(config.jwt.secret_key_path.clone()).unwrap();
-        let mut jwt_private_key: Vec<u8> = Vec::new();
-        f.read_to_end(&mut jwt_private_key).unwrap();
+        let jwt_signer = Arc::new(JwtSigner::from_config(&config.jwt).unwrap());
+        let password_policy = Arc::new(PasswordPolicy::from_config(&config.password_policy).unwrap());
         let google_provider_service: Arc<JWTProviderService<GoogleProfile>> = Arc::new(JWTProviderServiceMock);
         let facebook_provider_service: Arc<JWTProviderService<FacebookProfile>> = Arc::new(JWTProviderServiceMock);
         let static_context = StaticContext::new(
@@ -473,12 +1422,19 @@ pub mod tests {
             client_handle.clone(),
             Arc::new(config),
             MOCK_REPO_FACTORY,
-            jwt_private_key,
+            jwt_signer,
+            None,
+            password_policy,
         );
         let time_limited_http_client = TimeLimitedHttpClient::new(client_handle, Duration::new(1, 0));
         let dynamic_context = DynamicContext::new(
             user_id,
             String::default(),
+            None,
+            None,
+            None,
+            None,
+            None,
             time_limited_http_client,
             google_provider_service,
             facebook_provider_service,
@@ -512,6 +1468,9 @@ pub mod tests {
             referer: None,
             utm_marks: None,
             revoke_before: SystemTime::now(),
+            roles_version: 0,
+            blocked_until: None,
+            tokens_revoked_at: None,
         }
     }
 
@@ -553,17 +1512,53 @@ pub mod tests {
         }
     }
 
-    pub fn create_reset_token(token: String, email: String) -> ResetToken {
+    pub fn create_reset_token(token: String, email: String, token_type: ResetTokenType) -> ResetToken {
         ResetToken {
             token,
             email,
-            token_type: TokenType::EmailVerify,
+            token_type,
             uuid: uuid::Uuid::new_v4(),
             created_at: SystemTime::now(),
             updated_at: SystemTime::now(),
         }
     }
 
+    /// Only deactivated users are issued reactivation tokens
+    fn reset_token_email(token_type: ResetTokenType) -> String {
+        match token_type {
+            ResetTokenType::Reactivation => MOCK_DEACTIVATED_EMAIL.to_string(),
+            _ => MOCK_EMAIL.to_string(),
+        }
+    }
+
+    pub fn create_webhook(id: Uuid) -> Webhook {
+        Webhook {
+            id,
+            url: "http://localhost/webhook".to_string(),
+            secret: "webhook_secret_key".to_string(),
+            event_types: vec![SecurityEventType::PasswordChanged],
+            is_active: true,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        }
+    }
+
+    pub fn create_webhook_delivery(id: Uuid) -> WebhookDelivery {
+        WebhookDelivery {
+            id,
+            webhook_id: Uuid::new_v4(),
+            event_type: SecurityEventType::PasswordChanged,
+            payload: serde_json::Value::Null,
+            response_code: None,
+            error: Some("Connection refused".to_string()),
+            succeeded: false,
+            attempts: 1,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+            pending: false,
+        }
+    }
+
     pub fn password_create(clear_password: String) -> String {
         let salt = rand::random::<u64>().to_string().split_off(10);
         let pass = clear_password + &salt;
@@ -672,7 +1667,16 @@ pub mod tests {
     pub static MOCK_EMAIL: &'static str = "example@mail.com";
     pub static MOCK_PASSWORD: &'static str = "password";
     pub static MOCK_TOKEN: &'static str = "token";
+    pub static MOCK_CLIENT_ID: &'static str = "saga";
+    pub static MOCK_API_TOKEN: &'static str = "stq_mock_api_token";
+    pub static MOCK_API_TOKEN_HASH: &'static str = "eb32658e896ff0c5819433a196d80dc9b21db6746b16f54c6d1e6201fe96365d";
     pub static MOCK_SAGA_ID: &'static str = "saga_id";
+    pub static MOCK_COMPLETED_SAGA_ID: &'static str = "completed_saga_id";
+    pub static MOCK_MISSING_USER_ID: i32 = 404;
+    pub static MOCK_DEACTIVATED_USER_ID: i32 = 410;
+    pub static MOCK_DEACTIVATED_EMAIL: &'static str = "deactivated@mail.com";
+    pub static MOCK_DELETED_USER_ID: i32 = 411;
+    pub static MOCK_TOKENS_REVOKED_USER_ID: i32 = 412;
     pub static GOOGLE_TOKEN: &'static str =
         "ya29.GlxRBXyOU1dfRmFEdVE1oOK3SyQ6UKh4RTESu0J-C19N2o5RCQVEALMi5DKlgctjTQclLCrLQkUovOb05ikfYQdZ2paFja9Uf4GN1hoysgp_dDr9NLgvfo7fGth \
          Y8A";
@@ -680,5 +1684,4 @@ pub mod tests {
         "AQDr-FG4bmYyrhYGk9ZJg1liqTRBfKfRbXopSd72_Qjexg3e4ybh9EJZFErHwyhw0oKyUOEbCQSalC4D8b3B2r4eJiyEmyW-E_ESsVnyThn27j8KEDDfsxCwUJxZY6fD \
          wZt9LWMEHnHYEnFxABIupKN8y8bj_SH8wxIZoDm-YzZtYbj7VUf9g0vPKOkA_1hnjjW8TGrEKmbhFZLWLj6wJgC3uek3D3MahUhd_k3K-4BjOJNyXa8h_ESPQWNHt9sII \
          IDmhAw5X4iVmdbte7tQWf6y96vd_muwA4hKMRxzc7gMQo16tcI7hazQaJ1rJj39G8poG9Ac7AjdO6O7vSnYB9IqeLFbhKH56IyJoCR_05e2tg";
-
 }
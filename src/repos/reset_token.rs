@@ -9,10 +9,8 @@ use diesel::Connection;
 use failure::Fail;
 use uuid::Uuid;
 
-use stq_static_resources::TokenType;
-
 use super::types::RepoResult;
-use models::ResetToken;
+use models::{ResetToken, ResetTokenType};
 use schema::reset_tokens::dsl::*;
 
 /// Identities repository, responsible for handling identities
@@ -22,19 +20,22 @@ pub struct ResetTokenRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager
 
 pub trait ResetTokenRepo {
     /// Create token for user
-    fn upsert(&self, email_arg: String, token_type_arg: TokenType, uuid: Option<Uuid>) -> RepoResult<ResetToken>;
+    fn upsert(&self, email_arg: String, token_type_arg: ResetTokenType, uuid: Option<Uuid>) -> RepoResult<ResetToken>;
 
     /// Find by token
-    fn find_by_token(&self, token_arg: String, token_type_arg: TokenType) -> RepoResult<ResetToken>;
+    fn find_by_token(&self, token_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken>;
 
     /// Find by email
-    fn find_by_email(&self, email_arg: String, token_type_arg: TokenType) -> RepoResult<Option<ResetToken>>;
+    fn find_by_email(&self, email_arg: String, token_type_arg: ResetTokenType) -> RepoResult<Option<ResetToken>>;
 
     /// Delete by token
-    fn delete_by_token(&self, token_arg: String, token_type_arg: TokenType) -> RepoResult<ResetToken>;
+    fn delete_by_token(&self, token_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken>;
 
     /// Delete by email
-    fn delete_by_email(&self, email_arg: String, token_type_arg: TokenType) -> RepoResult<ResetToken>;
+    fn delete_by_email(&self, email_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken>;
+
+    /// Delete tokens of all types by email
+    fn delete_all_by_email(&self, email_arg: String) -> RepoResult<()>;
 }
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ResetTokenRepoImpl<'a, T> {
@@ -45,7 +46,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
 
 impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ResetTokenRepo for ResetTokenRepoImpl<'a, T> {
     /// Create token for user
-    fn upsert(&self, email_arg: String, token_type_arg: TokenType, uuid_: Option<Uuid>) -> RepoResult<ResetToken> {
+    fn upsert(&self, email_arg: String, token_type_arg: ResetTokenType, uuid_: Option<Uuid>) -> RepoResult<ResetToken> {
         let filtered = reset_tokens
             .filter(email.eq(email_arg.clone()))
             .filter(token_type.eq(token_type_arg.clone()));
@@ -70,7 +71,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     }
 
     /// Find by token
-    fn find_by_token(&self, token_arg: String, token_type_arg: TokenType) -> RepoResult<ResetToken> {
+    fn find_by_token(&self, token_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken> {
         let query = reset_tokens.filter(token.eq(token_arg.clone()).and(token_type.eq(token_type_arg.clone())));
 
         query.first::<ResetToken>(self.db_conn).map_err(|e| {
@@ -80,7 +81,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     }
 
     /// Find by email
-    fn find_by_email(&self, email_arg: String, token_type_arg: TokenType) -> RepoResult<Option<ResetToken>> {
+    fn find_by_email(&self, email_arg: String, token_type_arg: ResetTokenType) -> RepoResult<Option<ResetToken>> {
         let query = reset_tokens.filter(email.eq(email_arg.clone()).and(token_type.eq(token_type_arg.clone())));
 
         query.get_result(self.db_conn).optional().map_err(|e| {
@@ -90,7 +91,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     }
 
     /// Delete by token
-    fn delete_by_token(&self, token_arg: String, token_type_arg: TokenType) -> RepoResult<ResetToken> {
+    fn delete_by_token(&self, token_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken> {
         let filtered = reset_tokens.filter(token.eq(token_arg.clone()).and(token_type.eq(token_type_arg.clone())));
         let query = diesel::delete(filtered);
         query.get_result(self.db_conn).map_err(|e| {
@@ -100,7 +101,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
     }
 
     /// Delete by email
-    fn delete_by_email(&self, email_arg: String, token_type_arg: TokenType) -> RepoResult<ResetToken> {
+    fn delete_by_email(&self, email_arg: String, token_type_arg: ResetTokenType) -> RepoResult<ResetToken> {
         let filtered = reset_tokens.filter(email.eq(email_arg.clone()).and(token_type.eq(token_type_arg.clone())));
         let query = diesel::delete(filtered);
         query.get_result(self.db_conn).map_err(|e| {
@@ -108,4 +109,13 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
                 .into()
         })
     }
+
+    /// Delete tokens of all types by email
+    fn delete_all_by_email(&self, email_arg: String) -> RepoResult<()> {
+        let query = diesel::delete(reset_tokens.filter(email.eq(email_arg.clone())));
+        query
+            .execute(self.db_conn)
+            .map(|_| ())
+            .map_err(|e| e.context(format!("Delete all by email {} error occured", email_arg)).into())
+    }
 }
@@ -0,0 +1,123 @@
+//! Repo for service_clients table. Service clients are internal services,
+//! authenticated with client credentials.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewServiceClient, ServiceClient};
+use repos::legacy_acl::*;
+use schema::service_clients::dsl::*;
+
+/// Service clients repository
+pub trait ServiceClientsRepo {
+    /// Registers new service client
+    fn create(&self, payload: NewServiceClient) -> RepoResult<ServiceClient>;
+
+    /// Returns service client by its id
+    fn find(&self, client_id_arg: String) -> RepoResult<Option<ServiceClient>>;
+
+    /// Returns all service clients, newest first
+    fn list(&self) -> RepoResult<Vec<ServiceClient>>;
+
+    /// Deactivates service client, its tokens are not accepted anymore
+    fn deactivate(&self, client_id_arg: String) -> RepoResult<Option<ServiceClient>>;
+}
+
+/// Implementation of ServiceClientsRepo trait
+pub struct ServiceClientsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, ServiceClient>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ServiceClientsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, ServiceClient>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> ServiceClientsRepo
+    for ServiceClientsRepoImpl<'a, T>
+{
+    /// Registers new service client
+    fn create(&self, payload: NewServiceClient) -> RepoResult<ServiceClient> {
+        acl::check(&*self.acl, Resource::ServiceClients, Action::Create, self, None)
+            .and_then(|_| {
+                diesel::insert_into(service_clients)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new service client {} error occured", payload.name))
+                    .into()
+            })
+    }
+
+    /// Returns service client by its id
+    fn find(&self, client_id_arg: String) -> RepoResult<Option<ServiceClient>> {
+        service_clients
+            .filter(client_id.eq(client_id_arg.clone()))
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|client: Option<ServiceClient>| {
+                if let Some(ref client) = client {
+                    acl::check(&*self.acl, Resource::ServiceClients, Action::Read, self, Some(client))?;
+                }
+                Ok(client)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find service client {} error occured", client_id_arg)).into())
+    }
+
+    /// Returns all service clients, newest first
+    fn list(&self) -> RepoResult<Vec<ServiceClient>> {
+        acl::check(&*self.acl, Resource::ServiceClients, Action::Read, self, None)
+            .and_then(|_| {
+                service_clients
+                    .order(created_at.desc())
+                    .get_results(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context("List service clients error occured").into())
+    }
+
+    /// Deactivates service client, its tokens are not accepted anymore
+    fn deactivate(&self, client_id_arg: String) -> RepoResult<Option<ServiceClient>> {
+        acl::check(&*self.acl, Resource::ServiceClients, Action::Update, self, None)
+            .and_then(|_| {
+                let filtered = service_clients.filter(client_id.eq(client_id_arg.clone()));
+                diesel::update(filtered)
+                    .set(is_active.eq(false))
+                    .get_result(self.db_conn)
+                    .optional()
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Deactivate service client {} error occured", client_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, ServiceClient>
+    for ServiceClientsRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&ServiceClient>) -> bool {
+        match *scope {
+            Scope::All => true,
+            // service clients are not owned by users
+            Scope::Owned => false,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Repo for user_blocks table. User blocks are the history of
+//! permanent and temporary blocks of users.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewUserBlock, UserBlock};
+use repos::legacy_acl::*;
+use schema::user_blocks::dsl::*;
+
+/// User blocks repository
+pub trait UserBlocksRepo {
+    /// Records new block of the user
+    fn create(&self, payload: NewUserBlock) -> RepoResult<UserBlock>;
+
+    /// Returns the latest block of the user, that has not been lifted, even if it has expired
+    fn find_current(&self, user_id_arg: UserId) -> RepoResult<Option<UserBlock>>;
+
+    /// Lifts all blocks of the user, `unblocked_by_arg` is `None` for expired blocks
+    fn lift(&self, user_id_arg: UserId, unblocked_by_arg: Option<UserId>) -> RepoResult<Vec<UserBlock>>;
+
+    /// Returns all blocks of the user, newest first
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<UserBlock>>;
+
+    /// Erases reasons of all blocks of the user, keeping the blocks themselves
+    fn clear_reasons(&self, user_id_arg: UserId) -> RepoResult<()>;
+}
+
+/// Implementation of UserBlocksRepo trait
+pub struct UserBlocksRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserBlock>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserBlocksRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, UserBlock>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserBlocksRepo for UserBlocksRepoImpl<'a, T> {
+    /// Records new block of the user
+    fn create(&self, payload: NewUserBlock) -> RepoResult<UserBlock> {
+        acl::check(&*self.acl, Resource::UserBlocks, Action::Create, self, None)
+            .and_then(|_| {
+                diesel::insert_into(user_blocks)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create a new user block {:?} error occured", payload)).into())
+    }
+
+    /// Returns the latest block of the user, that has not been lifted, even if it has expired
+    fn find_current(&self, user_id_arg: UserId) -> RepoResult<Option<UserBlock>> {
+        let query = user_blocks
+            .filter(user_id.eq(user_id_arg))
+            .filter(unblocked_at.is_null())
+            .order(created_at.desc());
+
+        query
+            .first(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|block: Option<UserBlock>| {
+                if let Some(ref block) = block {
+                    acl::check(&*self.acl, Resource::UserBlocks, Action::Read, self, Some(block))?;
+                }
+                Ok(block)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Find current block of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Lifts all blocks of the user, `unblocked_by_arg` is `None` for expired blocks
+    fn lift(&self, user_id_arg: UserId, unblocked_by_arg: Option<UserId>) -> RepoResult<Vec<UserBlock>> {
+        acl::check(&*self.acl, Resource::UserBlocks, Action::Update, self, None)
+            .and_then(|_| {
+                let filter = user_blocks.filter(user_id.eq(user_id_arg)).filter(unblocked_at.is_null());
+                diesel::update(filter)
+                    .set((unblocked_by.eq(unblocked_by_arg), unblocked_at.eq(SystemTime::now())))
+                    .get_results(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Lift blocks of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Returns all blocks of the user, newest first
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<UserBlock>> {
+        let query = user_blocks.filter(user_id.eq(user_id_arg)).order(created_at.desc());
+
+        query
+            .get_results(self.db_conn)
+            .map_err(From::from)
+            .and_then(|blocks: Vec<UserBlock>| {
+                for block in &blocks {
+                    acl::check(&*self.acl, Resource::UserBlocks, Action::Read, self, Some(block))?;
+                }
+                Ok(blocks)
+            })
+            .map_err(|e: FailureError| e.context(format!("List blocks of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Erases reasons of all blocks of the user, keeping the blocks themselves
+    fn clear_reasons(&self, user_id_arg: UserId) -> RepoResult<()> {
+        acl::check(&*self.acl, Resource::UserBlocks, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(user_blocks.filter(user_id.eq(user_id_arg)))
+                    .set(reason.eq(None::<String>))
+                    .execute(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map(|_| ())
+            .map_err(|e: FailureError| {
+                e.context(format!("Clear block reasons of user {} error occured", user_id_arg))
+                    .into()
+            })
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, UserBlock>
+    for UserBlocksRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&UserBlock>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => obj.map(|block| block.user_id == user_id_arg).unwrap_or(false),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Repo for user_deletions table. Accounts wait there for anonymization
+//! until the grace period, the user can cancel deletion within, has passed.
+
+use std::time::SystemTime;
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::{BigInt, Timestamp};
+use diesel::Connection;
+use failure::Fail;
+
+use stq_types::UserId;
+
+use super::types::RepoResult;
+use models::{NewUserDeletion, UserDeletion};
+use schema::user_deletions::dsl::*;
+
+/// User deletions repository
+pub trait UserDeletionsRepo {
+    /// Creates deletion of account
+    fn create(&self, payload: NewUserDeletion) -> RepoResult<UserDeletion>;
+
+    /// Cancels deletion with the token, unless its grace period has passed.
+    /// Returns `None` if there is no such deletion.
+    fn cancel(&self, token_arg: String) -> RepoResult<Option<UserDeletion>>;
+
+    /// Cancels pending deletion of account, even if its grace period has passed,
+    /// so the job doesn't anonymize reactivated account. Returns `None` if there is no such deletion.
+    fn cancel_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Option<UserDeletion>>;
+
+    /// Returns deletion of account, pending or completed
+    fn find_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Option<UserDeletion>>;
+
+    /// Returns at most `count` deletions, whose grace period has passed, oldest first,
+    /// and locks them until the end of transaction. Rows locked by another worker are skipped.
+    fn list_due(&self, count: i64) -> RepoResult<Vec<UserDeletion>>;
+
+    /// Marks deletion as completed
+    fn complete(&self, user_id_arg: UserId) -> RepoResult<UserDeletion>;
+}
+
+/// Implementation of UserDeletionsRepo trait
+pub struct UserDeletionsRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserDeletionsRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T) -> Self {
+        Self { db_conn }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserDeletionsRepo
+    for UserDeletionsRepoImpl<'a, T>
+{
+    /// Creates deletion of account
+    fn create(&self, payload: NewUserDeletion) -> RepoResult<UserDeletion> {
+        diesel::insert_into(user_deletions)
+            .values(&payload)
+            .get_result(self.db_conn)
+            .map_err(|e| {
+                e.context(format!("Create deletion of user {} error occured", payload.user_id))
+                    .into()
+            })
+    }
+
+    /// Cancels deletion with the token, unless its grace period has passed.
+    /// Returns `None` if there is no such deletion.
+    fn cancel(&self, token_arg: String) -> RepoResult<Option<UserDeletion>> {
+        let filter = user_deletions
+            .filter(token.eq(token_arg))
+            .filter(completed_at.is_null())
+            .filter(delete_after.gt(SystemTime::now()));
+
+        diesel::delete(filter)
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context("Cancel user deletion error occured").into())
+    }
+
+    /// Cancels pending deletion of account, even if its grace period has passed,
+    /// so the job doesn't anonymize reactivated account. Returns `None` if there is no such deletion.
+    fn cancel_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Option<UserDeletion>> {
+        let filter = user_deletions.filter(user_id.eq(user_id_arg)).filter(completed_at.is_null());
+
+        diesel::delete(filter)
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Cancel deletion of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Returns deletion of account, pending or completed
+    fn find_by_user_id(&self, user_id_arg: UserId) -> RepoResult<Option<UserDeletion>> {
+        user_deletions
+            .find(user_id_arg)
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(|e| e.context(format!("Find deletion of user {} error occured", user_id_arg)).into())
+    }
+
+    /// Returns at most `count` deletions, whose grace period has passed, oldest first,
+    /// and locks them until the end of transaction. Rows locked by another worker are skipped.
+    fn list_due(&self, count: i64) -> RepoResult<Vec<UserDeletion>> {
+        diesel::sql_query(
+            "SELECT * FROM user_deletions WHERE completed_at IS NULL AND delete_after <= $1 \
+             ORDER BY delete_after LIMIT $2 FOR UPDATE SKIP LOCKED",
+        )
+        .bind::<Timestamp, _>(SystemTime::now())
+        .bind::<BigInt, _>(count)
+        .load(self.db_conn)
+        .map_err(|e| e.context("List due user deletions error occured").into())
+    }
+
+    /// Marks deletion as completed
+    fn complete(&self, user_id_arg: UserId) -> RepoResult<UserDeletion> {
+        diesel::update(user_deletions.filter(user_id.eq(user_id_arg)))
+            .set(completed_at.eq(SystemTime::now()))
+            .get_result(self.db_conn)
+            .map_err(|e| e.context(format!("Complete deletion of user {} error occured", user_id_arg)).into())
+    }
+}
@@ -0,0 +1,117 @@
+//! Repo for user_notes table. User notes are internal notes of
+//! support staff on users accounts.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewUserNote, UserNote};
+use repos::legacy_acl::*;
+use schema::user_notes::dsl::*;
+
+/// User notes repository
+pub trait UserNotesRepo {
+    /// Creates new note on the user
+    fn create(&self, payload: NewUserNote) -> RepoResult<UserNote>;
+
+    /// Returns notes on the user, newest first
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<UserNote>>;
+
+    /// Deletes specific note on the user, returns `None` if there is no such note
+    fn delete(&self, user_id_arg: UserId, id_arg: Uuid) -> RepoResult<Option<UserNote>>;
+
+    /// Deletes all notes on the user
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()>;
+}
+
+/// Implementation of UserNotesRepo trait
+pub struct UserNotesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, UserNote>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserNotesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, UserNote>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> UserNotesRepo for UserNotesRepoImpl<'a, T> {
+    /// Creates new note on the user
+    fn create(&self, payload: NewUserNote) -> RepoResult<UserNote> {
+        acl::check(&*self.acl, Resource::UserNotes, Action::Create, self, None)
+            .and_then(|_| {
+                diesel::insert_into(user_notes)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new note on user {} error occured", payload.user_id))
+                    .into()
+            })
+    }
+
+    /// Returns notes on the user, newest first
+    fn list_for_user(&self, user_id_arg: UserId) -> RepoResult<Vec<UserNote>> {
+        acl::check(&*self.acl, Resource::UserNotes, Action::Read, self, None)
+            .and_then(|_| {
+                user_notes
+                    .filter(user_id.eq(user_id_arg))
+                    .order(created_at.desc())
+                    .get_results(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("List notes on user {} error occured", user_id_arg)).into())
+    }
+
+    /// Deletes specific note on the user, returns `None` if there is no such note
+    fn delete(&self, user_id_arg: UserId, id_arg: Uuid) -> RepoResult<Option<UserNote>> {
+        acl::check(&*self.acl, Resource::UserNotes, Action::Delete, self, None)
+            .and_then(|_| {
+                diesel::delete(user_notes.filter(id.eq(id_arg)).filter(user_id.eq(user_id_arg)))
+                    .get_result(self.db_conn)
+                    .optional()
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete note {} on user {} error occured", id_arg, user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Deletes all notes on the user
+    fn delete_by_user_id(&self, user_id_arg: UserId) -> RepoResult<()> {
+        acl::check(&*self.acl, Resource::UserNotes, Action::Delete, self, None)
+            .and_then(|_| {
+                diesel::delete(user_notes.filter(user_id.eq(user_id_arg)))
+                    .execute(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map(|_| ())
+            .map_err(|e: FailureError| e.context(format!("Delete notes on user {} error occured", user_id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, UserNote>
+    for UserNotesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, user_id_arg: UserId, scope: &Scope, obj: Option<&UserNote>) -> bool {
+        match *scope {
+            Scope::All => true,
+            // notes belong to their authors, not to the users they are about
+            Scope::Owned => obj.map(|note| note.author_id == Some(user_id_arg)).unwrap_or(false),
+        }
+    }
+}
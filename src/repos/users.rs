@@ -1,14 +1,17 @@
 //! Users repo, presents CRUD operations with db for users
 use std::time::SystemTime;
 
+use chrono::naive::MIN_DATE;
+use chrono::{Datelike, NaiveDate, Utc};
 use diesel;
 use diesel::connection::AnsiTransactionManager;
 use diesel::dsl::{exists, sql};
 use diesel::pg::Pg;
 use diesel::prelude::*;
+use diesel::query_builder::{AstPass, QueryFragment, QueryId};
 use diesel::query_dsl::RunQueryDsl;
 use diesel::select;
-use diesel::sql_types::{Bool, VarChar};
+use diesel::sql_types::{BigInt, Bool, Date, Float, Integer, Jsonb, Nullable, Text, Timestamp, VarChar};
 use diesel::{Connection, PgTextExpressionMethods};
 use failure::Error as FailureError;
 use failure::Fail;
@@ -18,10 +21,16 @@ use stq_types::UserId;
 use super::acl;
 use super::types::RepoResult;
 use models::authorization::*;
-use models::{NewUser, UpdateUser, User, UserSearchResults, UsersSearchTerms};
+use models::{
+    AcquisitionGroupBy, AcquisitionRow, DateRange, NewUser, SortDirection, UpdateUser, User, UserFuzzyMatch, UserSearchResults,
+    UsersBatchResults, UsersCursor, UsersSearchPage, UsersSearchPageResults, UsersSearchTerms, UsersSortField,
+};
 use repos::legacy_acl::*;
 use schema::users::dsl::*;
 
+/// Name of cursor, that users are exported through
+const EXPORT_CURSOR: &str = "users_export";
+
 /// Users repository, responsible for handling users
 pub struct UsersRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
     pub db_conn: &'a T,
@@ -35,6 +44,21 @@ pub trait UsersRepo {
     /// Find specific user by ID
     fn find(&self, user_id: UserId) -> RepoResult<Option<User>>;
 
+    /// Find users by IDs, missing and forbidden users are reported separately
+    fn find_many(&self, user_ids: Vec<UserId>) -> RepoResult<UsersBatchResults>;
+
+    /// Find specific user by ID and lock it until the end of transaction
+    fn find_for_update(&self, user_id: UserId) -> RepoResult<Option<User>>;
+
+    /// Marks user as updated, e.g. after changing its roles
+    fn touch(&self, user_id: UserId) -> RepoResult<User>;
+
+    /// Marks user as updated after changing its roles and increments version of its roles
+    fn bump_roles_version(&self, user_id: UserId) -> RepoResult<User>;
+
+    /// Sets time of the latest successful sign-in of the user, `updated_at` is left intact by the trigger
+    fn set_last_login(&self, user_id: UserId, at: SystemTime) -> RepoResult<User>;
+
     /// Check that user with specified email already exists
     fn email_exists(&self, email_arg: String) -> RepoResult<bool>;
 
@@ -53,8 +77,17 @@ pub trait UsersRepo {
     /// Deactivates specific user
     fn deactivate(&self, user_id: UserId) -> RepoResult<User>;
 
+    /// Activates specific deactivated user
+    fn activate(&self, user_id: UserId) -> RepoResult<User>;
+
+    /// Replaces personal data of specific user with placeholders, the user stays deactivated
+    fn anonymize(&self, user_id: UserId) -> RepoResult<User>;
+
     /// Set block status of specific user
-    fn set_block_status(&self, user_id: UserId, is_blocked_arg: bool) -> RepoResult<User>;
+    fn set_block_status(&self, user_id: UserId, is_blocked_arg: bool, blocked_until_arg: Option<SystemTime>) -> RepoResult<User>;
+
+    /// Unblocks up to `count` users, whose temporary blocks have expired. Rows locked by another worker are skipped.
+    fn lift_expired_blocks(&self, count: i64) -> RepoResult<Vec<User>>;
 
     /// Deletes specific user
     fn delete_by_saga_id(&self, saga_id_arg: String) -> RepoResult<User>;
@@ -65,9 +98,25 @@ pub trait UsersRepo {
     /// Search users limited by `from`, `skip` and `count` parameters
     fn search(&self, from: Option<UserId>, skip: i64, count: i64, term: UsersSearchTerms) -> RepoResult<UserSearchResults>;
 
+    /// Search users page by page, using cursor of the previous page
+    fn search_page(&self, term: UsersSearchTerms, page: UsersSearchPage) -> RepoResult<UsersSearchPageResults>;
+
     /// Fuzzy search users by email
     fn fuzzy_search_by_email(&self, email_arg: String) -> RepoResult<Vec<User>>;
 
+    /// Fuzzy search users by email, names and phone ranked by trigram similarity
+    fn fuzzy_search(&self, query: String, limit: i64) -> RepoResult<Vec<UserFuzzyMatch>>;
+
+    /// Counts signups made in `period` grouped by signup attribute, most popular values first
+    fn acquisition_stats(&self, group_by: AcquisitionGroupBy, period: DateRange) -> RepoResult<Vec<AcquisitionRow>>;
+
+    /// Declares cursor over users matching search terms for export, ordered by id.
+    /// Cursor lives until the end of transaction, that must be already open.
+    fn declare_export_cursor(&self, term: UsersSearchTerms) -> RepoResult<()>;
+
+    /// Fetches up to `count` next users from export cursor
+    fn fetch_export_cursor(&self, count: i64) -> RepoResult<Vec<User>>;
+
     /// Revoke all tokens for user
     fn revoke_tokens(&self, user_id: UserId, revoke_before: SystemTime) -> RepoResult<()>;
 }
@@ -109,6 +158,86 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .map_err(|e: FailureError| e.context(format!("Find specific user {} error occured", user_id_arg)).into())
     }
 
+    /// Find users by IDs, missing and forbidden users are reported separately
+    fn find_many(&self, user_ids: Vec<UserId>) -> RepoResult<UsersBatchResults> {
+        let query = users.filter(id.eq_any(user_ids.clone()));
+
+        query
+            .get_results(self.db_conn)
+            .map_err(From::from)
+            .and_then(|users_res: Vec<User>| {
+                let mut result = UsersBatchResults::default();
+                for user in users_res {
+                    if self.acl.allows(Resource::Users, Action::Read, self, Some(&user))? {
+                        result.users.insert(user.id, user);
+                    } else {
+                        result.forbidden.push(user.id);
+                    }
+                }
+
+                for user_id in user_ids.iter() {
+                    let is_found = result.users.contains_key(user_id) || result.forbidden.contains(user_id);
+                    if !is_found && !result.missing.contains(user_id) {
+                        result.missing.push(*user_id);
+                    }
+                }
+
+                Ok(result)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find users by ids {:?} error occured", user_ids)).into())
+    }
+
+    /// Find specific user by ID and lock it until the end of transaction
+    fn find_for_update(&self, user_id_arg: UserId) -> RepoResult<Option<User>> {
+        let query = users.find(user_id_arg.clone()).for_update();
+
+        query
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|user: Option<User>| {
+                if let Some(ref user) = user {
+                    acl::check(&*self.acl, Resource::Users, Action::Read, self, Some(user))?;
+                };
+                Ok(user)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Find specific user {} for update error occured", user_id_arg))
+                    .into()
+            })
+    }
+
+    /// Marks user as updated, e.g. after changing its roles
+    fn touch(&self, user_id_arg: UserId) -> RepoResult<User> {
+        let filter = users.filter(id.eq(user_id_arg.clone()));
+        let query = diesel::update(filter).set(updated_at.eq(diesel::dsl::now));
+
+        query
+            .get_result(self.db_conn)
+            .map_err(|e| e.context(format!("Touch user {} error occured", user_id_arg)).into())
+    }
+
+    /// Marks user as updated after changing its roles and increments version of its roles
+    fn bump_roles_version(&self, user_id_arg: UserId) -> RepoResult<User> {
+        let filter = users.filter(id.eq(user_id_arg));
+        let query = diesel::update(filter).set((updated_at.eq(diesel::dsl::now), roles_version.eq(roles_version + 1)));
+
+        query.get_result(self.db_conn).map_err(|e| {
+            e.context(format!("Bump roles version of user {} error occured", user_id_arg))
+                .into()
+        })
+    }
+
+    /// Sets time of the latest successful sign-in of the user, `updated_at` is left intact by the trigger
+    fn set_last_login(&self, user_id_arg: UserId, at: SystemTime) -> RepoResult<User> {
+        let filter = users.filter(id.eq(user_id_arg));
+        let query = diesel::update(filter).set(last_login_at.eq(at));
+
+        acl::check(&*self.acl, Resource::Users, Action::Update, self, None)
+            .and_then(|_| query.get_result(self.db_conn).map_err(From::from))
+            .map_err(|e: FailureError| e.context(format!("Set last login of user {} error occured", user_id_arg)).into())
+    }
+
     /// Check that user with specified email already exists
     fn email_exists(&self, email_arg: String) -> RepoResult<bool> {
         let query = select(exists(users.filter(email.eq(email_arg.clone()))));
@@ -214,8 +343,58 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .map_err(|e: FailureError| e.context(format!("Deactivates user {:?} error occured", user_id_arg)).into())
     }
 
+    /// Activates specific deactivated user
+    fn activate(&self, user_id_arg: UserId) -> RepoResult<User> {
+        let query = users.find(user_id_arg.clone());
+
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|user: User| acl::check(&*self.acl, Resource::Users, Action::Delete, self, Some(&user)))
+            .and_then(|_| {
+                let filter = users.filter(id.eq(user_id_arg.clone())).filter(is_active.eq(false));
+                let query = diesel::update(filter).set((is_active.eq(true), updated_at.eq(diesel::dsl::now)));
+
+                query.get_result(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Activate user {:?} error occured", user_id_arg)).into())
+    }
+
+    /// Replaces personal data of specific user with placeholders, the user stays deactivated
+    fn anonymize(&self, user_id_arg: UserId) -> RepoResult<User> {
+        let query = users.find(user_id_arg.clone());
+
+        query
+            .get_result(self.db_conn)
+            .map_err(From::from)
+            .and_then(|user: User| acl::check(&*self.acl, Resource::Users, Action::Delete, self, Some(&user)))
+            .and_then(|_| {
+                let filter = users.filter(id.eq(user_id_arg));
+                let query = diesel::update(filter).set((
+                    email.eq(format!("deleted-{}@deleted.invalid", user_id_arg)),
+                    email_verified.eq(false),
+                    phone.eq(None::<String>),
+                    phone_verified.eq(false),
+                    is_active.eq(false),
+                    first_name.eq(None::<String>),
+                    last_name.eq(None::<String>),
+                    middle_name.eq(None::<String>),
+                    gender.eq(None::<String>),
+                    birthdate.eq(None::<NaiveDate>),
+                    avatar.eq(None::<String>),
+                    utm_marks.eq(None::<serde_json::Value>),
+                    country.eq(None::<String>),
+                    referer.eq(None::<String>),
+                    updated_at.eq(diesel::dsl::now),
+                ));
+
+                query.get_result(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Anonymize user {} error occured", user_id_arg)).into())
+    }
+
     /// Set block status of specific user
-    fn set_block_status(&self, user_id_arg: UserId, is_blocked_arg: bool) -> RepoResult<User> {
+    fn set_block_status(&self, user_id_arg: UserId, is_blocked_arg: bool, blocked_until_arg: Option<SystemTime>) -> RepoResult<User> {
         let query = users.find(user_id_arg.clone());
 
         query
@@ -224,7 +403,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .and_then(|user: User| acl::check(&*self.acl, Resource::Users, Action::Block, self, Some(&user)))
             .and_then(|_| {
                 let filter = users.filter(id.eq(user_id_arg.clone()));
-                let query = diesel::update(filter).set(is_blocked.eq(is_blocked_arg));
+                let query = diesel::update(filter).set((is_blocked.eq(is_blocked_arg), blocked_until.eq(blocked_until_arg)));
 
                 query.get_result(self.db_conn).map_err(From::from)
             })
@@ -234,14 +413,32 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             })
     }
 
+    /// Unblocks up to `count` users, whose temporary blocks have expired. Rows locked by another worker are skipped.
+    fn lift_expired_blocks(&self, count: i64) -> RepoResult<Vec<User>> {
+        let query = diesel::sql_query(
+            "UPDATE users SET is_blocked = 'f', blocked_until = NULL WHERE id IN ( \
+             SELECT id FROM users WHERE is_blocked AND blocked_until <= now() ORDER BY blocked_until LIMIT $1 FOR UPDATE SKIP LOCKED \
+             ) RETURNING *",
+        )
+        .bind::<BigInt, _>(count);
+
+        acl::check(&*self.acl, Resource::Users, Action::Block, self, None)
+            .and_then(|_| query.load(self.db_conn).map_err(From::from))
+            .map_err(|e: FailureError| e.context(format!("Lift {} expired blocks error occured", count)).into())
+    }
+
     /// Deletes specific user by saga id
     fn delete_by_saga_id(&self, saga_id_arg: String) -> RepoResult<User> {
-        let filtered = users.filter(saga_id.eq(saga_id_arg.clone()));
-        let query = diesel::delete(filtered);
-        query.get_result(self.db_conn).map_err(|e| {
-            e.context(format!("Delete specific user by saga id {:?} error occured", saga_id_arg))
-                .into()
-        })
+        acl::check(&*self.acl, Resource::Users, Action::DeleteBySaga, self, None)
+            .and_then(|_| {
+                let filtered = users.filter(saga_id.eq(saga_id_arg.clone()));
+                let query = diesel::delete(filtered);
+                query.get_result(self.db_conn).map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Delete specific user by saga id {:?} error occured", saga_id_arg))
+                    .into()
+            })
     }
 
     /// Delete user by id
@@ -300,6 +497,70 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             })
     }
 
+    /// Search users page by page, using cursor of the previous page
+    fn search_page(&self, term: UsersSearchTerms, page: UsersSearchPage) -> RepoResult<UsersSearchPageResults> {
+        let (sort, direction) = match page.cursor {
+            Some(ref cursor) => (cursor.sort, cursor.direction),
+            None => (page.sort, page.direction),
+        };
+        let count = page.count;
+
+        // hide user_id == 1
+        let mut query = users.filter(id.ne(1)).filter(by_search_terms(&term)).into_boxed();
+
+        if let Some(ref cursor) = page.cursor {
+            query = query.filter(after_cursor(cursor)?);
+        }
+
+        let order_sql = format!(
+            "{expr} {dir}, id {dir}",
+            expr = sort_expression(sort),
+            dir = match direction {
+                SortDirection::Asc => "ASC",
+                SortDirection::Desc => "DESC",
+            }
+        );
+
+        // one extra user is fetched to find out if there is a next page
+        query
+            .order(sql::<Text>(&order_sql))
+            .limit(count + 1)
+            .get_results(self.db_conn)
+            .map_err(From::from)
+            .and_then(|mut users_res: Vec<User>| {
+                for user in &users_res {
+                    acl::check(&*self.acl, Resource::Users, Action::Read, self, Some(&user))?;
+                }
+
+                let next_cursor = if users_res.len() as i64 > count {
+                    users_res.truncate(count as usize);
+                    users_res.last().map(|user| UsersCursor::new(sort, direction, user).encode())
+                } else {
+                    None
+                };
+
+                let total_count = if page.with_total_count {
+                    let total_count_query = users.filter(id.ne(1).and(by_search_terms(&term))).count();
+                    Some(total_count_query.get_result::<i64>(self.db_conn)? as u32)
+                } else {
+                    None
+                };
+
+                Ok(UsersSearchPageResults {
+                    users: users_res,
+                    next_cursor,
+                    total_count,
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!(
+                    "search page of users error occured (sort: {:?}, direction: {:?})",
+                    sort, direction
+                ))
+                .into()
+            })
+    }
+
     /// Fuzzy search users by email
     fn fuzzy_search_by_email(&self, term_email: String) -> RepoResult<Vec<User>> {
         let query = users.filter(email.like(format!("%{}%", term_email))).order(id);
@@ -315,6 +576,82 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             })
             .map_err(|e: FailureError| e.context(format!("fuzzy search for users by email error occured")).into())
     }
+
+    /// Fuzzy search users by email, names and phone ranked by trigram similarity.
+    /// Users current user is not allowed to read are skipped.
+    fn fuzzy_search(&self, query: String, limit: i64) -> RepoResult<Vec<UserFuzzyMatch>> {
+        let pattern = format!("%{}%", escape_like(&query));
+        let query_ = users
+            .select((::schema::users::all_columns, similarity_score(&query)))
+            .filter(id.ne(1))
+            .filter(fuzzy_match(&query, &pattern))
+            .order((similarity_score(&query).desc(), id))
+            .limit(limit);
+
+        // checked before the query, filtering of limited results would return less matches than there are
+        acl::check(&*self.acl, Resource::Users, Action::Read, self, None)
+            .and_then(|_| query_.get_results(self.db_conn).map_err(From::from))
+            .map(|users_res: Vec<(User, f32)>| {
+                users_res
+                    .into_iter()
+                    .map(|(user, similarity)| UserFuzzyMatch { user, similarity })
+                    .collect()
+            })
+            .map_err(|e: FailureError| e.context(format!("fuzzy search for users by {:?} error occured", query)).into())
+    }
+
+    /// Counts signups made in `period` grouped by signup attribute, most popular values first
+    fn acquisition_stats(&self, group_by: AcquisitionGroupBy, period: DateRange) -> RepoResult<Vec<AcquisitionRow>> {
+        let query = diesel::sql_query(format!(
+            "SELECT {} AS value, COUNT(*) AS signups, \
+             COUNT(*) FILTER (WHERE users.email_verified) AS verified, \
+             COUNT(*) FILTER (WHERE users.is_active) AS active \
+             FROM users LEFT JOIN identities ON identities.user_id = users.id \
+             WHERE users.id <> 1 AND ($1::date IS NULL OR users.created_at >= $1) \
+             AND ($2::date IS NULL OR users.created_at < $2 + 1) \
+             GROUP BY value ORDER BY signups DESC, value",
+            group_by.sql_expression()
+        ))
+        .bind::<Nullable<Date>, _>(period.from)
+        .bind::<Nullable<Date>, _>(period.to);
+
+        acl::check(&*self.acl, Resource::Users, Action::Read, self, None)
+            .and_then(|_| query.load(self.db_conn).map_err(From::from))
+            .map_err(|e: FailureError| {
+                e.context(format!("Acquisition stats by {:?} for {:?} error occured", group_by, period))
+                    .into()
+            })
+    }
+
+    /// Declares cursor over users matching search terms for export, ordered by id.
+    /// Cursor lives until the end of transaction, that must be already open.
+    fn declare_export_cursor(&self, term: UsersSearchTerms) -> RepoResult<()> {
+        let query = users.filter(id.ne(1)).filter(by_search_terms(&term)).into_boxed().order(id);
+
+        acl::check(&*self.acl, Resource::Users, Action::Export, self, None)
+            .and_then(|_| {
+                DeclareCursor {
+                    name: EXPORT_CURSOR,
+                    query,
+                }
+                .execute(self.db_conn)
+                .map_err(From::from)
+            })
+            .map(|_| ())
+            .map_err(|e: FailureError| {
+                e.context(format!("declare export cursor by search terms {:?} error occured", term))
+                    .into()
+            })
+    }
+
+    /// Fetches up to `count` next users from export cursor
+    fn fetch_export_cursor(&self, count: i64) -> RepoResult<Vec<User>> {
+        diesel::sql_query(format!("FETCH FORWARD {} FROM {}", count, EXPORT_CURSOR))
+            .load(self.db_conn)
+            .map_err(From::from)
+            .map_err(|e: FailureError| e.context(format!("fetch {} users from export cursor error occured", count)).into())
+    }
+
     /// Revoke all tokens for user
     fn revoke_tokens(&self, user_id_arg: UserId, revoke_before_: SystemTime) -> RepoResult<()> {
         let query = users.find(user_id_arg.clone());
@@ -325,7 +662,7 @@ impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager
             .and_then(|user: User| acl::check(&*self.acl, Resource::Users, Action::Update, self, Some(&user)))
             .and_then(|_| {
                 let filter = users.filter(id.eq(user_id_arg.clone()));
-                let query = diesel::update(filter).set(revoke_before.eq(revoke_before_));
+                let query = diesel::update(filter).set((revoke_before.eq(revoke_before_), tokens_revoked_at.eq(SystemTime::now())));
 
                 query.get_result(self.db_conn).map_err(From::from).map(|_: User| ())
             })
@@ -375,6 +712,193 @@ fn by_search_terms(term: &UsersSearchTerms) -> Box<BoxableExpression<users, Pg,
     if let Some(term_is_blocked) = term.is_blocked.clone() {
         expr = Box::new(expr.and(is_blocked.eq(term_is_blocked)));
     }
+    if let Some(ref range) = term.created_at {
+        if let Some(from) = range.from {
+            expr = Box::new(expr.and(created_at.ge(from)));
+        }
+        if let Some(to) = range.to {
+            expr = Box::new(expr.and(created_at.le(to)));
+        }
+    }
+    if let Some(ref range) = term.last_login_at {
+        if let Some(from) = range.from {
+            expr = Box::new(expr.and(last_login_at.ge(from)));
+        }
+        if let Some(to) = range.to {
+            expr = Box::new(expr.and(last_login_at.le(to)));
+        }
+    }
+    if let Some(term_email_verified) = term.email_verified {
+        expr = Box::new(expr.and(email_verified.eq(term_email_verified)));
+    }
+    if let Some(term_phone_verified) = term.phone_verified {
+        expr = Box::new(expr.and(phone_verified.eq(term_phone_verified)));
+    }
+    if let Some(term_is_active) = term.is_active {
+        expr = Box::new(expr.and(is_active.eq(term_is_active)));
+    }
+    if let Some(term_country) = term.country.clone() {
+        expr = Box::new(expr.and(country.eq_any(term_country)));
+    }
+    if let Some(term_gender) = term.gender.clone() {
+        expr = Box::new(expr.and(gender.eq(term_gender)));
+    }
+    if let Some(ref range) = term.birthdate {
+        if let Some(from) = range.from {
+            expr = Box::new(expr.and(birthdate.ge(from)));
+        }
+        if let Some(to) = range.to {
+            expr = Box::new(expr.and(birthdate.le(to)));
+        }
+    }
+    if let Some(ref range) = term.age {
+        let today = Utc::today().naive_utc();
+        if let Some(min) = range.min {
+            expr = Box::new(expr.and(birthdate.le(years_before(today, min))));
+        }
+        if let Some(max) = range.max {
+            expr = Box::new(expr.and(birthdate.gt(years_before(today, max.saturating_add(1)))));
+        }
+    }
+    if let Some(term_referal) = term.referal {
+        expr = Box::new(expr.and(referal.eq(term_referal)));
+    }
+    if let Some(term_role) = term.role.clone() {
+        let role_expr = sql("EXISTS (SELECT 1 FROM user_roles WHERE user_roles.user_id = users.id AND user_roles.name = ")
+            .bind::<VarChar, _>(term_role)
+            .sql(")");
+        expr = Box::new(expr.and(role_expr));
+    }
+    if let Some(term_utm_marks) = term.utm_marks.clone() {
+        let marks = term_utm_marks
+            .into_iter()
+            .map(|(key, value)| (key, serde_json::Value::String(value)))
+            .collect::<serde_json::Map<String, serde_json::Value>>();
+        let utm_marks_expr = sql("utm_marks @> ").bind::<Jsonb, _>(serde_json::Value::Object(marks));
+        expr = Box::new(expr.and(utm_marks_expr));
+    }
 
     expr
 }
+
+/// Returns the same day `years` years before `date`, Feb 29 becomes Feb 28
+fn years_before(date: NaiveDate, years: u32) -> NaiveDate {
+    let year = date.year().saturating_sub(years.min(i32::max_value() as u32) as i32);
+    date.with_year(year)
+        .or_else(|| NaiveDate::from_ymd_opt(year, 2, 28))
+        .unwrap_or(MIN_DATE)
+}
+
+fn sort_expression(sort: UsersSortField) -> &'static str {
+    match sort {
+        UsersSortField::Id => "id",
+        UsersSortField::CreatedAt => "created_at",
+        UsersSortField::LastLoginAt => "last_login_at",
+        UsersSortField::Email => "email",
+        // same as concat_ws(' ', first_name, last_name), but immutable, so that it can be indexed
+        UsersSortField::Name => "COALESCE(first_name || ' ' || last_name, first_name, last_name, '')",
+    }
+}
+
+/// Best trigram similarity of `query` to email, names and phone
+fn similarity_score(query: &str) -> Box<BoxableExpression<users, Pg, SqlType = Float>> {
+    Box::new(
+        sql("GREATEST(similarity(email, ")
+            .bind::<Text, _>(query.to_string())
+            .sql("), similarity(COALESCE(first_name, ''), ")
+            .bind::<Text, _>(query.to_string())
+            .sql("), similarity(COALESCE(last_name, ''), ")
+            .bind::<Text, _>(query.to_string())
+            .sql("), similarity(COALESCE(phone, ''), ")
+            .bind::<Text, _>(query.to_string())
+            .sql("))"),
+    )
+}
+
+/// Users either containing `pattern` or similar to `query`, both conditions are served by trigram indexes
+fn fuzzy_match(query: &str, pattern: &str) -> Box<BoxableExpression<users, Pg, SqlType = Bool>> {
+    let mut expr: Box<BoxableExpression<users, Pg, SqlType = Bool>> = Box::new(email.ilike(pattern.to_string()));
+    for column in &["first_name", "last_name", "phone"] {
+        expr = Box::new(expr.or(sql(&format!("{} ILIKE ", column)).bind::<Text, _>(pattern.to_string())));
+    }
+    for column in &["email", "first_name", "last_name", "phone"] {
+        expr = Box::new(expr.or(sql(&format!("{} % ", column)).bind::<Text, _>(query.to_string())));
+    }
+    expr
+}
+
+/// Escapes `LIKE` wildcards so that query is matched literally
+fn escape_like(query: &str) -> String {
+    query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn after_cursor(cursor: &UsersCursor) -> RepoResult<Box<BoxableExpression<users, Pg, SqlType = Bool>>> {
+    let op = match cursor.direction {
+        SortDirection::Asc => ">",
+        SortDirection::Desc => "<",
+    };
+
+    let expr: Box<BoxableExpression<users, Pg, SqlType = Bool>> = match cursor.sort {
+        UsersSortField::Id => Box::new(sql(&format!("id {} ", op)).bind::<Integer, _>(cursor.id.0)),
+        UsersSortField::CreatedAt | UsersSortField::LastLoginAt => Box::new(
+            sql(&format!("({}, id) {} (", sort_expression(cursor.sort), op))
+                .bind::<Timestamp, _>(cursor.time()?)
+                .sql(", ")
+                .bind::<Integer, _>(cursor.id.0)
+                .sql(")"),
+        ),
+        UsersSortField::Email | UsersSortField::Name => Box::new(
+            sql(&format!("({}, id) {} (", sort_expression(cursor.sort), op))
+                .bind::<VarChar, _>(cursor.text()?)
+                .sql(", ")
+                .bind::<Integer, _>(cursor.id.0)
+                .sql(")"),
+        ),
+    };
+
+    Ok(expr)
+}
+
+/// `DECLARE` statement of cursor over the query
+struct DeclareCursor<Q> {
+    name: &'static str,
+    query: Q,
+}
+
+impl<Q: QueryFragment<Pg>> QueryFragment<Pg> for DeclareCursor<Q> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.push_sql("DECLARE ");
+        out.push_identifier(self.name)?;
+        out.push_sql(" NO SCROLL CURSOR FOR ");
+        self.query.walk_ast(out.reborrow())
+    }
+}
+
+impl<Q> QueryId for DeclareCursor<Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Q, Conn> RunQueryDsl<Conn> for DeclareCursor<Q> {}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{escape_like, years_before};
+
+    #[test]
+    fn test_years_before() {
+        assert_eq!(years_before(NaiveDate::from_ymd(2019, 2, 13), 18), NaiveDate::from_ymd(2001, 2, 13));
+        assert_eq!(years_before(NaiveDate::from_ymd(2020, 2, 29), 1), NaiveDate::from_ymd(2019, 2, 28));
+        assert_eq!(years_before(NaiveDate::from_ymd(2019, 2, 13), 0), NaiveDate::from_ymd(2019, 2, 13));
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("john_doe"), "john\\_doe");
+        assert_eq!(escape_like("100%"), "100\\%");
+        assert_eq!(escape_like("a\\b"), "a\\\\b");
+    }
+}
@@ -0,0 +1,295 @@
+//! Repos for webhooks and webhook_deliveries tables. Webhooks are
+//! subscriptions of external services to security events, deliveries
+//! are log of every attempt to notify them.
+
+use diesel;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_dsl::RunQueryDsl;
+use diesel::sql_types::BigInt;
+use diesel::Connection;
+use failure::Error as FailureError;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use super::acl;
+use super::types::RepoResult;
+use models::authorization::*;
+use models::{NewWebhook, NewWebhookDelivery, SecurityEventType, Webhook, WebhookDelivery};
+use repos::legacy_acl::*;
+use schema::webhook_deliveries::dsl as webhook_deliveries_dsl;
+use schema::webhooks::dsl as webhooks_dsl;
+
+/// Webhooks repository, responsible for handling webhook subscriptions
+pub trait WebhooksRepo {
+    /// Returns list of webhooks
+    fn list(&self) -> RepoResult<Vec<Webhook>>;
+
+    /// Returns active webhooks subscribed to the event type
+    fn list_subscribed(&self, event_type: SecurityEventType) -> RepoResult<Vec<Webhook>>;
+
+    /// Find specific webhook by ID
+    fn find(&self, id: Uuid) -> RepoResult<Option<Webhook>>;
+
+    /// Creates new webhook
+    fn create(&self, payload: NewWebhook) -> RepoResult<Webhook>;
+
+    /// Deletes specific webhook
+    fn delete(&self, id: Uuid) -> RepoResult<Webhook>;
+}
+
+/// Webhook deliveries repository, responsible for handling deliveries log
+pub trait WebhookDeliveriesRepo {
+    /// Returns latest deliveries, limited by `count` parameter
+    fn list(&self, failed_only: bool, count: i64) -> RepoResult<Vec<WebhookDelivery>>;
+
+    /// Find specific delivery by ID
+    fn find(&self, id: Uuid) -> RepoResult<Option<WebhookDelivery>>;
+
+    /// Creates new delivery
+    fn create(&self, payload: NewWebhookDelivery) -> RepoResult<WebhookDelivery>;
+
+    /// Takes up to `count` oldest pending deliveries for sending and counts the attempt.
+    /// Rows locked by another worker are skipped.
+    fn claim_pending(&self, count: i64) -> RepoResult<Vec<WebhookDelivery>>;
+
+    /// Records result of delivery attempt
+    fn record_attempt(&self, id: Uuid, response_code: Option<i32>, error: Option<String>) -> RepoResult<WebhookDelivery>;
+
+    /// Puts delivery back to the queue, forgetting result of the last attempt
+    fn requeue(&self, id: Uuid) -> RepoResult<WebhookDelivery>;
+}
+
+/// Implementation of WebhooksRepo trait
+pub struct WebhooksRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, Webhook>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WebhooksRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, Webhook>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WebhooksRepo for WebhooksRepoImpl<'a, T> {
+    /// Returns list of webhooks
+    fn list(&self) -> RepoResult<Vec<Webhook>> {
+        acl::check(&*self.acl, Resource::Webhooks, Action::Read, self, None)
+            .and_then(|_| {
+                webhooks_dsl::webhooks
+                    .order(webhooks_dsl::created_at)
+                    .get_results(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context("List webhooks error occured").into())
+    }
+
+    /// Returns active webhooks subscribed to the event type
+    fn list_subscribed(&self, event_type: SecurityEventType) -> RepoResult<Vec<Webhook>> {
+        let query = webhooks_dsl::webhooks
+            .filter(webhooks_dsl::is_active.eq(true))
+            .filter(webhooks_dsl::event_types.contains(vec![event_type]));
+
+        query
+            .get_results(self.db_conn)
+            .map_err(From::from)
+            .and_then(|webhooks: Vec<Webhook>| {
+                for webhook in &webhooks {
+                    acl::check(&*self.acl, Resource::Webhooks, Action::Read, self, Some(webhook))?;
+                }
+                Ok(webhooks)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List webhooks subscribed to {} error occured", event_type))
+                    .into()
+            })
+    }
+
+    /// Find specific webhook by ID
+    fn find(&self, id_arg: Uuid) -> RepoResult<Option<Webhook>> {
+        webhooks_dsl::webhooks
+            .find(id_arg)
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|webhook: Option<Webhook>| {
+                if let Some(ref webhook) = webhook {
+                    acl::check(&*self.acl, Resource::Webhooks, Action::Read, self, Some(webhook))?;
+                }
+                Ok(webhook)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find webhook {} error occured", id_arg)).into())
+    }
+
+    /// Creates new webhook
+    fn create(&self, payload: NewWebhook) -> RepoResult<Webhook> {
+        acl::check(&*self.acl, Resource::Webhooks, Action::Create, self, None)
+            .and_then(|_| {
+                diesel::insert_into(webhooks_dsl::webhooks)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Create a new webhook {:?} error occured", payload)).into())
+    }
+
+    /// Deletes specific webhook
+    fn delete(&self, id_arg: Uuid) -> RepoResult<Webhook> {
+        acl::check(&*self.acl, Resource::Webhooks, Action::Delete, self, None)
+            .and_then(|_| {
+                diesel::delete(webhooks_dsl::webhooks.filter(webhooks_dsl::id.eq(id_arg)))
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Delete webhook {} error occured", id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, Webhook>
+    for WebhooksRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&Webhook>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
+
+/// Implementation of WebhookDeliveriesRepo trait
+pub struct WebhookDeliveriesRepoImpl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> {
+    pub db_conn: &'a T,
+    pub acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>,
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WebhookDeliveriesRepoImpl<'a, T> {
+    pub fn new(db_conn: &'a T, acl: Box<Acl<Resource, Action, Scope, FailureError, WebhookDelivery>>) -> Self {
+        Self { db_conn, acl }
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> WebhookDeliveriesRepo
+    for WebhookDeliveriesRepoImpl<'a, T>
+{
+    /// Returns latest deliveries, limited by `count` parameter
+    fn list(&self, failed_only: bool, count: i64) -> RepoResult<Vec<WebhookDelivery>> {
+        let mut query = webhook_deliveries_dsl::webhook_deliveries.into_boxed();
+
+        if failed_only {
+            query = query.filter(webhook_deliveries_dsl::succeeded.eq(false));
+        }
+
+        acl::check(&*self.acl, Resource::Webhooks, Action::Read, self, None)
+            .and_then(|_| {
+                query
+                    .order(webhook_deliveries_dsl::created_at.desc())
+                    .limit(count)
+                    .get_results(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("List webhook deliveries limited by {} error occured", count))
+                    .into()
+            })
+    }
+
+    /// Find specific delivery by ID
+    fn find(&self, id_arg: Uuid) -> RepoResult<Option<WebhookDelivery>> {
+        webhook_deliveries_dsl::webhook_deliveries
+            .find(id_arg)
+            .get_result(self.db_conn)
+            .optional()
+            .map_err(From::from)
+            .and_then(|delivery: Option<WebhookDelivery>| {
+                if let Some(ref delivery) = delivery {
+                    acl::check(&*self.acl, Resource::Webhooks, Action::Read, self, Some(delivery))?;
+                }
+                Ok(delivery)
+            })
+            .map_err(|e: FailureError| e.context(format!("Find webhook delivery {} error occured", id_arg)).into())
+    }
+
+    /// Creates new delivery
+    fn create(&self, payload: NewWebhookDelivery) -> RepoResult<WebhookDelivery> {
+        acl::check(&*self.acl, Resource::Webhooks, Action::Create, self, None)
+            .and_then(|_| {
+                diesel::insert_into(webhook_deliveries_dsl::webhook_deliveries)
+                    .values(&payload)
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Create a new webhook delivery {:?} error occured", payload))
+                    .into()
+            })
+    }
+
+    /// Takes up to `count` oldest pending deliveries for sending and counts the attempt.
+    /// Rows locked by another worker are skipped.
+    fn claim_pending(&self, count: i64) -> RepoResult<Vec<WebhookDelivery>> {
+        let query = diesel::sql_query(
+            "UPDATE webhook_deliveries SET pending = 'f', attempts = attempts + 1 WHERE id IN ( \
+             SELECT id FROM webhook_deliveries WHERE pending ORDER BY created_at LIMIT $1 FOR UPDATE SKIP LOCKED \
+             ) RETURNING *",
+        )
+        .bind::<BigInt, _>(count);
+
+        acl::check(&*self.acl, Resource::Webhooks, Action::Update, self, None)
+            .and_then(|_| query.load(self.db_conn).map_err(From::from))
+            .map_err(|e: FailureError| {
+                e.context(format!("Claim {} pending webhook deliveries error occured", count))
+                    .into()
+            })
+    }
+
+    /// Records result of delivery attempt
+    fn record_attempt(&self, id_arg: Uuid, response_code_arg: Option<i32>, error_arg: Option<String>) -> RepoResult<WebhookDelivery> {
+        use schema::webhook_deliveries::dsl::*;
+
+        let succeeded_arg = error_arg.is_none();
+
+        acl::check(&*self.acl, Resource::Webhooks, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(webhook_deliveries.filter(id.eq(id_arg)))
+                    .set((
+                        response_code.eq(response_code_arg),
+                        error.eq(error_arg.clone()),
+                        succeeded.eq(succeeded_arg),
+                    ))
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| {
+                e.context(format!("Record attempt of webhook delivery {} error occured", id_arg))
+                    .into()
+            })
+    }
+
+    /// Puts delivery back to the queue, forgetting result of the last attempt
+    fn requeue(&self, id_arg: Uuid) -> RepoResult<WebhookDelivery> {
+        use schema::webhook_deliveries::dsl::*;
+
+        acl::check(&*self.acl, Resource::Webhooks, Action::Update, self, None)
+            .and_then(|_| {
+                diesel::update(webhook_deliveries.filter(id.eq(id_arg)))
+                    .set((pending.eq(true), response_code.eq(None::<i32>), error.eq(None::<String>)))
+                    .get_result(self.db_conn)
+                    .map_err(From::from)
+            })
+            .map_err(|e: FailureError| e.context(format!("Requeue webhook delivery {} error occured", id_arg)).into())
+    }
+}
+
+impl<'a, T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static> CheckScope<Scope, WebhookDelivery>
+    for WebhookDeliveriesRepoImpl<'a, T>
+{
+    fn is_in_scope(&self, _user_id: UserId, scope: &Scope, _obj: Option<&WebhookDelivery>) -> bool {
+        match *scope {
+            Scope::All => true,
+            Scope::Owned => false,
+        }
+    }
+}
@@ -1,3 +1,26 @@
+table! {
+    api_tokens (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        name -> Varchar,
+        token_hash -> Varchar,
+        scopes -> Jsonb,
+        expires_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Uuid,
+        user_id -> Nullable<Int4>,
+        action -> Varchar,
+        payload -> Jsonb,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     identities (user_id) {
         user_id -> Int4,
@@ -8,6 +31,45 @@ table! {
     }
 }
 
+table! {
+    login_confirmations (token) {
+        token -> Varchar,
+        user_id -> Int4,
+        provider -> Varchar,
+        ip -> Nullable<Varchar>,
+        user_agent -> Nullable<Varchar>,
+        device -> Nullable<Varchar>,
+        country -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        confirmed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    login_events (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        provider -> Varchar,
+        ip -> Nullable<Varchar>,
+        user_agent -> Nullable<Varchar>,
+        succeeded -> Bool,
+        failure_reason -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        device -> Nullable<Varchar>,
+        country -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    password_history (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        password -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     reset_tokens (token) {
         token -> Varchar,
@@ -19,6 +81,50 @@ table! {
     }
 }
 
+table! {
+    service_clients (client_id) {
+        client_id -> Varchar,
+        name -> Varchar,
+        secret_hash -> Varchar,
+        role -> Varchar,
+        is_active -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    user_blocks (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        reason -> Nullable<Varchar>,
+        blocked_by -> Nullable<Int4>,
+        blocked_until -> Nullable<Timestamp>,
+        unblocked_by -> Nullable<Int4>,
+        unblocked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    user_deletions (user_id) {
+        user_id -> Int4,
+        token -> Varchar,
+        requested_at -> Timestamp,
+        delete_after -> Timestamp,
+        completed_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    user_notes (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        author_id -> Nullable<Int4>,
+        text -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     user_roles (id) {
         user_id -> Int4,
@@ -55,15 +161,66 @@ table! {
         country -> Nullable<Varchar>,
         referer -> Nullable<Varchar>,
         revoke_before -> Timestamp,
+        roles_version -> Int4,
+        blocked_until -> Nullable<Timestamp>,
+        tokens_revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Uuid,
+        webhook_id -> Uuid,
+        event_type -> Varchar,
+        payload -> Jsonb,
+        response_code -> Nullable<Int4>,
+        error -> Nullable<Varchar>,
+        succeeded -> Bool,
+        attempts -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        pending -> Bool,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Uuid,
+        url -> Varchar,
+        secret -> Varchar,
+        event_types -> Array<Varchar>,
+        is_active -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+joinable!(api_tokens -> users (user_id));
+joinable!(audit_log -> users (user_id));
 joinable!(identities -> users (user_id));
+joinable!(login_confirmations -> users (user_id));
+joinable!(login_events -> users (user_id));
+joinable!(password_history -> identities (user_id));
+joinable!(user_blocks -> users (user_id));
+joinable!(user_deletions -> users (user_id));
+joinable!(user_notes -> users (user_id));
 joinable!(user_roles -> users (user_id));
+joinable!(webhook_deliveries -> webhooks (webhook_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    audit_log,
     identities,
+    login_confirmations,
+    login_events,
+    password_history,
     reset_tokens,
+    service_clients,
+    user_blocks,
+    user_deletions,
+    user_notes,
     user_roles,
     users,
+    webhook_deliveries,
+    webhooks,
 );
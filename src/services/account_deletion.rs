@@ -0,0 +1,349 @@
+//! Account deletion Services, lets users delete their own accounts. Accounts are
+//! deactivated at once and anonymized by background job after grace period,
+//! the user can cancel deletion within.
+
+use std::time::{Duration, SystemTime};
+
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::{Future, Stream};
+use r2d2::ManageConnection;
+use tokio_core::reactor::{Handle, Interval};
+
+use stq_http::client::TimeLimitedHttpClient;
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use controller::context::{DynamicContext, DynamicContextServices, StaticContext};
+use errors::Error;
+use models::{NewUserDeletion, SecurityEvent, SecurityEventType, User, UserDeletion, UserDeletionRequest};
+use repos::repo_factory::ReposFactory;
+use repos::IdentitiesRepo;
+use services::api_tokens::forbid_api_token;
+use services::impersonation::forbid_impersonation;
+use services::types::ServiceFuture;
+use services::util::password_verify;
+use services::webhooks::WebhooksService;
+use services::Service;
+
+pub trait AccountDeletionService {
+    /// Deactivates account of the current user, revokes its tokens and schedules its anonymization.
+    /// Password is required unless the user has signed in recently.
+    fn request_account_deletion(&self, payload: UserDeletionRequest) -> ServiceFuture<UserDeletion>;
+    /// Cancels deletion of account by the token, given on request, and activates the account
+    fn cancel_account_deletion(&self, token: String) -> ServiceFuture<User>;
+    /// Anonymizes accounts, whose grace period has passed. Returns ids of anonymized users.
+    fn complete_account_deletions(&self) -> ServiceFuture<Vec<UserId>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > AccountDeletionService for Service<T, M, F>
+{
+    /// Deactivates account of the current user, revokes its tokens and schedules its anonymization.
+    /// Password is required unless the user has signed in recently.
+    fn request_account_deletion(&self, payload: UserDeletionRequest) -> ServiceFuture<UserDeletion> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "delete account") {
+            return Box::new(future::err(e));
+        }
+        if let Err(e) = forbid_api_token(&self.dynamic_context, "delete account") {
+            return Box::new(future::err(e));
+        }
+        let current_uid = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only signed in users can delete account").into(),
+                ))
+            }
+        };
+        let config = self.static_context.config.clone();
+        let recently_authenticated = self
+            .dynamic_context
+            .token
+            .as_ref()
+            .and_then(|token| token.auth_time)
+            .map(|auth_time| Utc::now().timestamp() - auth_time <= config.account_deletion.recent_auth_s as i64)
+            .unwrap_or(false);
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service = self.clone();
+
+        debug!("Requesting deletion of user {}", current_uid);
+
+        let fut = self
+            .spawn_on_pool(move |conn| {
+                let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+                let ident_repo = repo_factory.create_identities_repo(&*conn);
+                let user_deletions_repo = repo_factory.create_user_deletions_repo(&*conn);
+                // tokens expire not later than now + jwt_exp, so all of them are revoked
+                let revoke_before = SystemTime::now() + Duration::from_secs(config.tokens.jwt_expiration_s);
+                let delete_after = SystemTime::now() + Duration::from_secs(config.account_deletion.grace_period_s);
+
+                conn.transaction::<UserDeletion, FailureError, _>(move || {
+                    if !recently_authenticated {
+                        check_password(&*ident_repo, current_uid, payload.password)?;
+                    }
+                    users_repo.deactivate(current_uid)?;
+                    users_repo.revoke_tokens(current_uid, revoke_before)?;
+                    user_deletions_repo.create(NewUserDeletion::new(current_uid, delete_after))
+                })
+                .map_err(|e: FailureError| e.context("Service account deletion, request endpoint error occured.").into())
+            })
+            .and_then(move |user_deletion| {
+                let events = vec![
+                    SecurityEvent::new(SecurityEventType::TokensRevoked, current_uid, None),
+                    SecurityEvent::new(SecurityEventType::AccountDeletionRequested, current_uid, None),
+                ];
+                future::join_all(events.into_iter().map(move |event| service.emit_security_event(event))).map(|_| user_deletion)
+            });
+
+        Box::new(fut)
+    }
+
+    /// Cancels deletion of account by the token, given on request, and activates the account
+    fn cancel_account_deletion(&self, token: String) -> ServiceFuture<User> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "reactivate accounts") {
+            return Box::new(future::err(e));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service = self.clone();
+
+        let fut = self
+            .spawn_on_pool(move |conn| {
+                let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+                let user_deletions_repo = repo_factory.create_user_deletions_repo(&*conn);
+
+                conn.transaction::<User, FailureError, _>(move || {
+                    let user_deletion = user_deletions_repo
+                        .cancel(token)?
+                        .ok_or_else(|| Error::InvalidToken.context("Deletion token is invalid or grace period has passed"))?;
+                    debug!("Cancelling deletion of user {}", user_deletion.user_id);
+                    users_repo.activate(user_deletion.user_id)
+                })
+                .map_err(|e: FailureError| e.context("Service account deletion, cancel endpoint error occured.").into())
+            })
+            .and_then(move |user| {
+                let event = SecurityEvent::new(SecurityEventType::AccountDeletionCancelled, user.id, None);
+                service.emit_security_event(event).map(|_| user)
+            });
+
+        Box::new(fut)
+    }
+
+    /// Anonymizes accounts, whose grace period has passed. Returns ids of anonymized users.
+    fn complete_account_deletions(&self) -> ServiceFuture<Vec<UserId>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let batch_size = self.static_context.config.account_deletion.job_batch_size;
+        let service = self.clone();
+
+        let fut = self
+            .spawn_on_pool(move |conn| {
+                let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+                let ident_repo = repo_factory.create_identities_repo(&*conn);
+                let reset_token_repo = repo_factory.create_reset_token_repo(&*conn);
+                let login_events_repo = repo_factory.create_login_events_repo_with_sys_acl(&*conn);
+                let login_confirmations_repo = repo_factory.create_login_confirmations_repo(&*conn);
+                let user_notes_repo = repo_factory.create_user_notes_repo_with_sys_acl(&*conn);
+                let user_blocks_repo = repo_factory.create_user_blocks_repo_with_sys_acl(&*conn);
+                let api_tokens_repo = repo_factory.create_api_tokens_repo_with_sys_acl(&*conn);
+                let user_deletions_repo = repo_factory.create_user_deletions_repo(&*conn);
+
+                // due deletions stay locked until the batch is done, so concurrent jobs take different ones
+                conn.transaction::<Vec<UserId>, FailureError, _>(|| {
+                    let mut user_ids = vec![];
+                    for user_deletion in user_deletions_repo.list_due(batch_size)? {
+                        let user_id = user_deletion.user_id;
+                        let result = conn.transaction::<UserDeletion, FailureError, _>(|| {
+                            let user = users_repo
+                                .find(user_id)?
+                                .ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)))?;
+                            users_repo.anonymize(user_id)?;
+                            ident_repo.delete_by_user_id(user_id)?;
+                            reset_token_repo.delete_all_by_email(user.email)?;
+                            login_events_repo.delete_by_user_id(user_id)?;
+                            login_confirmations_repo.delete_by_user_id(user_id)?;
+                            user_notes_repo.delete_by_user_id(user_id)?;
+                            user_blocks_repo.clear_reasons(user_id)?;
+                            api_tokens_repo.delete_by_user_id(user_id)?;
+                            user_deletions_repo.complete(user_id)
+                        });
+                        // failed deletion is retried by the next run
+                        match result {
+                            Ok(_) => user_ids.push(user_id),
+                            Err(e) => error!("Anonymization of user {} failed: {}", user_id, e),
+                        }
+                    }
+
+                    Ok(user_ids)
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service account deletion, complete endpoint error occured.").into())
+            .and_then(move |user_ids| {
+                let events = user_ids
+                    .iter()
+                    .map(|user_id| service.emit_security_event(SecurityEvent::new(SecurityEventType::AccountDeleted, *user_id, None)))
+                    .collect::<Vec<_>>();
+                future::join_all(events).map(|_| user_ids)
+            });
+
+        Box::new(fut)
+    }
+}
+
+/// Runs `complete_account_deletions` every `Config.account_deletion.job_interval_s` on the reactor
+pub fn spawn_account_deletion_job<T, M, F>(static_context: StaticContext<T, M, F>, handle: &Handle) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let interval = Duration::from_secs(static_context.config.account_deletion.job_interval_s);
+    let http_timeout = Duration::from_millis(static_context.config.client.http_timeout_ms);
+    let time_limited_http_client = TimeLimitedHttpClient::new(static_context.client_handle.clone(), http_timeout);
+    let DynamicContextServices {
+        google_provider_service,
+        facebook_provider_service,
+    } = static_context.dynamic_context_services(time_limited_http_client.clone());
+    let dynamic_context = DynamicContext::new(
+        None,
+        "account_deletion_job".to_string(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        time_limited_http_client,
+        google_provider_service,
+        facebook_provider_service,
+    );
+    let service = Service::new(static_context, dynamic_context);
+
+    let job = Interval::new(interval, handle)?
+        .map_err(|e| error!("Account deletion job timer error: {}", e))
+        .for_each(move |_| {
+            service.complete_account_deletions().then(|result| {
+                match result {
+                    Ok(ref user_ids) if !user_ids.is_empty() => info!("Anonymized deleted users {:?}", user_ids),
+                    Ok(_) => {}
+                    Err(e) => error!("Account deletion job error: {}", e),
+                }
+                Ok(())
+            })
+        });
+    handle.spawn(job);
+
+    Ok(())
+}
+
+/// Verifies password of the user, users signed up by social providers have no password
+fn check_password(ident_repo: &IdentitiesRepo, user_id: UserId, password: Option<String>) -> Result<(), FailureError> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            return Err(Error::Validate(validation_errors!({"password": ["required" => "Password is required, or sign in again"]})).into());
+        }
+    };
+    let db_hash = ident_repo
+        .find_by_id_provider(user_id, Provider::Email)
+        .ok()
+        .and_then(|ident| ident.password);
+    let verified = match db_hash {
+        Some(db_hash) => password_verify(&db_hash, password)?,
+        None => false,
+    };
+
+    if verified {
+        Ok(())
+    } else {
+        Err(Error::Validate(validation_errors!({"password": ["password" => "Wrong password"]})).into())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use tokio_core::reactor::Core;
+
+    use stq_static_resources::Provider;
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::account_deletion::AccountDeletionService;
+
+    #[test]
+    fn test_request_account_deletion() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = UserDeletionRequest {
+            password: Some(MOCK_PASSWORD.to_string()),
+        };
+        let work = service.request_account_deletion(payload);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_id, UserId(1));
+        assert!(result.completed_at.is_none());
+    }
+
+    #[test]
+    fn test_request_account_deletion_with_wrong_password() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        for password in vec![None, Some("wrong password".to_string())] {
+            let work = service.request_account_deletion(UserDeletionRequest { password });
+            assert!(core.run(work).is_err());
+        }
+    }
+
+    #[test]
+    fn test_request_account_deletion_after_sign_in() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let mut service = create_service(Some(UserId(1)), handle);
+        let exp = Utc::now().timestamp() + 3600;
+
+        // password is not required right after sign-in
+        service.dynamic_context.token = Some(JWTPayload::new(UserId(1), exp, Provider::Email));
+        let work = service.request_account_deletion(UserDeletionRequest { password: None });
+        assert!(core.run(work).is_ok());
+
+        // refreshed token is issued recently, but the user has signed in long ago
+        let auth_time = Utc::now().timestamp() - 86400;
+        service.dynamic_context.token = Some(JWTPayload::new(UserId(1), exp, Provider::Email).with_auth_time(Some(auth_time)));
+        let work = service.request_account_deletion(UserDeletionRequest { password: None });
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_cancel_account_deletion() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.cancel_account_deletion(MOCK_TOKEN.to_string());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, UserId(1));
+        assert!(result.is_active);
+        let work = service.cancel_account_deletion("unknown".to_string());
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_complete_account_deletions() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.complete_account_deletions();
+        let result = core.run(work).unwrap();
+        assert_eq!(result, vec![UserId(2), UserId(3)]);
+    }
+}
@@ -0,0 +1,270 @@
+//! API tokens Services, presents personal long-lived tokens of users,
+//! restricted to specific resources and actions
+
+use std::time::SystemTime;
+
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+use serde_json;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use controller::context::DynamicContext;
+use errors::Error;
+use models::{ApiToken, CreatedApiToken, NewApiToken, NewApiTokenPayload, API_TOKEN_PREFIX};
+use repos::ReposFactory;
+use services::impersonation::forbid_impersonation;
+use services::types::ServiceFuture;
+use services::util::check_user_block;
+use services::Service;
+
+pub trait ApiTokensService {
+    /// Returns API tokens of the current user
+    fn list_api_tokens(&self) -> ServiceFuture<Vec<ApiToken>>;
+    /// Creates API token of the current user, the token itself is returned only once
+    fn create_api_token(&self, payload: NewApiTokenPayload) -> ServiceFuture<CreatedApiToken>;
+    /// Revokes specific API token of the current user
+    fn revoke_api_token(&self, id: Uuid) -> ServiceFuture<ApiToken>;
+    /// Returns active API token, the request is authenticated with, if its user is still active and not blocked
+    fn authenticate_api_token(&self, token: String) -> ServiceFuture<ApiToken>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ApiTokensService for Service<T, M, F>
+{
+    /// Returns API tokens of the current user
+    fn list_api_tokens(&self) -> ServiceFuture<Vec<ApiToken>> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => return Box::new(future::err(Error::Forbidden.context("Only signed in users have API tokens").into())),
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let api_tokens_repo = repo_factory.create_api_tokens_repo(&*conn, Some(current_uid));
+            api_tokens_repo
+                .list_for_user(current_uid)
+                .map_err(|e: FailureError| e.context("Service api tokens, list endpoint error occured.").into())
+        })
+    }
+
+    /// Creates API token of the current user, the token itself is returned only once
+    fn create_api_token(&self, payload: NewApiTokenPayload) -> ServiceFuture<CreatedApiToken> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "create API tokens") {
+            return Box::new(future::err(e));
+        }
+        if let Err(e) = forbid_api_token(&self.dynamic_context, "create API tokens") {
+            return Box::new(future::err(e));
+        }
+        let current_uid = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only signed in users can create API tokens").into(),
+                ))
+            }
+        };
+        let expires_at = payload.expires_at.map(SystemTime::from);
+        if expires_at.map(|expires_at| expires_at <= SystemTime::now()).unwrap_or(false) {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"expires_at": ["expires_at" => "Token must expire in the future"]})).into(),
+            ));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Creating API token {} of user {}", payload.name, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let api_tokens_repo = repo_factory.create_api_tokens_repo(&*conn, Some(current_uid));
+            let token = generate_api_token();
+            let new_api_token = NewApiToken {
+                user_id: current_uid,
+                name: payload.name,
+                token_hash: api_token_hash(&token),
+                scopes: serde_json::to_value(&payload.scopes)?,
+                expires_at,
+            };
+            conn.transaction::<ApiToken, FailureError, _>(move || api_tokens_repo.create(new_api_token))
+                .map(|api_token| CreatedApiToken { token, api_token })
+                .map_err(|e: FailureError| e.context("Service api tokens, create endpoint error occured.").into())
+        })
+    }
+
+    /// Revokes specific API token of the current user
+    fn revoke_api_token(&self, id: Uuid) -> ServiceFuture<ApiToken> {
+        if let Err(e) = forbid_api_token(&self.dynamic_context, "revoke API tokens") {
+            return Box::new(future::err(e));
+        }
+        let current_uid = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only signed in users can revoke API tokens").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Revoking API token {} of user {}", id, current_uid);
+
+        self.spawn_on_pool(move |conn| {
+            let api_tokens_repo = repo_factory.create_api_tokens_repo(&*conn, Some(current_uid));
+            api_tokens_repo
+                .revoke(current_uid, id)
+                .and_then(|api_token| api_token.ok_or_else(|| Error::NotFound.context(format!("API token {} not found", id)).into()))
+                .map_err(|e: FailureError| e.context("Service api tokens, revoke endpoint error occured.").into())
+        })
+    }
+
+    /// Returns active API token, the request is authenticated with, if its user is still active and not blocked
+    fn authenticate_api_token(&self, token: String) -> ServiceFuture<ApiToken> {
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let api_tokens_repo = repo_factory.create_api_tokens_repo_with_sys_acl(&*conn);
+                let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+                let user_blocks_repo = repo_factory.create_user_blocks_repo_with_sys_acl(&*conn);
+
+                let api_token = api_tokens_repo
+                    .find_by_hash(api_token_hash(&token))?
+                    .filter(|api_token| api_token.is_active_at(SystemTime::now()))
+                    .ok_or_else(|| Error::InvalidToken.context("API token is invalid, expired or revoked"))?;
+                let user = users_repo
+                    .find(api_token.user_id)?
+                    .ok_or_else(|| Error::InvalidToken.context(format!("User {} of API token not found", api_token.user_id)))?;
+                if !user.is_active {
+                    return Err(Error::InvalidToken.context(format!("User {} is deactivated", user.id)).into());
+                }
+                check_user_block(&*users_repo, &*user_blocks_repo, &user)?;
+
+                Ok(api_token)
+            })
+            .map_err(|e: FailureError| e.context("Service api tokens, authenticate endpoint error occured.").into()),
+        )
+    }
+}
+
+/// Fails with `Forbidden` if the request is authenticated with API token.
+/// API tokens must not be able to take over the account or to create more tokens.
+pub fn forbid_api_token(dynamic_context: &DynamicContext, action: &str) -> Result<(), FailureError> {
+    match dynamic_context.api_token {
+        Some(api_token) => Err(Error::Forbidden
+            .context(format!("API token {} is not allowed to {}", api_token, action))
+            .into()),
+        None => Ok(()),
+    }
+}
+
+/// Hex encoded SHA-256 of API token, tokens are stored and looked up by it
+pub fn api_token_hash(token: &str) -> String {
+    let mut hasher = Sha256::default();
+    hasher.input(token.as_bytes());
+    hasher.result().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn generate_api_token() -> String {
+    let mut bytes = Uuid::new_v4().as_bytes().to_vec();
+    bytes.extend_from_slice(Uuid::new_v4().as_bytes());
+    format!("{}{}", API_TOKEN_PREFIX, encode_config(&bytes, URL_SAFE_NO_PAD))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    use stq_types::UserId;
+
+    use models::*;
+    use repos::repo_factory::tests::*;
+    use services::api_tokens::ApiTokensService;
+
+    #[test]
+    fn test_create_api_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(2)), handle);
+        let payload = NewApiTokenPayload {
+            name: "script".to_string(),
+            scopes: vec![ApiTokenScope {
+                resource: Resource::Users,
+                action: Action::Read,
+            }],
+            expires_at: None,
+        };
+        let work = service.create_api_token(payload);
+        let result = core.run(work).unwrap();
+        assert!(result.token.starts_with(API_TOKEN_PREFIX));
+        assert_eq!(result.api_token.user_id, UserId(2));
+        assert_eq!(result.api_token.scopes().len(), 1);
+    }
+
+    #[test]
+    fn test_create_api_token_with_api_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let mut service = create_service(Some(UserId(2)), handle);
+        service.dynamic_context.api_token = Some(Uuid::new_v4());
+        let payload = NewApiTokenPayload {
+            name: "script".to_string(),
+            scopes: vec![ApiTokenScope {
+                resource: Resource::Users,
+                action: Action::All,
+            }],
+            expires_at: None,
+        };
+        let work = service.create_api_token(payload);
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_create_api_token_while_impersonating() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let mut service = create_service(Some(UserId(2)), handle);
+        service.dynamic_context.impersonator = Some(UserId(1));
+        let payload = NewApiTokenPayload {
+            name: "script".to_string(),
+            scopes: vec![ApiTokenScope {
+                resource: Resource::Users,
+                action: Action::Read,
+            }],
+            expires_at: None,
+        };
+        let work = service.create_api_token(payload);
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_revoke_api_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(2)), handle);
+        let id = Uuid::new_v4();
+        let work = service.revoke_api_token(id);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, id);
+        assert!(result.revoked_at.is_some());
+    }
+
+    #[test]
+    fn test_authenticate_with_unknown_api_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.authenticate_api_token("stq_unknown".to_string());
+        assert!(core.run(work).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! GeoIP lookup of client country by local database of IPv4 ranges.
+//! Database is a CSV file with `first_ip,last_ip,country` lines, where
+//! addresses are either dotted or integer, e.g. `1.0.0.0,1.0.0.255,AUS`.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::Ipv4Addr;
+
+use failure::Error as FailureError;
+use failure::Fail;
+
+#[derive(Clone, Debug, Default)]
+pub struct GeoIp {
+    /// Ranges sorted by the first address
+    ranges: Vec<(u32, u32, String)>,
+}
+
+impl GeoIp {
+    /// Reads database from CSV file
+    pub fn from_file(path: &str) -> Result<Self, FailureError> {
+        let file = File::open(path).map_err(|e| e.context(format!("Could not open GeoIP database {}", path)))?;
+        Self::from_csv(BufReader::new(file)).map_err(|e| e.context(format!("Could not read GeoIP database {}", path)).into())
+    }
+
+    /// Reads database from CSV lines, empty lines, comments and header are skipped
+    pub fn from_csv<R: BufRead>(reader: R) -> Result<Self, FailureError> {
+        let mut ranges = vec![];
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split(',').map(|field| field.trim().trim_matches('"')).collect::<Vec<_>>();
+            match (fields.get(0), fields.get(1), fields.get(2)) {
+                (Some(first), Some(last), Some(country)) => match (parse_ip(first), parse_ip(last)) {
+                    (Some(first), Some(last)) if first <= last && !country.is_empty() => {
+                        ranges.push((first, last, country.to_string()));
+                    }
+                    // header
+                    (None, None) if n == 0 => {}
+                    _ => return Err(format_err!("Invalid GeoIP range at line {}", n + 1)),
+                },
+                _ => return Err(format_err!("Invalid GeoIP range at line {}", n + 1)),
+            }
+        }
+        ranges.sort_by_key(|&(first, _, _)| first);
+
+        Ok(Self { ranges })
+    }
+
+    /// Returns country of IPv4 address, IPv6 addresses are not supported
+    pub fn country(&self, ip: &str) -> Option<String> {
+        let ip = u32::from(ip.parse::<Ipv4Addr>().ok()?);
+        let index = match self.ranges.binary_search_by_key(&ip, |&(first, _, _)| first) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+
+        let (_, last, ref country) = self.ranges[index];
+        if ip <= last {
+            Some(country.clone())
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_ip(s: &str) -> Option<u32> {
+    s.parse::<Ipv4Addr>().ok().map(u32::from).or_else(|| s.parse::<u32>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geoip_country() {
+        let csv = "first_ip,last_ip,country\n1.0.0.0,1.0.0.255,AUS\n16777472,16778239,CHN\n\n# comment\n5.0.0.0,5.0.0.10,RUS\n";
+        let geoip = GeoIp::from_csv(csv.as_bytes()).unwrap();
+        assert_eq!(geoip.country("1.0.0.17"), Some("AUS".to_string()));
+        assert_eq!(geoip.country("1.0.1.0"), Some("CHN".to_string()));
+        assert_eq!(geoip.country("5.0.0.10"), Some("RUS".to_string()));
+        assert_eq!(geoip.country("5.0.0.11"), None);
+        assert_eq!(geoip.country("0.255.255.255"), None);
+        assert_eq!(geoip.country("::1"), None);
+    }
+
+    #[test]
+    fn test_geoip_invalid_range() {
+        assert!(GeoIp::from_csv("1.0.0.0,1.0.0.255,AUS\n1.0.1.0,AUS\n".as_bytes()).is_err());
+    }
+}
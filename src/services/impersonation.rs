@@ -0,0 +1,189 @@
+//! Impersonation Services, lets superusers act on behalf of other users
+//! with short-lived tokens. Every token issued and every request made with it
+//! is recorded in audit log.
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use r2d2::ManageConnection;
+use serde_json;
+
+use stq_types::{UserId, UsersRole};
+
+use controller::context::DynamicContext;
+use errors::Error;
+use models::{AuditAction, ImpersonatedRequestAudit, ImpersonationAudit, JWTPayload, NewAuditLogEntry, UserStatus, JWT};
+use repos::ReposFactory;
+use services::api_tokens::forbid_api_token;
+use services::jwt::create_jwt_payload;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait ImpersonationService {
+    /// Issues short-lived token to act on behalf of specific user, available to superusers only.
+    /// Other superusers can not be impersonated.
+    fn impersonate(&self, user_id: UserId, exp: i64) -> ServiceFuture<JWT>;
+    /// Records request made with impersonation token in audit log
+    fn record_impersonated_request(&self, method: String, path: String) -> ServiceFuture<()>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ImpersonationService for Service<T, M, F>
+{
+    /// Issues short-lived token to act on behalf of specific user, available to superusers only.
+    /// Other superusers can not be impersonated.
+    fn impersonate(&self, user_id: UserId, exp: i64) -> ServiceFuture<JWT> {
+        if let Err(e) = forbid_api_token(&self.dynamic_context, "impersonate users") {
+            return Box::new(future::err(e));
+        }
+        let current_uid = match (self.dynamic_context.user_id, self.dynamic_context.impersonator) {
+            (Some(current_uid), None) => current_uid,
+            _ => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only signed in superusers can impersonate users").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+        let jwt_signer = self.static_context.jwt_signer.clone();
+        let jwt_config = self.static_context.config.jwt.clone();
+
+        debug!("User {} impersonates user {} until {}", current_uid, user_id, exp);
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+                let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+                let ident_repo = repo_factory.create_identities_repo(&*conn);
+                let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+
+                if !user_roles_repo.list_for_user(current_uid)?.contains(&UsersRole::Superuser) {
+                    return Err(Error::Forbidden
+                        .context(format!("User {} is not allowed to impersonate users", current_uid))
+                        .into());
+                }
+                users_repo
+                    .find(user_id)?
+                    .ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)))?;
+                if user_roles_repo.list_for_user(user_id)?.contains(&UsersRole::Superuser) {
+                    return Err(Error::Forbidden
+                        .context(format!("Superuser {} can not be impersonated", user_id))
+                        .into());
+                }
+
+                // token looks the same as the one the user gets on sign in
+                let provider = ident_repo
+                    .find_by_user_id(user_id)?
+                    .map(|identity| identity.provider)
+                    .ok_or_else(|| Error::NotFound.context(format!("Identity of user {} not found", user_id)))?;
+                // the superuser has not signed in as the user, so impersonation token can't pass for recent sign-in
+                let payload = JWTPayload {
+                    impersonator: Some(current_uid),
+                    auth_time: None,
+                    ..create_jwt_payload(&jwt_config, &*users_repo, &*user_roles_repo, user_id, exp, provider)?
+                };
+                let token = jwt_signer.sign(&payload)?;
+
+                audit_log_repo.create(NewAuditLogEntry {
+                    user_id: Some(current_uid),
+                    action: AuditAction::UserImpersonated,
+                    payload: serde_json::to_value(ImpersonationAudit { user_id, exp })?,
+                })?;
+
+                Ok(JWT {
+                    token,
+                    status: UserStatus::Exists,
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service impersonation, impersonate endpoint error occured.").into()),
+        )
+    }
+
+    /// Records request made with impersonation token in audit log
+    fn record_impersonated_request(&self, method: String, path: String) -> ServiceFuture<()> {
+        let impersonator = self.dynamic_context.impersonator;
+        let user_id = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let audit_log_repo = repo_factory.create_audit_log_repo_with_sys_acl(&*conn);
+            audit_log_repo
+                .create(NewAuditLogEntry {
+                    user_id: impersonator,
+                    action: AuditAction::ImpersonatedRequest,
+                    payload: serde_json::to_value(ImpersonatedRequestAudit { user_id, method, path })?,
+                })
+                .map(|_| ())
+                .map_err(|e: FailureError| e.context("Service impersonation, record_request endpoint error occured.").into())
+        })
+    }
+}
+
+/// Fails with `Forbidden` if the request is made with impersonation token.
+/// Impersonating superusers must not be able to take over the account.
+pub fn forbid_impersonation(dynamic_context: &DynamicContext, action: &str) -> Result<(), FailureError> {
+    match dynamic_context.impersonator {
+        Some(impersonator) => Err(Error::Forbidden
+            .context(format!("User {} is not allowed to {} while impersonating", impersonator, action))
+            .into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::UpdateUser;
+    use repos::repo_factory::tests::*;
+    use services::impersonation::ImpersonationService;
+    use services::users::UsersService;
+
+    #[test]
+    fn test_impersonate() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.impersonate(UserId(2), 0);
+        assert!(core.run(work).is_ok());
+    }
+
+    #[test]
+    fn test_impersonate_superuser() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.impersonate(UserId(1), 0);
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_impersonate_by_ordinary_user() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(2)), handle);
+        let work = service.impersonate(UserId(3), 0);
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_update_while_impersonating() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let mut service = create_service(Some(UserId(2)), handle);
+        service.dynamic_context.impersonator = Some(UserId(1));
+        let work = service.update(UserId(2), UpdateUser::default(), None);
+        assert!(core.run(work).is_err());
+    }
+}
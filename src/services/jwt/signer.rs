@@ -0,0 +1,395 @@
+//! Signer of json web tokens. Algorithm and keys are configured in `Config.jwt`,
+//! keys are read in PEM or DER format.
+
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::Read;
+
+use base64::{decode as decode_base64, decode_config, encode_config, URL_SAFE_NO_PAD};
+use chrono::Utc;
+use failure::Error as FailureError;
+use failure::Fail;
+use jsonwebtoken::{decode, encode, Algorithm, Header, Validation};
+use ring::error::Unspecified;
+use ring::rand::SystemRandom;
+use ring::signature::{self, Ed25519KeyPair, VerificationAlgorithm};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use untrusted::{Input, Reader};
+
+use config::{JwtAlgorithm, JWT as JWTConfig};
+use errors::Error;
+
+/// Header of ES256 tokens, they are signed by ring directly
+const ES256_HEADER: &'static str = r#"{"typ":"JWT","alg":"ES256"}"#;
+/// Header of EdDSA tokens, jsonwebtoken doesn't know the algorithm
+const EDDSA_HEADER: &'static str = r#"{"typ":"JWT","alg":"EdDSA"}"#;
+/// Length of uncompressed P-256 point, that ends SubjectPublicKeyInfo of ES256 public key
+const P256_PUBLIC_KEY_LEN: usize = 65;
+/// Length of raw Ed25519 key, that ends SubjectPublicKeyInfo of EdDSA public key
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+/// Object identifier of elliptic curve public keys, 1.2.840.10045.2.1
+const EC_PUBLIC_KEY_OID: &'static [u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+/// Object identifier of P-256 curve, 1.2.840.10045.3.1.7
+const P256_CURVE_OID: &'static [u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+/// Object identifier of Ed25519 public keys, 1.3.101.112
+const ED25519_OID: &'static [u8] = &[0x2b, 0x65, 0x70];
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OID: u8 = 0x06;
+
+/// Signs and verifies json web tokens of users and internal services
+#[derive(Clone)]
+pub struct JwtSigner {
+    algorithm: JwtAlgorithm,
+    private_key: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ProbePayload {
+    exp: i64,
+}
+
+impl JwtSigner {
+    /// Reads keys from files configured in `Config.jwt`
+    pub fn from_config(config: &JWTConfig) -> Result<Self, FailureError> {
+        let private_key = read_key(&config.secret_key_path)?;
+        let public_key = read_key(&config.public_key_path)?;
+        Self::new(config.algorithm, &private_key, &public_key)
+    }
+
+    /// Creates signer from keys in PEM or DER format. Keys are checked
+    /// to match each other and the algorithm by signing probe token.
+    pub fn new(algorithm: JwtAlgorithm, private_key: &[u8], public_key: &[u8]) -> Result<Self, FailureError> {
+        let private_key = pem_to_der(private_key)?;
+        let public_key = pem_to_der(public_key)?;
+        let public_key = match algorithm {
+            JwtAlgorithm::RS256 => public_key,
+            JwtAlgorithm::ES256 => raw_public_key(public_key, EC_PUBLIC_KEY_OID, Some(P256_CURVE_OID), P256_PUBLIC_KEY_LEN)?,
+            JwtAlgorithm::EdDSA => raw_public_key(public_key, ED25519_OID, None, ED25519_PUBLIC_KEY_LEN)?,
+        };
+        let signer = Self {
+            algorithm,
+            private_key,
+            public_key,
+        };
+
+        let probe = signer.sign(&ProbePayload {
+            exp: Utc::now().timestamp() + 60,
+        })?;
+        signer
+            .verify::<ProbePayload>(&probe)
+            .ok_or_else(|| format_err!("Keys don't match each other or algorithm {:?}", algorithm))?;
+
+        Ok(signer)
+    }
+
+    /// Signs token with the payload as its claims
+    pub fn sign<P: Serialize + Debug>(&self, payload: &P) -> Result<String, FailureError> {
+        let token = match self.jsonwebtoken_algorithm() {
+            Some(algorithm) => encode(&Header::new(algorithm), payload, &self.private_key).map_err(|e| format_err!("{}", e)),
+            None => self.sign_with_ring(payload),
+        };
+
+        token.map_err(|e| {
+            e.context(Error::Parse)
+                .context(format!("Couldn't encode jwt: {:?}.", payload))
+                .into()
+        })
+    }
+
+    /// Returns claims of the token, if its signature is valid and it has not expired
+    pub fn verify<P: DeserializeOwned>(&self, token: &str) -> Option<P> {
+        match self.jsonwebtoken_algorithm() {
+            Some(algorithm) => {
+                let validation = Validation {
+                    algorithms: vec![algorithm],
+                    ..Validation::default()
+                };
+                decode::<P>(token, &self.public_key, &validation).ok().map(|data| data.claims)
+            }
+            None => self.verify_with_ring(token),
+        }
+    }
+
+    /// Only RS256 is left to jsonwebtoken, ES256 and EdDSA tokens are signed by ring directly
+    fn jsonwebtoken_algorithm(&self) -> Option<Algorithm> {
+        match self.algorithm {
+            JwtAlgorithm::RS256 => Some(Algorithm::RS256),
+            JwtAlgorithm::ES256 | JwtAlgorithm::EdDSA => None,
+        }
+    }
+
+    /// Returns header and `alg` name of tokens signed by ring
+    fn ring_header(&self) -> (&'static str, &'static str) {
+        match self.algorithm {
+            JwtAlgorithm::ES256 => (ES256_HEADER, "ES256"),
+            _ => (EDDSA_HEADER, "EdDSA"),
+        }
+    }
+
+    /// Assembles ES256 token as described in RFC 7518 or EdDSA token as described in RFC 8037
+    fn sign_with_ring<P: Serialize>(&self, payload: &P) -> Result<String, FailureError> {
+        let (header, _) = self.ring_header();
+        let signing_input = format!(
+            "{}.{}",
+            encode_config(header, URL_SAFE_NO_PAD),
+            encode_config(&serde_json::to_vec(payload)?, URL_SAFE_NO_PAD)
+        );
+        let private_key = Input::from(&self.private_key[..]);
+        let signature = match self.algorithm {
+            JwtAlgorithm::ES256 => {
+                let key_pair = signature::key_pair_from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, private_key)
+                    .map_err(|_| format_err!("Invalid P-256 private key"))?;
+                signature::sign(&key_pair, &SystemRandom::new(), Input::from(signing_input.as_bytes()))
+                    .map_err(|_| format_err!("Could not sign token with P-256 key"))?
+            }
+            _ => Ed25519KeyPair::from_pkcs8_maybe_unchecked(private_key)
+                .map_err(|_| format_err!("Invalid Ed25519 private key"))?
+                .sign(signing_input.as_bytes()),
+        };
+
+        Ok(format!("{}.{}", signing_input, encode_config(signature.as_ref(), URL_SAFE_NO_PAD)))
+    }
+
+    /// Verifies token signed by ring, tokens without expiration are rejected as by jsonwebtoken validation
+    fn verify_with_ring<P: DeserializeOwned>(&self, token: &str) -> Option<P> {
+        let mut parts = token.rsplitn(2, '.');
+        let (token_signature, signing_input) = (parts.next()?, parts.next()?);
+        let mut parts = signing_input.splitn(2, '.');
+        let (header, claims) = (parts.next()?, parts.next()?);
+
+        let (_, alg) = self.ring_header();
+        let header: serde_json::Value = serde_json::from_slice(&decode_config(header, URL_SAFE_NO_PAD).ok()?).ok()?;
+        if header["alg"] != alg {
+            return None;
+        }
+        let verification_algorithm: &VerificationAlgorithm = match self.algorithm {
+            JwtAlgorithm::ES256 => &signature::ECDSA_P256_SHA256_FIXED,
+            _ => &signature::ED25519,
+        };
+        let token_signature = decode_config(token_signature, URL_SAFE_NO_PAD).ok()?;
+        signature::verify(
+            verification_algorithm,
+            Input::from(&self.public_key[..]),
+            Input::from(signing_input.as_bytes()),
+            Input::from(&token_signature[..]),
+        )
+        .ok()?;
+
+        let claims: serde_json::Value = serde_json::from_slice(&decode_config(claims, URL_SAFE_NO_PAD).ok()?).ok()?;
+        match claims["exp"].as_i64() {
+            Some(exp) if exp > Utc::now().timestamp() => serde_json::from_value(claims).ok(),
+            _ => None,
+        }
+    }
+}
+
+fn read_key(path: &str) -> Result<Vec<u8>, FailureError> {
+    let mut key = vec![];
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut key))
+        .map_err(|e| e.context(format!("Could not read key {}", path)))?;
+    Ok(key)
+}
+
+/// Converts PEM encoded key to DER, DER keys are returned as is
+fn pem_to_der(key: &[u8]) -> Result<Vec<u8>, FailureError> {
+    let pem = match ::std::str::from_utf8(key) {
+        Ok(pem) if pem.trim_left().starts_with("-----BEGIN") => pem,
+        _ => return Ok(key.to_vec()),
+    };
+    let body = pem
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect::<String>();
+
+    decode_base64(&body).map_err(|e| e.context("Invalid PEM key").into())
+}
+
+/// Returns raw public key of SubjectPublicKeyInfo, checking identifiers of its algorithm and curve
+/// and the length of the key. Raw keys of the expected length are returned as is.
+fn raw_public_key(key: Vec<u8>, algorithm: &[u8], curve: Option<&[u8]>, len: usize) -> Result<Vec<u8>, FailureError> {
+    if key.len() == len {
+        return Ok(key);
+    }
+
+    Input::from(&key[..])
+        .read_all(Unspecified, |reader| {
+            read_der(reader, DER_SEQUENCE)?.read_all(Unspecified, |spki| {
+                read_der(spki, DER_SEQUENCE)?.read_all(Unspecified, |algorithm_identifier| {
+                    expect_der(algorithm_identifier, DER_OID, algorithm)?;
+                    match curve {
+                        Some(curve) => expect_der(algorithm_identifier, DER_OID, curve),
+                        // parameters of Ed25519 key must be absent, RFC 8410
+                        None => Ok(()),
+                    }
+                })?;
+                // the first byte of bit string is the number of unused bits
+                match read_der(spki, DER_BIT_STRING)?.as_slice_less_safe().split_first() {
+                    Some((&0, raw_key)) if raw_key.len() == len => Ok(raw_key.to_vec()),
+                    _ => Err(Unspecified),
+                }
+            })
+        })
+        .map_err(|_| format_err!("Public key is not a valid SubjectPublicKeyInfo of the configured algorithm"))
+}
+
+/// Reads DER value of the tag, keys are short, so lengths up to two bytes are supported
+fn read_der<'a>(reader: &mut Reader<'a>, tag: u8) -> Result<Input<'a>, Unspecified> {
+    if reader.read_byte().map_err(|_| Unspecified)? != tag {
+        return Err(Unspecified);
+    }
+    let len = match reader.read_byte().map_err(|_| Unspecified)? {
+        len if len < 0x80 => len as usize,
+        0x81 => reader.read_byte().map_err(|_| Unspecified)? as usize,
+        0x82 => {
+            let high = reader.read_byte().map_err(|_| Unspecified)? as usize;
+            (high << 8) | reader.read_byte().map_err(|_| Unspecified)? as usize
+        }
+        _ => return Err(Unspecified),
+    };
+    reader.skip_and_get_input(len).map_err(|_| Unspecified)
+}
+
+/// Reads DER value of the tag and checks that it is equal to `expected`
+fn expect_der(reader: &mut Reader, tag: u8, expected: &[u8]) -> Result<(), Unspecified> {
+    if read_der(reader, tag)?.as_slice_less_safe() == expected {
+        Ok(())
+    } else {
+        Err(Unspecified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::encode;
+
+    use stq_static_resources::Provider;
+    use stq_types::UserId;
+
+    use super::*;
+    use models::JWTPayload;
+
+    fn read_test_key(path: &str) -> Vec<u8> {
+        let mut key = vec![];
+        File::open(path).unwrap().read_to_end(&mut key).unwrap();
+        key
+    }
+
+    fn to_pem(der: &[u8], label: &str) -> Vec<u8> {
+        format!("-----BEGIN {}-----\n{}\n-----END {}-----\n", label, encode(der), label).into_bytes()
+    }
+
+    fn create_eddsa_signer() -> JwtSigner {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(Input::from(&pkcs8[..])).unwrap();
+        JwtSigner::new(JwtAlgorithm::EdDSA, &pkcs8[..], key_pair.public_key_bytes()).unwrap()
+    }
+
+    /// Returns PKCS#8 private key and SubjectPublicKeyInfo of new P-256 key pair
+    fn create_p256_keys() -> (Vec<u8>, Vec<u8>) {
+        let pkcs8 = signature::ECDSAKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+        let pkcs8 = pkcs8.as_ref().to_vec();
+        // PKCS#8 document of ring ends with the public key
+        let public_key = &pkcs8[pkcs8.len() - P256_PUBLIC_KEY_LEN..];
+        let mut spki = vec![
+            0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03,
+            0x01, 0x07, 0x03, 0x42, 0x00,
+        ];
+        spki.extend_from_slice(public_key);
+        (pkcs8, spki)
+    }
+
+    fn ed25519_spki(public_key: &[u8]) -> Vec<u8> {
+        let mut spki = vec![0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+        spki.extend_from_slice(public_key);
+        spki
+    }
+
+    #[test]
+    fn test_rs256_der_and_pem_keys() {
+        let private_key = read_test_key("config/keys/private_key.der");
+        let public_key = read_test_key("config/keys/public_key.der");
+        assert!(JwtSigner::new(JwtAlgorithm::RS256, &private_key, &public_key).is_ok());
+        let signer = JwtSigner::new(
+            JwtAlgorithm::RS256,
+            &to_pem(&private_key, "RSA PRIVATE KEY"),
+            &to_pem(&public_key, "RSA PUBLIC KEY"),
+        )
+        .unwrap();
+        let token = signer
+            .sign(&JWTPayload::new(UserId(1), Utc::now().timestamp() + 60, Provider::Email))
+            .unwrap();
+        let payload = signer.verify::<JWTPayload>(&token).unwrap();
+        assert_eq!(payload.user_id, UserId(1));
+    }
+
+    #[test]
+    fn test_keys_of_other_algorithm() {
+        let private_key = read_test_key("config/keys/private_key.der");
+        let public_key = read_test_key("config/keys/public_key.der");
+        assert!(JwtSigner::new(JwtAlgorithm::EdDSA, &private_key, &public_key).is_err());
+    }
+
+    #[test]
+    fn test_eddsa_sign_and_verify() {
+        let signer = create_eddsa_signer();
+        let token = signer
+            .sign(&JWTPayload::new(UserId(1), Utc::now().timestamp() + 60, Provider::Email))
+            .unwrap();
+        let payload = signer.verify::<JWTPayload>(&token).unwrap();
+        assert_eq!(payload.user_id, UserId(1));
+        assert!(create_eddsa_signer().verify::<JWTPayload>(&token).is_none());
+    }
+
+    #[test]
+    fn test_eddsa_expired_token() {
+        let signer = create_eddsa_signer();
+        let token = signer
+            .sign(&JWTPayload::new(UserId(1), Utc::now().timestamp() - 60, Provider::Email))
+            .unwrap();
+        assert!(signer.verify::<JWTPayload>(&token).is_none());
+    }
+
+    #[test]
+    fn test_es256_sign_and_verify() {
+        let (private_key, public_key) = create_p256_keys();
+        let signer = JwtSigner::new(JwtAlgorithm::ES256, &private_key, &to_pem(&public_key, "PUBLIC KEY")).unwrap();
+        let token = signer
+            .sign(&JWTPayload::new(UserId(1), Utc::now().timestamp() + 60, Provider::Email))
+            .unwrap();
+        let payload = signer.verify::<JWTPayload>(&token).unwrap();
+        assert_eq!(payload.user_id, UserId(1));
+
+        let (private_key, public_key) = create_p256_keys();
+        let other_signer = JwtSigner::new(JwtAlgorithm::ES256, &private_key, &public_key).unwrap();
+        assert!(other_signer.verify::<JWTPayload>(&token).is_none());
+        assert!(create_eddsa_signer().verify::<JWTPayload>(&token).is_none());
+    }
+
+    #[test]
+    fn test_public_key_info() {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(Input::from(&pkcs8[..])).unwrap();
+        let spki = ed25519_spki(key_pair.public_key_bytes());
+        assert!(JwtSigner::new(JwtAlgorithm::EdDSA, &pkcs8[..], &spki).is_ok());
+
+        // key of other algorithm, truncated key and key with trailing data are rejected
+        let (_, p256_spki) = create_p256_keys();
+        assert!(raw_public_key(p256_spki.clone(), ED25519_OID, None, ED25519_PUBLIC_KEY_LEN).is_err());
+        assert!(raw_public_key(spki.clone(), EC_PUBLIC_KEY_OID, Some(P256_CURVE_OID), P256_PUBLIC_KEY_LEN).is_err());
+        assert!(raw_public_key(spki[..spki.len() - 1].to_vec(), ED25519_OID, None, ED25519_PUBLIC_KEY_LEN).is_err());
+        let mut trailing = p256_spki.clone();
+        trailing.push(0);
+        assert!(raw_public_key(trailing, EC_PUBLIC_KEY_OID, Some(P256_CURVE_OID), P256_PUBLIC_KEY_LEN).is_err());
+        assert_eq!(
+            raw_public_key(p256_spki.clone(), EC_PUBLIC_KEY_OID, Some(P256_CURVE_OID), P256_PUBLIC_KEY_LEN).unwrap(),
+            p256_spki[p256_spki.len() - P256_PUBLIC_KEY_LEN..].to_vec()
+        );
+    }
+}
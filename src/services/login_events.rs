@@ -0,0 +1,411 @@
+//! Login events Services, records sign-ins of users, presents their history
+//! and detects suspicious sign-ins from new devices and countries
+
+use std::time::{Duration, SystemTime};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use futures::Future;
+use r2d2::ManageConnection;
+use serde_json;
+use sha2::{Digest, Sha256};
+
+use stq_static_resources::Provider;
+use stq_types::UserId;
+
+use config::SuspiciousLogin as SuspiciousLoginConfig;
+use errors::Error;
+use models::{
+    LoginClient, LoginConfirmation, LoginEvent, NewLoginConfirmation, NewLoginEvent, SecurityEvent, SecurityEventType, SuspiciousLogin,
+    SuspiciousLoginReason,
+};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::webhooks::WebhooksService;
+use services::Service;
+
+pub trait LoginEventsService {
+    /// Returns latest sign-ins of the current user
+    fn list_login_events(&self, count: i64) -> ServiceFuture<Vec<LoginEvent>>;
+    /// Records successful sign-in of the user and updates its last login time.
+    /// Recording errors are only logged, so that they never prevent user from signing in.
+    fn record_login_success(&self, user_id: UserId, provider: Provider, client: LoginClient) -> ServiceFuture<()>;
+    /// Records failed sign-in of the user with specified email, attempts to sign in
+    /// with unknown emails are not recorded. Recording errors are only logged.
+    fn record_login_failure(&self, email: String, provider: Provider, client: LoginClient, error: &FailureError) -> ServiceFuture<()>;
+    /// Checks sign-in of the user against devices and countries of its previous sign-ins.
+    /// Suspicious sign-in is reported with `suspicious_login` security event and fails
+    /// with `login.confirmation_required` error, if confirmation by email is required.
+    fn check_login(&self, user_id: UserId, provider: Provider, client: LoginClient) -> ServiceFuture<()>;
+    /// Confirms suspicious sign-in by token from email
+    fn confirm_login(&self, token: String) -> ServiceFuture<LoginConfirmation>;
+    /// Returns token of the pending sign-in confirmation of the user to be sent by email
+    fn get_login_confirmation_token(&self, user_id: UserId) -> ServiceFuture<String>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > LoginEventsService for Service<T, M, F>
+{
+    /// Returns latest sign-ins of the current user
+    fn list_login_events(&self, count: i64) -> ServiceFuture<Vec<LoginEvent>> {
+        let current_uid = match self.dynamic_context.user_id {
+            Some(current_uid) => current_uid,
+            None => {
+                return Box::new(future::err(
+                    Error::Forbidden.context("Only signed in users have login events").into(),
+                ))
+            }
+        };
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let login_events_repo = repo_factory.create_login_events_repo(&*conn, Some(current_uid));
+            login_events_repo
+                .list_for_user(current_uid, count)
+                .map_err(|e: FailureError| e.context("Service login events, list endpoint error occured.").into())
+        })
+    }
+
+    /// Records successful sign-in of the user and updates its last login time.
+    /// Recording errors are only logged, so that they never prevent user from signing in.
+    fn record_login_success(&self, user_id: UserId, provider: Provider, client: LoginClient) -> ServiceFuture<()> {
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            let login_events_repo = repo_factory.create_login_events_repo_with_sys_acl(&*conn);
+
+            let res = users_repo
+                .set_last_login(user_id, SystemTime::now())
+                .and_then(|_| login_events_repo.create(NewLoginEvent::new(user_id, provider, client, None)));
+            if let Err(e) = res {
+                error!("Recording sign-in of user {} failed: {}", user_id, e);
+            }
+            Ok(())
+        })
+    }
+
+    /// Records failed sign-in of the user with specified email, attempts to sign in
+    /// with unknown emails are not recorded. Recording errors are only logged.
+    fn record_login_failure(&self, email: String, provider: Provider, client: LoginClient, error: &FailureError) -> ServiceFuture<()> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let failure_reason = login_failure_reason(error);
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            let login_events_repo = repo_factory.create_login_events_repo_with_sys_acl(&*conn);
+
+            let res = users_repo.find_by_email(email.clone()).and_then(|user| match user {
+                Some(user) => login_events_repo
+                    .create(NewLoginEvent::new(user.id, provider, client, Some(failure_reason)))
+                    .map(|_| ()),
+                None => Ok(()),
+            });
+            if let Err(e) = res {
+                error!("Recording failed sign-in of user with email {} failed: {}", email, e);
+            }
+            Ok(())
+        })
+    }
+
+    /// Checks sign-in of the user against devices and countries of its previous sign-ins.
+    /// Suspicious sign-in is reported with `suspicious_login` security event and fails
+    /// with `login.confirmation_required` error, if confirmation by email is required.
+    fn check_login(&self, user_id: UserId, provider: Provider, client: LoginClient) -> ServiceFuture<()> {
+        let config = self.static_context.config.suspicious_login.clone();
+        if !config.enabled {
+            return Box::new(future::ok(()));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service = self.clone();
+
+        let fut = self
+            .spawn_on_pool(move |conn| {
+                let login_events_repo = repo_factory.create_login_events_repo_with_sys_acl(&*conn);
+                let login_confirmations_repo = repo_factory.create_login_confirmations_repo(&*conn);
+
+                let history = login_events_repo.list_succeeded_for_user(user_id, config.history_size)?;
+                let reasons = suspicious_login_reasons(&config, &client, &history);
+                if reasons.is_empty() {
+                    return Ok(None);
+                }
+
+                // token is sent by email, webhooks only learn that the confirmation is required
+                if config.require_confirmation {
+                    let expires_at = SystemTime::now() + Duration::from_secs(config.confirmation_expiration_s);
+                    let payload = NewLoginConfirmation::new(user_id, provider.clone(), client.clone(), expires_at);
+                    login_confirmations_repo.create(payload)?;
+                }
+
+                Ok(Some(SuspiciousLogin {
+                    provider,
+                    client,
+                    reasons,
+                    confirmation_required: config.require_confirmation,
+                }))
+            })
+            .and_then(move |suspicious_login| -> ServiceFuture<()> {
+                match suspicious_login {
+                    Some(suspicious_login) => {
+                        warn!("Suspicious sign-in of user {}: {:?}", user_id, suspicious_login.reasons);
+                        let confirmation_required = suspicious_login.confirmation_required;
+                        let data = serde_json::to_value(&suspicious_login).ok();
+                        let event = SecurityEvent::new(SecurityEventType::SuspiciousLogin, user_id, data);
+                        Box::new(service.emit_security_event(event).and_then(move |_| {
+                            if confirmation_required {
+                                Err(Error::Validate(
+                                    validation_errors!({"login": ["confirmation_required" => "Sign-in must be confirmed by email"]}),
+                                )
+                                .into())
+                            } else {
+                                Ok(())
+                            }
+                        }))
+                    }
+                    None => Box::new(future::ok(())),
+                }
+            })
+            .map_err(|e: FailureError| e.context("Service login events, check_login endpoint error occured.").into());
+
+        Box::new(fut)
+    }
+
+    /// Confirms suspicious sign-in by token from email
+    fn confirm_login(&self, token: String) -> ServiceFuture<LoginConfirmation> {
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let login_confirmations_repo = repo_factory.create_login_confirmations_repo(&*conn);
+            login_confirmations_repo
+                .confirm(token)
+                .and_then(|confirmation| {
+                    confirmation.ok_or_else(|| {
+                        Error::Validate(validation_errors!({"token": ["expired" => "Confirmation token is invalid or expired"]})).into()
+                    })
+                })
+                .map_err(|e: FailureError| e.context("Service login events, confirm_login endpoint error occured.").into())
+        })
+    }
+
+    /// Returns token of the pending sign-in confirmation of the user to be sent by email
+    fn get_login_confirmation_token(&self, user_id: UserId) -> ServiceFuture<String> {
+        if !self.dynamic_context.is_super_admin() {
+            return Box::new(future::err(Error::Forbidden.context("Cannot get login confirmation token").into()));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let login_confirmations_repo = repo_factory.create_login_confirmations_repo(&*conn);
+            login_confirmations_repo
+                .find_pending(user_id)?
+                .map(|confirmation| confirmation.token)
+                .ok_or_else(|| Error::NotFound.context("Token not found").into())
+        })
+        .map_err(|e: FailureError| {
+            e.context("Service login events, get_login_confirmation_token endpoint error occured.")
+                .into()
+        })
+    }
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > Service<T, M, F>
+{
+    /// Describes client of the current request: its address, user agent,
+    /// fingerprint of the device and country of the address
+    pub fn login_client(&self) -> LoginClient {
+        let ip = self.dynamic_context.client_ip.clone();
+        let user_agent = self.dynamic_context.user_agent.clone();
+        let device = user_agent.as_ref().map(|user_agent| device_fingerprint(user_agent));
+        let country = match (&self.static_context.geoip, &ip) {
+            (Some(geoip), Some(ip)) => geoip.country(ip),
+            _ => None,
+        };
+
+        LoginClient {
+            ip,
+            user_agent,
+            device,
+            country,
+        }
+    }
+}
+
+/// Hex encoded SHA-256 of the user agent
+pub fn device_fingerprint(user_agent: &str) -> String {
+    let mut hasher = Sha256::default();
+    hasher.input(user_agent.as_bytes());
+    hasher.result().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Reasons to consider sign-in of the client suspicious, given latest successful sign-ins
+/// of the user. Devices and countries are compared only to the ones recorded before,
+/// so that unknown device or country of the client is never suspicious.
+pub fn suspicious_login_reasons(
+    config: &SuspiciousLoginConfig,
+    client: &LoginClient,
+    history: &[LoginEvent],
+) -> Vec<SuspiciousLoginReason> {
+    let mut reasons = vec![];
+    if !config.enabled || (history.len() as i64) < config.min_history_size.max(1) {
+        return reasons;
+    }
+
+    if config.detect_new_device && is_new(&client.device, history.iter().map(|event| &event.device)) {
+        reasons.push(SuspiciousLoginReason::NewDevice);
+    }
+    if config.detect_new_country && is_new(&client.country, history.iter().map(|event| &event.country)) {
+        reasons.push(SuspiciousLoginReason::NewCountry);
+    }
+
+    reasons
+}
+
+fn is_new<'a, I: Iterator<Item = &'a Option<String>>>(value: &Option<String>, known: I) -> bool {
+    let known = known.filter_map(|known| known.as_ref()).collect::<Vec<_>>();
+    match value {
+        Some(value) => !known.is_empty() && !known.contains(&value),
+        None => false,
+    }
+}
+
+/// Short machine readable reason of failed sign-in, e.g. `email.blocked` or `password.password`
+pub fn login_failure_reason(error: &FailureError) -> String {
+    match error.causes().filter_map(|cause| cause.downcast_ref::<Error>()).next() {
+        Some(Error::Validate(errors)) => errors
+            .clone()
+            .inner()
+            .into_iter()
+            .flat_map(|(field, errors)| errors.into_iter().map(move |error| format!("{}.{}", field, error.code)))
+            .next()
+            .unwrap_or_else(|| "validation".to_string()),
+        Some(Error::NotFound) => "not_found".to_string(),
+        Some(Error::Forbidden) | Some(Error::InvalidToken) => "forbidden".to_string(),
+        _ => "internal".to_string(),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use failure::Error as FailureError;
+    use failure::Fail;
+    use tokio_core::reactor::Core;
+
+    use stq_static_resources::Provider;
+    use stq_types::UserId;
+
+    use config::Config;
+    use errors::Error;
+    use models::{LoginClient, SuspiciousLoginReason};
+    use repos::repo_factory::tests::*;
+    use services::login_events::{device_fingerprint, login_failure_reason, suspicious_login_reasons, LoginEventsService};
+
+    #[test]
+    fn test_list_login_events() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.list_login_events(5);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 5);
+        assert!(result.iter().all(|event| event.user_id == UserId(1)));
+    }
+
+    #[test]
+    fn test_list_login_events_unauthorized() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.list_login_events(5);
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_login_failure_reason() {
+        let error: FailureError = Error::Validate(validation_errors!({"email": ["blocked" => "Email is blocked"]}))
+            .context("Service jwt, create_token_email endpoint error occured.")
+            .into();
+        assert_eq!(login_failure_reason(&error), "email.blocked");
+        assert_eq!(login_failure_reason(&FailureError::from(Error::NotFound)), "not_found");
+    }
+
+    #[test]
+    fn test_suspicious_login_reasons() {
+        let config = Config::new().unwrap().suspicious_login;
+        let mut event = create_login_event(UserId(1));
+        event.device = Some(device_fingerprint("Firefox"));
+        event.country = Some("RUS".to_string());
+        let history = vec![event];
+
+        let known_client = LoginClient {
+            ip: Some("127.0.0.1".to_string()),
+            user_agent: Some("Firefox".to_string()),
+            device: Some(device_fingerprint("Firefox")),
+            country: Some("RUS".to_string()),
+        };
+        assert!(suspicious_login_reasons(&config, &known_client, &history).is_empty());
+
+        let new_client = LoginClient {
+            device: Some(device_fingerprint("Chrome")),
+            country: Some("USA".to_string()),
+            ..known_client.clone()
+        };
+        assert_eq!(
+            suspicious_login_reasons(&config, &new_client, &history),
+            vec![SuspiciousLoginReason::NewDevice, SuspiciousLoginReason::NewCountry]
+        );
+        assert!(suspicious_login_reasons(&config, &new_client, &[]).is_empty());
+        assert!(suspicious_login_reasons(&config, &LoginClient::default(), &history).is_empty());
+    }
+
+    #[test]
+    fn test_check_login_without_history() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let client = LoginClient {
+            user_agent: Some("Firefox".to_string()),
+            device: Some(device_fingerprint("Firefox")),
+            ..LoginClient::default()
+        };
+        let work = service.check_login(UserId(1), Provider::Email, client);
+        assert!(core.run(work).is_ok());
+    }
+
+    #[test]
+    fn test_confirm_login() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.confirm_login(MOCK_TOKEN.to_string());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_id, UserId(1));
+        let work = service.confirm_login("unknown".to_string());
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_get_login_confirmation_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle.clone());
+        let work = service.get_login_confirmation_token(UserId(2));
+        let result = core.run(work).unwrap();
+        assert_eq!(result, MOCK_TOKEN.to_string());
+        let service = create_service(Some(UserId(2)), handle);
+        let work = service.get_login_confirmation_token(UserId(2));
+        assert!(core.run(work).is_err());
+    }
+}
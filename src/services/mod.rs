@@ -1,11 +1,23 @@
 //! Services is a core layer for the app business logic like
 //! validation, authorization, etc.
 
+pub mod account_deletion;
+pub mod api_tokens;
+pub mod geoip;
+pub mod impersonation;
 pub mod jwt;
+pub mod login_events;
 pub mod mocks;
+pub mod password_policy;
+pub mod referrals;
+pub mod service_clients;
 pub mod types;
+pub mod user_notes;
 pub mod user_roles;
 pub mod users;
+pub mod users_export;
+pub mod users_import;
 pub mod util;
+pub mod webhooks;
 
 pub use self::types::Service;
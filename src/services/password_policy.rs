@@ -0,0 +1,220 @@
+//! Password policy, checks passwords chosen by users against rules
+//! configured in `Config.password_policy`. Every failed rule is reported
+//! as separate validation error with the rule as its code.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use failure::Error as FailureError;
+use failure::Fail;
+use serde_json::Value;
+use validator::{ValidationError, ValidationErrors};
+
+use config::PasswordPolicy as PasswordPolicyConfig;
+
+/// Shorter local parts of emails are not banned, they would reject too many passwords
+const MIN_BANNED_EMAIL_LOCAL_PART_LEN: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    config: PasswordPolicyConfig,
+    /// Lowercase common passwords
+    common_passwords: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    /// Creates policy from config, common passwords are read from the configured file
+    pub fn from_config(config: &PasswordPolicyConfig) -> Result<Self, FailureError> {
+        let common_passwords = match config.common_passwords_path {
+            Some(ref path) => read_common_passwords(path)?,
+            None => HashSet::new(),
+        };
+        Ok(Self::new(config.clone(), common_passwords))
+    }
+
+    pub fn new(config: PasswordPolicyConfig, common_passwords: HashSet<String>) -> Self {
+        let common_passwords = common_passwords.into_iter().map(|password| password.to_lowercase()).collect();
+        Self { config, common_passwords }
+    }
+
+    /// Checks password against all rules, failed rules are reported under `field`
+    pub fn check(&self, field: &'static str, password: &str, email: Option<&str>) -> Result<(), ValidationErrors> {
+        let mut failed = vec![];
+        let length = password.chars().count();
+        let lowercase_password = password.to_lowercase();
+
+        if length < self.config.min_length {
+            failed.push(rule_error(
+                "min_length",
+                format!("Password must be at least {} characters long", self.config.min_length),
+                vec![("min", Value::from(self.config.min_length))],
+            ));
+        }
+        if length > self.config.max_length {
+            failed.push(rule_error(
+                "max_length",
+                format!("Password must be at most {} characters long", self.config.max_length),
+                vec![("max", Value::from(self.config.max_length))],
+            ));
+        }
+        if self.config.require_lowercase && !password.chars().any(char::is_lowercase) {
+            failed.push(rule_error(
+                "lowercase",
+                "Password must contain a lowercase letter".to_string(),
+                vec![],
+            ));
+        }
+        if self.config.require_uppercase && !password.chars().any(char::is_uppercase) {
+            failed.push(rule_error(
+                "uppercase",
+                "Password must contain an uppercase letter".to_string(),
+                vec![],
+            ));
+        }
+        if self.config.require_digit && !password.chars().any(|c| c.is_numeric()) {
+            failed.push(rule_error("digit", "Password must contain a digit".to_string(), vec![]));
+        }
+        if self.config.require_special && !password.chars().any(|c| !c.is_alphanumeric()) {
+            failed.push(rule_error(
+                "special",
+                "Password must contain a special character".to_string(),
+                vec![],
+            ));
+        }
+
+        let banned_substrings = self
+            .config
+            .banned_substrings
+            .iter()
+            .map(|substring| substring.to_lowercase())
+            .filter(|substring| !substring.is_empty() && lowercase_password.contains(substring.as_str()))
+            .collect::<Vec<_>>();
+        if !banned_substrings.is_empty() {
+            failed.push(rule_error(
+                "banned_substring",
+                "Password contains banned words".to_string(),
+                vec![("substrings", Value::from(banned_substrings))],
+            ));
+        }
+
+        if self.config.ban_email_local_part {
+            let local_part = email
+                .and_then(|email| email.split('@').next())
+                .map(|local_part| local_part.to_lowercase());
+            if let Some(local_part) = local_part {
+                if local_part.chars().count() >= MIN_BANNED_EMAIL_LOCAL_PART_LEN && lowercase_password.contains(local_part.as_str()) {
+                    failed.push(rule_error("email", "Password must not contain the email".to_string(), vec![]));
+                }
+            }
+        }
+
+        if self.common_passwords.contains(&lowercase_password) {
+            failed.push(rule_error("common_password", "Password is too common".to_string(), vec![]));
+        }
+
+        if failed.is_empty() {
+            return Ok(());
+        }
+        let mut errors = ValidationErrors::new();
+        for error in failed {
+            errors.add(field, error);
+        }
+        Err(errors)
+    }
+}
+
+fn rule_error(code: &'static str, message: String, params: Vec<(&'static str, Value)>) -> ValidationError {
+    ValidationError {
+        code: Cow::from(code),
+        message: Some(Cow::from(message)),
+        params: params
+            .into_iter()
+            .map(|(name, value)| (Cow::from(name), value))
+            .collect::<HashMap<_, _>>(),
+    }
+}
+
+/// Reads common passwords, one per line, empty lines and comments are skipped
+fn read_common_passwords(path: &str) -> Result<HashSet<String>, FailureError> {
+    let file = File::open(path).map_err(|e| e.context(format!("Could not open common passwords {}", path)))?;
+    let mut common_passwords = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| e.context(format!("Could not read common passwords {}", path)))?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            common_passwords.insert(line.to_string());
+        }
+    }
+    Ok(common_passwords)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_config() -> PasswordPolicyConfig {
+        PasswordPolicyConfig {
+            min_length: 8,
+            max_length: 128,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_special: true,
+            banned_substrings: vec!["Storiqa".to_string()],
+            ban_email_local_part: true,
+            common_passwords_path: None,
+            history_size: 0,
+        }
+    }
+
+    fn failed_rules(result: Result<(), ValidationErrors>) -> Vec<String> {
+        let errors = result.unwrap_err().inner();
+        let mut rules = errors["password"].iter().map(|error| error.code.to_string()).collect::<Vec<_>>();
+        rules.sort();
+        rules
+    }
+
+    #[test]
+    fn test_valid_password() {
+        let policy = PasswordPolicy::new(create_config(), HashSet::new());
+        assert!(policy
+            .check("password", "Correct-Horse-Battery-Staple-1", Some("john@mail.com"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_long_password() {
+        let policy = PasswordPolicy::new(create_config(), HashSet::new());
+        let password = format!("Aa1-{}", "x".repeat(60));
+        assert!(policy.check("password", &password, None).is_ok());
+        let password = format!("Aa1-{}", "x".repeat(200));
+        assert_eq!(failed_rules(policy.check("password", &password, None)), vec!["max_length"]);
+    }
+
+    #[test]
+    fn test_every_failed_rule_is_reported() {
+        let policy = PasswordPolicy::new(create_config(), HashSet::new());
+        assert_eq!(
+            failed_rules(policy.check("password", "john", Some("john@mail.com"))),
+            vec!["digit", "email", "min_length", "special", "uppercase"]
+        );
+        assert_eq!(
+            failed_rules(policy.check("password", "MY-STORIQA-PASSWORD", None)),
+            vec!["banned_substring", "digit", "lowercase"]
+        );
+    }
+
+    #[test]
+    fn test_common_password() {
+        let mut common_passwords = HashSet::new();
+        common_passwords.insert("Qwerty-123456".to_string());
+        let policy = PasswordPolicy::new(create_config(), common_passwords);
+        assert_eq!(
+            failed_rules(policy.check("password", "QWERTY-123456", None)),
+            vec!["common_password", "lowercase"]
+        );
+        assert!(policy.check("password", "Qwerty-1234567", None).is_ok());
+    }
+}
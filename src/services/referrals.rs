@@ -0,0 +1,171 @@
+//! Referrals Services, presents referral tree and referral program statistics
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future;
+use r2d2::ManageConnection;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::{Referral, ReferralStats, TopReferrer};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait ReferralsService {
+    /// Returns direct referrals of the user
+    fn list_referrals(&self, user_id: UserId, offset: i64, count: i64) -> ServiceFuture<Vec<Referral>>;
+    /// Returns referral statistics of the user for the last `days` days
+    fn user_referral_stats(&self, user_id: UserId, days: u64, max_depth: i32) -> ServiceFuture<ReferralStats>;
+    /// Returns referral statistics of the whole program for the last `days` days
+    fn referral_stats(&self, days: u64) -> ServiceFuture<ReferralStats>;
+    /// Returns users with the most referrals
+    fn top_referrers(&self, count: i64) -> ServiceFuture<Vec<TopReferrer>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ReferralsService for Service<T, M, F>
+{
+    /// Returns direct referrals of the user
+    fn list_referrals(&self, user_id: UserId, offset: i64, count: i64) -> ServiceFuture<Vec<Referral>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo(&*conn, current_uid);
+            let referrals_repo = repo_factory.create_referrals_repo(&*conn, current_uid);
+
+            users_repo
+                .find(user_id)?
+                .ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)))?;
+            referrals_repo
+                .list(user_id, offset, count)
+                .map_err(|e: FailureError| e.context("Service referrals, list endpoint error occured.").into())
+        })
+    }
+
+    /// Returns referral statistics of the user for the last `days` days
+    fn user_referral_stats(&self, user_id: UserId, days: u64, max_depth: i32) -> ServiceFuture<ReferralStats> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let since = match days_ago(days) {
+            Ok(since) => since,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo(&*conn, current_uid);
+            let referrals_repo = repo_factory.create_referrals_repo(&*conn, current_uid);
+
+            users_repo
+                .find(user_id)?
+                .ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)))?;
+            referrals_repo
+                .stats(Some(user_id), since, Some(max_depth))
+                .map_err(|e: FailureError| e.context("Service referrals, user_stats endpoint error occured.").into())
+        })
+    }
+
+    /// Returns referral statistics of the whole program for the last `days` days
+    fn referral_stats(&self, days: u64) -> ServiceFuture<ReferralStats> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let since = match days_ago(days) {
+            Ok(since) => since,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        self.spawn_on_pool(move |conn| {
+            let referrals_repo = repo_factory.create_referrals_repo(&*conn, current_uid);
+            referrals_repo
+                .stats(None, since, None)
+                .map_err(|e: FailureError| e.context("Service referrals, stats endpoint error occured.").into())
+        })
+    }
+
+    /// Returns users with the most referrals
+    fn top_referrers(&self, count: i64) -> ServiceFuture<Vec<TopReferrer>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let referrals_repo = repo_factory.create_referrals_repo(&*conn, current_uid);
+            referrals_repo
+                .top_referrers(count)
+                .map_err(|e: FailureError| e.context("Service referrals, top_referrers endpoint error occured.").into())
+        })
+    }
+}
+
+/// Returns the moment `days` days ago, fails with validation error if it is before unix epoch
+fn days_ago(days: u64) -> Result<SystemTime, FailureError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok();
+    days.checked_mul(24 * 60 * 60)
+        .and_then(|secs| now.and_then(|now| now.checked_sub(Duration::from_secs(secs))))
+        .map(|since| UNIX_EPOCH + since)
+        .ok_or_else(|| Error::Validate(validation_errors!({"days": ["range" => "Number of days is too large"]})).into())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use repos::repo_factory::tests::*;
+    use services::referrals::ReferralsService;
+
+    #[test]
+    fn test_list_referrals() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.list_referrals(UserId(1), 0, 5);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 5);
+    }
+
+    #[test]
+    fn test_user_referral_stats() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.user_referral_stats(UserId(1), 30, 3);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.referrer, Some(UserId(1)));
+        assert_eq!(result.total, result.verified + result.unverified);
+        assert_eq!(result.by_depth.map(|by_depth| by_depth.len()), Some(3));
+    }
+
+    #[test]
+    fn test_referral_stats_with_too_many_days() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.referral_stats(u64::max_value());
+        let result = core.run(work);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_top_referrers() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.top_referrers(3);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 3);
+        assert!(result[0].referrals >= result[1].referrals);
+    }
+}
@@ -0,0 +1,251 @@
+//! Service clients Services, registers internal services and issues them
+//! access tokens by OAuth 2.0 client credentials grant
+
+use base64::encode;
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use errors::Error;
+use models::{
+    ClientCredentialsRequest, CreatedServiceClient, NewServiceClient, NewServiceClientPayload, ServiceAccessToken, ServiceClient,
+    ServicePrincipal, ServiceTokenPayload, BEARER_TOKEN_TYPE, CLIENT_CREDENTIALS_GRANT_TYPE,
+};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::util::{password_create, password_verify};
+use services::Service;
+
+pub trait ServiceClientsService {
+    /// Returns all registered service clients
+    fn list_service_clients(&self) -> ServiceFuture<Vec<ServiceClient>>;
+    /// Registers new service client, its secret is returned only once
+    fn create_service_client(&self, payload: NewServiceClientPayload) -> ServiceFuture<CreatedServiceClient>;
+    /// Issues access token to service client by client credentials grant
+    fn create_service_token(&self, payload: ClientCredentialsRequest) -> ServiceFuture<ServiceAccessToken>;
+    /// Deactivates service client, so that its issued tokens are rejected
+    fn deactivate_service_client(&self, client_id: String) -> ServiceFuture<ServiceClient>;
+    /// Checks that service client of the token is still registered and active
+    fn verify_service_client(&self, principal: ServicePrincipal) -> ServiceFuture<ServiceClient>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > ServiceClientsService for Service<T, M, F>
+{
+    /// Returns all registered service clients
+    fn list_service_clients(&self) -> ServiceFuture<Vec<ServiceClient>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let service_clients_repo = repo_factory.create_service_clients_repo(&*conn, current_uid);
+            service_clients_repo
+                .list()
+                .map_err(|e: FailureError| e.context("Service service clients, list endpoint error occured.").into())
+        })
+    }
+
+    /// Registers new service client, its secret is returned only once
+    fn create_service_client(&self, payload: NewServiceClientPayload) -> ServiceFuture<CreatedServiceClient> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Registering service client {} with role {}", payload.name, payload.role);
+
+        self.spawn_on_pool(move |conn| {
+            let service_clients_repo = repo_factory.create_service_clients_repo(&*conn, current_uid);
+            let client_secret = encode(&format!("{}{}", Uuid::new_v4(), Uuid::new_v4()));
+            service_clients_repo
+                .create(NewServiceClient {
+                    client_id: Uuid::new_v4().to_string(),
+                    name: payload.name,
+                    secret_hash: password_create(client_secret.clone()),
+                    role: payload.role,
+                })
+                .map(|client| CreatedServiceClient {
+                    client_id: client.client_id,
+                    client_secret,
+                    name: client.name,
+                    role: client.role,
+                })
+                .map_err(|e: FailureError| e.context("Service service clients, create endpoint error occured.").into())
+        })
+    }
+
+    /// Issues access token to service client by client credentials grant
+    fn create_service_token(&self, payload: ClientCredentialsRequest) -> ServiceFuture<ServiceAccessToken> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service_expiration_s = self.static_context.config.tokens.service_expiration_s;
+        let jwt_signer = self.static_context.jwt_signer.clone();
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                if payload.grant_type != CLIENT_CREDENTIALS_GRANT_TYPE {
+                    return Err(Error::Validate(
+                        validation_errors!({"grant_type": ["unsupported_grant_type" => "Only client_credentials grant is supported"]}),
+                    )
+                    .into());
+                }
+
+                let service_clients_repo = repo_factory.create_service_clients_repo_with_sys_acl(&*conn);
+                let client_secret = payload.client_secret;
+                let client = service_clients_repo
+                    .find(payload.client_id.clone())?
+                    .filter(|client| client.is_active)
+                    .filter(|client| password_verify(&client.secret_hash, client_secret.clone()).unwrap_or(false))
+                    .ok_or_else(|| {
+                        error!("Client credentials of service client {} are invalid.", payload.client_id);
+                        Error::Validate(validation_errors!({"client": ["invalid_client" => "Client credentials are invalid"]}))
+                    })?;
+
+                let token_payload =
+                    ServiceTokenPayload::new(client.client_id, client.role, Utc::now().timestamp() + service_expiration_s as i64);
+                let access_token = jwt_signer.sign(&token_payload)?;
+
+                Ok(ServiceAccessToken {
+                    access_token,
+                    token_type: BEARER_TOKEN_TYPE.to_string(),
+                    expires_in: service_expiration_s,
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service service clients, create_token endpoint error occured.").into()),
+        )
+    }
+
+    /// Deactivates service client, so that its issued tokens are rejected
+    fn deactivate_service_client(&self, client_id: String) -> ServiceFuture<ServiceClient> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Deactivating service client {}", client_id);
+
+        Box::new(
+            self.spawn_on_pool(move |conn| {
+                let service_clients_repo = repo_factory.create_service_clients_repo(&*conn, current_uid);
+                service_clients_repo.deactivate(client_id.clone())?.ok_or_else(|| {
+                    format_err!("Service client {} not found", client_id)
+                        .context(Error::NotFound)
+                        .into()
+                })
+            })
+            .map_err(|e: FailureError| e.context("Service service clients, deactivate endpoint error occured.").into()),
+        )
+    }
+
+    /// Checks that service client of the token is still registered and active
+    fn verify_service_client(&self, principal: ServicePrincipal) -> ServiceFuture<ServiceClient> {
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let service_clients_repo = repo_factory.create_service_clients_repo_with_sys_acl(&*conn);
+            service_clients_repo
+                .find(principal.client_id.clone())?
+                .filter(|client| client.is_active && client.role == principal.role)
+                .ok_or_else(|| {
+                    Error::InvalidToken
+                        .context(format!("Service client {} is not active", principal.client_id))
+                        .into()
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::{ClientCredentialsRequest, NewServiceClientPayload, ServicePrincipal, ServiceRole};
+    use repos::repo_factory::tests::*;
+    use services::service_clients::ServiceClientsService;
+
+    fn create_credentials(grant_type: &str, client_secret: &str) -> ClientCredentialsRequest {
+        ClientCredentialsRequest {
+            grant_type: grant_type.to_string(),
+            client_id: MOCK_CLIENT_ID.to_string(),
+            client_secret: client_secret.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_create_service_client() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = NewServiceClientPayload {
+            name: "saga".to_string(),
+            role: ServiceRole::Saga,
+        };
+        let work = service.create_service_client(payload);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.role, ServiceRole::Saga);
+        assert!(!result.client_secret.is_empty());
+    }
+
+    #[test]
+    fn test_create_service_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.create_service_token(create_credentials("client_credentials", MOCK_PASSWORD));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.token_type, "Bearer");
+    }
+
+    #[test]
+    fn test_create_service_token_with_wrong_secret() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.create_service_token(create_credentials("client_credentials", "wrong"));
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_create_service_token_with_unsupported_grant() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.create_service_token(create_credentials("password", MOCK_PASSWORD));
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_deactivate_service_client() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.deactivate_service_client(MOCK_CLIENT_ID.to_string());
+        let result = core.run(work).unwrap();
+        assert!(!result.is_active);
+    }
+
+    #[test]
+    fn test_verify_service_client() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let principal = ServicePrincipal {
+            client_id: MOCK_CLIENT_ID.to_string(),
+            role: ServiceRole::Saga,
+        };
+        assert!(core.run(service.verify_service_client(principal)).is_ok());
+
+        let principal = ServicePrincipal {
+            client_id: "unknown".to_string(),
+            role: ServiceRole::Saga,
+        };
+        assert!(core.run(service.verify_service_client(principal)).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use diesel::pg::Pg;
 use diesel::Connection;
 use failure::Error as FailureError;
 use failure::Fail;
-use futures::Future;
+use futures::{Future, Stream};
 use r2d2::{ManageConnection, PooledConnection};
 
 use controller::context::{DynamicContext, StaticContext};
@@ -13,6 +13,9 @@ use repos::repo_factory::*;
 /// Service layer Future
 pub type ServiceFuture<T> = Box<Future<Item = T, Error = FailureError>>;
 
+/// Service layer Stream
+pub type ServiceStream<T> = Box<Stream<Item = T, Error = FailureError>>;
+
 /// Service
 pub struct Service<T, M, F>
 where
@@ -0,0 +1,121 @@
+//! User notes Services, presents internal notes of support staff on users accounts
+
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use r2d2::ManageConnection;
+use uuid::Uuid;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::{NewUserNote, NewUserNotePayload, UserNote};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait UserNotesService {
+    /// Returns notes on specific user
+    fn list_user_notes(&self, user_id: UserId) -> ServiceFuture<Vec<UserNote>>;
+    /// Creates note on specific user, authored by the current user
+    fn create_user_note(&self, user_id: UserId, payload: NewUserNotePayload) -> ServiceFuture<UserNote>;
+    /// Deletes specific note on the user
+    fn delete_user_note(&self, user_id: UserId, id: Uuid) -> ServiceFuture<UserNote>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > UserNotesService for Service<T, M, F>
+{
+    /// Returns notes on specific user
+    fn list_user_notes(&self, user_id: UserId) -> ServiceFuture<Vec<UserNote>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let user_notes_repo = repo_factory.create_user_notes_repo(&*conn, current_uid);
+            user_notes_repo
+                .list_for_user(user_id)
+                .map_err(|e: FailureError| e.context("Service user notes, list endpoint error occured.").into())
+        })
+    }
+
+    /// Creates note on specific user, authored by the current user
+    fn create_user_note(&self, user_id: UserId, payload: NewUserNotePayload) -> ServiceFuture<UserNote> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo(&*conn, current_uid);
+            let user_notes_repo = repo_factory.create_user_notes_repo(&*conn, current_uid);
+            users_repo
+                .find(user_id)
+                .and_then(|user| user.ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)).into()))
+                .and_then(|_| {
+                    user_notes_repo.create(NewUserNote {
+                        user_id,
+                        author_id: current_uid,
+                        text: payload.text,
+                    })
+                })
+                .map_err(|e: FailureError| e.context("Service user notes, create endpoint error occured.").into())
+        })
+    }
+
+    /// Deletes specific note on the user
+    fn delete_user_note(&self, user_id: UserId, id: Uuid) -> ServiceFuture<UserNote> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let user_notes_repo = repo_factory.create_user_notes_repo(&*conn, current_uid);
+            user_notes_repo
+                .delete(user_id, id)
+                .and_then(|note| note.ok_or_else(|| Error::NotFound.context(format!("Note {} on user {} not found", id, user_id)).into()))
+                .map_err(|e: FailureError| e.context("Service user notes, delete endpoint error occured.").into())
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    use stq_types::UserId;
+
+    use models::NewUserNotePayload;
+    use repos::repo_factory::tests::*;
+    use services::user_notes::UserNotesService;
+
+    #[test]
+    fn test_create_user_note() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = NewUserNotePayload {
+            text: "Previous contact by phone".to_string(),
+        };
+        let work = service.create_user_note(UserId(2), payload);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.user_id, UserId(2));
+        assert_eq!(result.author_id, Some(UserId(1)));
+    }
+
+    #[test]
+    fn test_delete_user_note() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let id = Uuid::new_v4();
+        let work = service.delete_user_note(UserId(2), id);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, id);
+    }
+}
@@ -4,26 +4,29 @@ use diesel::connection::AnsiTransactionManager;
 use diesel::pg::Pg;
 use diesel::Connection;
 use failure::Error as FailureError;
+use futures::future;
 use r2d2::ManageConnection;
 
 use stq_types::{RoleId, UserId, UsersRole};
 
 use models::{NewUserRole, RemoveUserRole, UserRole};
 use repos::ReposFactory;
+use services::impersonation::forbid_impersonation;
 use services::types::ServiceFuture;
+use services::util::check_entity_tag;
 use services::Service;
 
 pub trait UserRolesService {
     /// Returns role by user ID
     fn get_roles(&self, user_id: UserId) -> ServiceFuture<Vec<UsersRole>>;
-    /// Creates new user_role
-    fn create_user_role(&self, payload: NewUserRole) -> ServiceFuture<UserRole>;
-    /// Remove user_role
-    fn delete_user_role(&self, payload: RemoveUserRole) -> ServiceFuture<UserRole>;
-    /// Deletes roles for user
-    fn delete_user_role_by_user_id(&self, user_id_arg: UserId) -> ServiceFuture<Vec<UserRole>>;
-    /// Deletes role for user by id
-    fn delete_user_role_by_id(&self, id_arg: RoleId) -> ServiceFuture<UserRole>;
+    /// Creates new user_role if user still matches `if_match` entity tags
+    fn create_user_role(&self, payload: NewUserRole, if_match: Option<Vec<String>>) -> ServiceFuture<UserRole>;
+    /// Remove user_role if user still matches `if_match` entity tags
+    fn delete_user_role(&self, payload: RemoveUserRole, if_match: Option<Vec<String>>) -> ServiceFuture<UserRole>;
+    /// Deletes roles for user if user still matches `if_match` entity tags
+    fn delete_user_role_by_user_id(&self, user_id_arg: UserId, if_match: Option<Vec<String>>) -> ServiceFuture<Vec<UserRole>>;
+    /// Deletes role for user by id if user still matches `if_match` entity tags
+    fn delete_user_role_by_id(&self, id_arg: RoleId, if_match: Option<Vec<String>>) -> ServiceFuture<UserRole>;
 }
 
 impl<
@@ -46,52 +49,88 @@ impl<
     }
 
     /// Creates new user_role
-    fn create_user_role(&self, new_user_role: NewUserRole) -> ServiceFuture<UserRole> {
+    fn create_user_role(&self, new_user_role: NewUserRole, if_match: Option<Vec<String>>) -> ServiceFuture<UserRole> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "change roles") {
+            return Box::new(future::err(e));
+        }
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
         self.spawn_on_pool(move |conn| {
             let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
-            conn.transaction::<UserRole, FailureError, _>(move || user_roles_repo.create(new_user_role))
-                .map_err(|e: FailureError| e.context("Service user_roles, create endpoint error occured.").into())
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            conn.transaction::<UserRole, FailureError, _>(move || {
+                check_entity_tag(&*users_repo, new_user_role.user_id, &if_match)?;
+                let user_role = user_roles_repo.create(new_user_role)?;
+                users_repo.bump_roles_version(user_role.user_id)?;
+                Ok(user_role)
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, create endpoint error occured.").into())
         })
     }
 
     /// Remove user_role
-    fn delete_user_role(&self, user_role: RemoveUserRole) -> ServiceFuture<UserRole> {
+    fn delete_user_role(&self, user_role: RemoveUserRole, if_match: Option<Vec<String>>) -> ServiceFuture<UserRole> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "change roles") {
+            return Box::new(future::err(e));
+        }
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
         self.spawn_on_pool(move |conn| {
             let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
-            conn.transaction::<UserRole, FailureError, _>(move || user_roles_repo.delete_user_role(user_role.user_id, user_role.name))
-                .map_err(|e: FailureError| e.context("Service user_roles, delete_user_role endpoint error occured.").into())
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            conn.transaction::<UserRole, FailureError, _>(move || {
+                check_entity_tag(&*users_repo, user_role.user_id, &if_match)?;
+                let user_role = user_roles_repo.delete_user_role(user_role.user_id, user_role.name)?;
+                users_repo.bump_roles_version(user_role.user_id)?;
+                Ok(user_role)
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, delete_user_role endpoint error occured.").into())
         })
     }
 
     /// Deletes specific user role
-    fn delete_user_role_by_user_id(&self, user_id_arg: UserId) -> ServiceFuture<Vec<UserRole>> {
+    fn delete_user_role_by_user_id(&self, user_id_arg: UserId, if_match: Option<Vec<String>>) -> ServiceFuture<Vec<UserRole>> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "change roles") {
+            return Box::new(future::err(e));
+        }
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
         self.spawn_on_pool(move |conn| {
             let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
-            user_roles_repo
-                .delete_by_user_id(user_id_arg)
-                .map_err(|e: FailureError| e.context("Service user_roles, delete_by_user_id endpoint error occured.").into())
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            conn.transaction::<Vec<UserRole>, FailureError, _>(move || {
+                check_entity_tag(&*users_repo, user_id_arg, &if_match)?;
+                let user_roles = user_roles_repo.delete_by_user_id(user_id_arg)?;
+                users_repo.bump_roles_version(user_id_arg)?;
+                Ok(user_roles)
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, delete_by_user_id endpoint error occured.").into())
         })
     }
 
     /// Deletes role for user by id
-    fn delete_user_role_by_id(&self, id_arg: RoleId) -> ServiceFuture<UserRole> {
+    fn delete_user_role_by_id(&self, id_arg: RoleId, if_match: Option<Vec<String>>) -> ServiceFuture<UserRole> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "change roles") {
+            return Box::new(future::err(e));
+        }
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
         self.spawn_on_pool(move |conn| {
             let user_roles_repo = repo_factory.create_user_roles_repo(&*conn, current_uid);
-            user_roles_repo
-                .delete_by_id(id_arg)
-                .map_err(|e: FailureError| e.context("Service user_roles, delete_by_id endpoint error occured.").into())
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            conn.transaction::<UserRole, FailureError, _>(move || {
+                // owner of the role is known only after deleting it, the transaction
+                // is rolled back if the owner doesn't match preconditions
+                let user_role = user_roles_repo.delete_by_id(id_arg)?;
+                check_entity_tag(&*users_repo, user_role.user_id, &if_match)?;
+                users_repo.bump_roles_version(user_role.user_id)?;
+                Ok(user_role)
+            })
+            .map_err(|e: FailureError| e.context("Service user_roles, delete_by_id endpoint error occured.").into())
         })
     }
 }
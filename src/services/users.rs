@@ -9,27 +9,36 @@ use diesel::Connection;
 use failure::Error as FailureError;
 use failure::Fail;
 use futures::future;
-use futures::{Future, IntoFuture};
-use jsonwebtoken::{encode, Algorithm, Header};
+use futures::{Future, Stream};
 
 use r2d2::ManageConnection;
+use serde_json;
+use tokio_core::reactor::{Handle, Interval};
 use uuid::Uuid;
 
-use stq_static_resources::{Provider, TokenType};
+use stq_http::client::TimeLimitedHttpClient;
+use stq_static_resources::Provider;
 use stq_types::UserId;
 
 use super::types::ServiceFuture;
-use super::util::{password_create, password_verify};
+use super::util::{check_entity_tag, check_user_active, password_create, password_verify};
+use controller::context::{DynamicContext, DynamicContextServices, StaticContext};
 use errors::Error;
 use models::*;
 use repos::repo_factory::ReposFactory;
-use repos::UsersRepo;
+use repos::{PasswordHistoryRepo, UserDeletionsRepo, UsersRepo};
+use services::api_tokens::forbid_api_token;
+use services::impersonation::forbid_impersonation;
 use services::jwt::JWTService;
+use services::login_events::LoginEventsService;
+use services::webhooks::WebhooksService;
 use services::Service;
 
 pub trait UsersService {
     /// Returns user by ID
     fn get(&self, user_id: UserId) -> ServiceFuture<Option<User>>;
+    /// Returns users by IDs
+    fn get_batch(&self, user_ids: Vec<UserId>) -> ServiceFuture<UsersBatchResults>;
     /// Returns total user count
     fn count(&self, only_active_users: bool) -> ServiceFuture<i64>;
     /// Returns current user
@@ -38,6 +47,8 @@ pub trait UsersService {
     fn list(&self, from: UserId, count: i64) -> ServiceFuture<Vec<User>>;
     /// Deactivates specific user
     fn deactivate(&self, user_id: UserId) -> ServiceFuture<User>;
+    /// Reactivates specific deactivated user and cancels its pending deletion
+    fn reactivate(&self, user_id: UserId) -> ServiceFuture<User>;
     /// Deletes user by saga id
     fn delete_by_saga_id(&self, saga_id: String) -> ServiceFuture<User>;
     /// Delete user by id
@@ -45,27 +56,43 @@ pub trait UsersService {
     /// Creates new user
     fn create(&self, payload: NewIdentity, user_payload: Option<NewUser>) -> ServiceFuture<User>;
     /// Get existing reset token
-    fn get_existing_reset_token(&self, user: UserId, token_type: TokenType) -> ServiceFuture<ResetToken>;
+    fn get_existing_reset_token(&self, user: UserId, token_type: ResetTokenType) -> ServiceFuture<ResetToken>;
     /// Get email verification token
     fn get_email_verification_token(&self, email: String) -> ServiceFuture<String>;
     /// Verifies email
     fn verify_email(&self, token_arg: String) -> ServiceFuture<EmailVerifyApplyToken>;
-    /// Updates specific user
-    fn update(&self, user_id: UserId, payload: UpdateUser) -> ServiceFuture<User>;
+    /// Updates specific user if it still matches `if_match` entity tags
+    fn update(&self, user_id: UserId, payload: UpdateUser, if_match: Option<Vec<String>>) -> ServiceFuture<User>;
     /// Change user password
     fn change_password(&self, payload: ChangeIdentityPassword) -> ServiceFuture<String>;
     /// Get password reset token
     fn get_password_reset_token(&self, email_arg: String, uuid: Uuid) -> ServiceFuture<String>;
     /// Apply password reset
     fn password_reset_apply(&self, token: String, new_pass: String) -> ServiceFuture<ResetApplyToken>;
+    /// Get token for reactivation of deactivated account
+    fn get_reactivation_token(&self, email_arg: String, uuid: Uuid) -> ServiceFuture<String>;
+    /// Reactivates account by the token and cancels its pending deletion
+    fn reactivation_apply(&self, token: String) -> ServiceFuture<User>;
     /// Find by email
     fn find_by_email(&self, email: String) -> ServiceFuture<Option<User>>;
     /// Search users limited by `from`, `skip` and `count` parameters
     fn search(&self, from: Option<UserId>, skip: i64, count: i64, term: UsersSearchTerms) -> ServiceFuture<UserSearchResults>;
-    /// Set block status for specific user
-    fn set_block_status(&self, user_id: UserId, is_blocked: bool) -> ServiceFuture<User>;
+    /// Search users page by page using opaque cursors
+    fn search_page(&self, term: UsersSearchTerms, page: UsersSearchPage) -> ServiceFuture<UsersSearchPageResults>;
+    /// Blocks specific user permanently or until `blocked_until`, if it still matches `if_match` entity tags
+    fn block(&self, user_id: UserId, payload: BlockUser, if_match: Option<Vec<String>>) -> ServiceFuture<User>;
+    /// Lifts blocks of specific user if it still matches `if_match` entity tags
+    fn unblock(&self, user_id: UserId, if_match: Option<Vec<String>>) -> ServiceFuture<User>;
+    /// Returns history of blocks of specific user
+    fn list_blocks(&self, user_id: UserId) -> ServiceFuture<Vec<UserBlock>>;
+    /// Unblocks users, whose temporary blocks have expired. Returns ids of unblocked users.
+    fn lift_expired_blocks(&self) -> ServiceFuture<Vec<UserId>>;
     /// Fuzzy search users by email
     fn fuzzy_search_by_email(&self, term_email: String) -> ServiceFuture<Vec<User>>;
+    /// Fuzzy search users by email, names and phone ranked by similarity
+    fn fuzzy_search(&self, query: String, limit: i64) -> ServiceFuture<Vec<UserFuzzyMatch>>;
+    /// Returns signups made in `period` grouped by signup attribute
+    fn acquisition_report(&self, group_by: AcquisitionGroupBy, period: DateRange) -> ServiceFuture<AcquisitionReport>;
     /// Revoke all tokens for user
     fn revoke_tokens(&self, user_id: UserId, provider: Provider) -> ServiceFuture<String>;
 }
@@ -91,6 +118,21 @@ impl<
         })
     }
 
+    /// Returns users by IDs
+    fn get_batch(&self, user_ids: Vec<UserId>) -> ServiceFuture<UsersBatchResults> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Getting users {:?}", user_ids);
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+            users_repo
+                .find_many(user_ids)
+                .map_err(|e: FailureError| e.context("Service users, get_batch endpoint error occured.").into())
+        })
+    }
+
     /// Returns total user count
     fn count(&self, only_active_users: bool) -> ServiceFuture<i64> {
         let current_uid = self.dynamic_context.user_id;
@@ -154,17 +196,138 @@ impl<
         })
     }
 
-    /// Set block status for specific user
-    fn set_block_status(&self, user_id: UserId, is_blocked: bool) -> ServiceFuture<User> {
+    /// Reactivates specific deactivated user and cancels its pending deletion
+    fn reactivate(&self, user_id: UserId) -> ServiceFuture<User> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "reactivate accounts") {
+            return Box::new(future::err(e));
+        }
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service = self.clone();
+
+        debug!("Reactivating user {}", &user_id);
+
+        let fut = self
+            .spawn_on_pool(move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                let user_deletions_repo = repo_factory.create_user_deletions_repo(&conn);
+                conn.transaction::<User, FailureError, _>(move || {
+                    let user = users_repo
+                        .find(user_id)?
+                        .ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)))?;
+                    if user.is_active {
+                        return Err(
+                            Error::Validate(validation_errors!({"user": ["not_deactivated" => "Account is not deactivated"]})).into(),
+                        );
+                    }
+                    reactivate_user(&*users_repo, &*user_deletions_repo, user_id)
+                })
+                .map_err(|e: FailureError| e.context("Service users, reactivate endpoint error occured.").into())
+            })
+            .and_then(move |user| {
+                let event = SecurityEvent::new(SecurityEventType::AccountReactivated, user.id, None);
+                service.emit_security_event(event).map(|_| user)
+            });
+
+        Box::new(fut)
+    }
+
+    /// Blocks specific user permanently or until `blocked_until`, if it still matches `if_match` entity tags
+    fn block(&self, user_id: UserId, payload: BlockUser, if_match: Option<Vec<String>>) -> ServiceFuture<User> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service = self.clone();
+        let blocked_until = payload.blocked_until;
+        debug!("Blocking user {} until {:?}", &user_id, blocked_until);
+
+        if blocked_until.map(|until| until <= Utc::now()).unwrap_or(false) {
+            return Box::new(future::err(
+                Error::Validate(validation_errors!({"blocked_until": ["past" => "End of the block must be in the future"]})).into(),
+            ));
+        }
+
+        let fut = self
+            .spawn_on_pool(move |conn| {
+                let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+                let user_blocks_repo = repo_factory.create_user_blocks_repo(&conn, current_uid);
+                conn.transaction::<(User, UserBlock), FailureError, _>(move || {
+                    check_entity_tag(&*users_repo, user_id, &if_match)?;
+                    let user = users_repo.set_block_status(user_id, true, blocked_until.map(SystemTime::from))?;
+                    // new block replaces the previous one
+                    user_blocks_repo.lift(user_id, current_uid)?;
+                    let block = user_blocks_repo.create(NewUserBlock {
+                        user_id,
+                        reason: payload.reason,
+                        blocked_by: current_uid,
+                        blocked_until: blocked_until.map(SystemTime::from),
+                    })?;
+                    Ok((user, block))
+                })
+                .map_err(|e: FailureError| e.context("Service users, block endpoint error occured.").into())
+            })
+            .and_then(move |(user, block)| {
+                let mut data = serde_json::Map::new();
+                data.insert("reason".to_string(), serde_json::to_value(&block.reason).unwrap_or_default());
+                data.insert(
+                    "blocked_until".to_string(),
+                    serde_json::to_value(&blocked_until).unwrap_or_default(),
+                );
+                let event = SecurityEvent::new(SecurityEventType::AccountBlocked, user.id, Some(serde_json::Value::Object(data)));
+                service.emit_security_event(event).map(move |_| user)
+            });
+
+        Box::new(fut)
+    }
+
+    /// Lifts blocks of specific user if it still matches `if_match` entity tags
+    fn unblock(&self, user_id: UserId, if_match: Option<Vec<String>>) -> ServiceFuture<User> {
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
-        debug!("Set block status {} for user {}", is_blocked, &user_id);
+        debug!("Unblocking user {}", &user_id);
 
         self.spawn_on_pool(move |conn| {
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .set_block_status(user_id, is_blocked)
-                .map_err(|e: FailureError| e.context("Service users, set_block_status endpoint error occured.").into())
+            let user_blocks_repo = repo_factory.create_user_blocks_repo(&conn, current_uid);
+            conn.transaction::<User, FailureError, _>(move || {
+                check_entity_tag(&*users_repo, user_id, &if_match)?;
+                let user = users_repo.set_block_status(user_id, false, None)?;
+                user_blocks_repo.lift(user_id, current_uid)?;
+                Ok(user)
+            })
+            .map_err(|e: FailureError| e.context("Service users, unblock endpoint error occured.").into())
+        })
+    }
+
+    /// Returns history of blocks of specific user
+    fn list_blocks(&self, user_id: UserId) -> ServiceFuture<Vec<UserBlock>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let user_blocks_repo = repo_factory.create_user_blocks_repo(&conn, current_uid);
+            user_blocks_repo
+                .list_for_user(user_id)
+                .map_err(|e: FailureError| e.context("Service users, list_blocks endpoint error occured.").into())
+        })
+    }
+
+    /// Unblocks users, whose temporary blocks have expired. Returns ids of unblocked users.
+    fn lift_expired_blocks(&self) -> ServiceFuture<Vec<UserId>> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let batch_size = self.static_context.config.user_blocks.job_batch_size;
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&conn);
+            let user_blocks_repo = repo_factory.create_user_blocks_repo_with_sys_acl(&conn);
+            conn.transaction::<Vec<UserId>, FailureError, _>(move || {
+                let mut user_ids = vec![];
+                for user in users_repo.lift_expired_blocks(batch_size)? {
+                    user_blocks_repo.lift(user.id, None)?;
+                    user_ids.push(user.id);
+                }
+                Ok(user_ids)
+            })
+            .map_err(|e: FailureError| e.context("Service users, lift_expired_blocks endpoint error occured.").into())
         })
     }
 
@@ -176,6 +339,7 @@ impl<
         debug!("Deleting user with saga ID {}", &saga_id);
 
         self.spawn_on_pool(move |conn| {
+            // permission is granted to saga service and superusers only
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
             users_repo
                 .delete_by_saga_id(saga_id)
@@ -205,21 +369,48 @@ impl<
     }
 
     /// Creates new user
-    fn create(&self, payload: NewIdentity, user_payload: Option<NewUser>) -> ServiceFuture<User> {
+    fn create(&self, mut payload: NewIdentity, mut user_payload: Option<NewUser>) -> ServiceFuture<User> {
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
+        // emails are stored in lower case, the same way import does
+        payload.email = payload.email.to_lowercase();
+        if let Some(ref mut user_payload) = user_payload {
+            user_payload.email = user_payload.email.to_lowercase();
+        }
+
         debug!(
             "Creating new user with payload: {:?} and user_payload: {:?}",
             &payload, &user_payload
         );
 
+        let password_policy = self.static_context.password_policy.clone();
+
         self.spawn_on_pool(move |conn| {
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
             let ident_repo = repo_factory.create_identities_repo(&conn);
             let users_repo_with_sys_acl = repo_factory.create_users_repo_with_sys_acl(&conn);
 
             conn.transaction::<User, FailureError, _>(move || {
+                // Saga retries the same request on timeouts, so identity with this saga id
+                // means that user has already been created by previous attempt
+                ident_repo.lock_saga_id(&payload.saga_id)?;
+                if let Some(identity) = ident_repo.find_by_saga_id(payload.saga_id.clone())? {
+                    let user = users_repo_with_sys_acl
+                        .find(identity.user_id)?
+                        .ok_or_else(|| Error::NotFound.context(format!("User {} not found", identity.user_id)))?;
+                    check_saga_replay(&identity, &user, &payload, user_payload.as_ref())?;
+                    debug!("User {} has already been created by saga {}", identity.user_id, payload.saga_id);
+                    return Ok(user);
+                }
+
+                // replayed request is answered above even if the policy has changed since the user was created
+                if let (Provider::Email, Some(password)) = (payload.provider, payload.password.as_ref()) {
+                    password_policy
+                        .check("password", password, Some(&payload.email))
+                        .map_err(Error::Validate)?;
+                }
+
                 let exists = ident_repo.email_exists(payload.email.to_string())?;
                 if !exists {
                     let mut new_user = user_payload.unwrap_or(NewUser::from(payload.clone()));
@@ -251,7 +442,7 @@ impl<
         self.spawn_on_pool(move |conn| {
             let reset_repo = repo_factory.create_reset_token_repo(&conn);
             let token = reset_repo
-                .find_by_email(email.clone(), TokenType::EmailVerify)
+                .find_by_email(email.clone(), ResetTokenType::EmailVerify)
                 .map_err(|e| e.context(format!("Can not find token by email {}", email.clone())))?;
 
             if let Some(token) = token {
@@ -268,7 +459,7 @@ impl<
             }
 
             reset_repo
-                .upsert(email.clone(), TokenType::EmailVerify, None)
+                .upsert(email.clone(), ResetTokenType::EmailVerify, None)
                 .map(|t| t.token)
                 .map_err(|e| e.context("Can not create reset token").into())
                 .map_err(|e: FailureError| e.context("Service users, resend_verification_link endpoint error occured.").into())
@@ -276,7 +467,7 @@ impl<
     }
 
     /// Get existing email verification token
-    fn get_existing_reset_token(&self, user_id: UserId, token_type: TokenType) -> ServiceFuture<ResetToken> {
+    fn get_existing_reset_token(&self, user_id: UserId, token_type: ResetTokenType) -> ServiceFuture<ResetToken> {
         if !self.dynamic_context.is_super_admin() {
             // can only super admin with id = 1
             return Box::new(future::err(
@@ -304,10 +495,10 @@ impl<
     /// Verifies email
     fn verify_email(&self, token_arg: String) -> ServiceFuture<EmailVerifyApplyToken> {
         let repo_factory = self.static_context.repo_factory.clone();
-        let secret = self.static_context.jwt_private_key.clone();
         let verify_expiration_s = self.static_context.config.tokens.verify_expiration_s;
         let jwt_expiration_s = self.static_context.config.tokens.jwt_expiration_s;
         let service = self.clone();
+        let client = self.login_client();
 
         let fut = self
             .spawn_on_pool(move |conn| {
@@ -316,7 +507,7 @@ impl<
                     let reset_repo = repo_factory.create_reset_token_repo(&conn);
 
                     let reset_token: ResetToken = reset_repo
-                        .find_by_token(token_arg.clone(), TokenType::EmailVerify)
+                        .find_by_token(token_arg.clone(), ResetTokenType::EmailVerify)
                         .map_err(|e| e.context(Error::InvalidToken))?;
 
                     let user = match SystemTime::now().duration_since(reset_token.updated_at) {
@@ -354,16 +545,21 @@ impl<
             .and_then(move |user| {
                 let provider = Provider::Email;
                 let exp = Utc::now().timestamp() + jwt_expiration_s as i64;
-                service
-                    .create_jwt(user.id, exp, secret, provider)
-                    .and_then(move |token| future::ok(EmailVerifyApplyToken { token, user }))
+                service.create_jwt(user.id, exp, provider.clone()).and_then(move |token| {
+                    service
+                        .record_login_success(user.id, provider, client)
+                        .map(move |_| EmailVerifyApplyToken { token, user })
+                })
             });
 
         Box::new(fut)
     }
 
     /// Updates specific user
-    fn update(&self, user_id: UserId, payload: UpdateUser) -> ServiceFuture<User> {
+    fn update(&self, user_id: UserId, payload: UpdateUser, if_match: Option<Vec<String>>) -> ServiceFuture<User> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "update profile") {
+            return Box::new(future::err(e));
+        }
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
 
@@ -371,24 +567,35 @@ impl<
 
         self.spawn_on_pool(move |conn| {
             let users_repo = repo_factory.create_users_repo(&conn, current_uid);
-            users_repo
-                .find(user_id.clone())
-                .and_then(move |_user| users_repo.update(user_id, payload))
-                .map_err(|e: FailureError| e.context("Service users, update endpoint error occured.").into())
+            conn.transaction::<User, FailureError, _>(move || {
+                let user = check_entity_tag(&*users_repo, user_id, &if_match)?;
+                check_user_active(&user)?;
+                users_repo.update(user_id, payload)
+            })
+            .map_err(|e: FailureError| e.context("Service users, update endpoint error occured.").into())
         })
     }
 
     fn change_password(&self, payload: ChangeIdentityPassword) -> ServiceFuture<String> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "change password") {
+            return Box::new(future::err(e));
+        }
+        if let Err(e) = forbid_api_token(&self.dynamic_context, "change password") {
+            return Box::new(future::err(e));
+        }
         let service = self.clone();
         match self.dynamic_context.user_id {
             Some(current_uid) => {
                 let repo_factory = self.static_context.repo_factory.clone();
+                let password_policy = self.static_context.password_policy.clone();
+                let history_size = self.static_context.config.password_policy.history_size;
 
                 debug!("Updating user password {}", &current_uid);
 
                 Box::new(
                     self.spawn_on_pool(move |conn| {
                         let ident_repo = repo_factory.create_identities_repo(&conn);
+                        let password_history_repo = repo_factory.create_password_history_repo(&conn);
                         let old_password = payload.old_password.clone();
                         let new_password = payload.new_password.clone();
 
@@ -402,6 +609,17 @@ impl<
                                     Err(Error::Validate(validation_errors!({"password": ["password" => "Wrong password"]})).into())
                                 } else {
                                     //password verified
+                                    password_policy
+                                        .check("new_password", &new_password, Some(&identity.email))
+                                        .map_err(Error::Validate)?;
+                                    check_password_history(
+                                        &*password_history_repo,
+                                        &identity,
+                                        &new_password,
+                                        history_size,
+                                        "new_password",
+                                    )?;
+                                    update_password_history(&*password_history_repo, &identity, history_size)?;
                                     debug!("Changing password for identity {:?}", &identity);
                                     let update = UpdateIdentity {
                                         password: Some(password_create(new_password)),
@@ -416,7 +634,10 @@ impl<
                         })
                         .map_err(|e: FailureError| e.context("Service users, change_password endpoint error occured.").into())
                     })
-                    .and_then(move |identity| service.revoke_tokens(identity.user_id, Provider::Email)),
+                    .and_then(move |identity| {
+                        let event = SecurityEvent::new(SecurityEventType::PasswordChanged, identity.user_id, None);
+                        service.revoke_tokens_with_event(identity.user_id, Provider::Email, event)
+                    }),
                 )
             }
             None => Box::new(future::err(
@@ -445,7 +666,7 @@ impl<
                     .map_err(|e| e.context("Identity by email search failure").context(Error::InvalidToken))?;
                 debug!("Found identity {:?}, generating reset token.", &ident);
                 let token = reset_repo
-                    .find_by_email(email.clone(), TokenType::PasswordReset)
+                    .find_by_email(email.clone(), ResetTokenType::PasswordReset)
                     .map_err(|e| e.context(format!("Can not find token by email {}", email.clone())))?;
 
                 if let Some(token) = token {
@@ -462,7 +683,7 @@ impl<
                 }
 
                 let t = reset_repo
-                    .upsert(ident.email.clone(), TokenType::PasswordReset, Some(uuid))
+                    .upsert(ident.email.clone(), ResetTokenType::PasswordReset, Some(uuid))
                     .map_err(|e| e.context("Can not create reset token"))?;
                 Ok(t.token)
             }
@@ -474,6 +695,8 @@ impl<
         let repo_factory = self.static_context.repo_factory.clone();
         let service = self.clone();
         let reset_expiration_s = self.static_context.config.tokens.reset_expiration_s;
+        let password_policy = self.static_context.password_policy.clone();
+        let history_size = self.static_context.config.password_policy.history_size;
 
         debug!("Resetting password for token {}.", &token_arg);
 
@@ -482,9 +705,10 @@ impl<
                 {
                     let reset_repo = repo_factory.create_reset_token_repo(&conn);
                     let ident_repo = repo_factory.create_identities_repo(&conn);
+                    let password_history_repo = repo_factory.create_password_history_repo(&conn);
 
                     let reset_token = reset_repo
-                        .find_by_token(token_arg.clone(), TokenType::PasswordReset)
+                        .find_by_token(token_arg.clone(), ResetTokenType::PasswordReset)
                         .map_err(|e| e.context("Reset token by token search failure").context(Error::InvalidToken))?;
 
                     debug!("Checking reset token's {:?} expiration", &reset_token);
@@ -493,6 +717,11 @@ impl<
                             if elapsed.as_secs() < reset_expiration_s {
                                 let ident = ident_repo.get_by_email(reset_token.email.clone())?;
                                 debug!("Token check successful, resetting password for identity {:?}", &ident);
+                                password_policy
+                                    .check("password", &new_pass, Some(&ident.email))
+                                    .map_err(Error::Validate)?;
+                                check_password_history(&*password_history_repo, &ident, &new_pass, history_size, "password")?;
+                                update_password_history(&*password_history_repo, &ident, history_size)?;
 
                                 let update = match ident.provider {
                                     Provider::Email => UpdateIdentity {
@@ -518,12 +747,121 @@ impl<
                 .map_err(|e: FailureError| e.context("Service users, password_reset_apply endpoint error occured.").into())
             })
             .and_then(move |identity| {
-                service.revoke_tokens(identity.user_id, identity.provider).and_then(move |token| {
-                    Ok(ResetApplyToken {
-                        token,
-                        email: identity.email,
+                let event = SecurityEvent::new(SecurityEventType::PasswordReset, identity.user_id, None);
+                service
+                    .revoke_tokens_with_event(identity.user_id, identity.provider, event)
+                    .and_then(move |token| {
+                        Ok(ResetApplyToken {
+                            token,
+                            email: identity.email,
+                        })
                     })
+            });
+
+        Box::new(fut)
+    }
+
+    /// Get token for reactivation of account, that has been deactivated by the user.
+    /// Accounts deactivated by administrator can only be reactivated by administrator.
+    fn get_reactivation_token(&self, email_arg: String, uuid: Uuid) -> ServiceFuture<String> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "reactivate accounts") {
+            return Box::new(future::err(e));
+        }
+        let email = email_arg.clone();
+        let repo_factory = self.static_context.repo_factory.clone();
+        let email_sending_timeout = self.static_context.config.tokens.email_sending_timeout_s;
+
+        let fut = self.spawn_on_pool(move |conn| {
+            let reset_repo = repo_factory.create_reset_token_repo(&conn);
+            let users_repo = repo_factory.create_users_repo_with_sys_acl(&conn);
+            let user_deletions_repo = repo_factory.create_user_deletions_repo(&conn);
+
+            conn.transaction::<String, FailureError, _>(move || {
+                let user = users_repo.find_by_email(email.clone())?;
+                let user = user.ok_or_else(|| Error::Validate(validation_errors!({"email": ["not_exists" => "Email does not exist"]})))?;
+                if user.is_active {
+                    return Err(Error::Validate(validation_errors!({"email": ["not_deactivated" => "Account is not deactivated"]})).into());
+                }
+                check_self_deactivated(&*user_deletions_repo, user.id)?;
+
+                if let Some(token) = reset_repo.find_by_email(user.email.clone(), ResetTokenType::Reactivation)? {
+                    let token_duration = SystemTime::now()
+                        .duration_since(token.updated_at)
+                        .map_err(|e| Error::InvalidTime.context(format!("Can not calc duration : {}", e.to_string())))?
+                        .as_secs();
+                    if token_duration < email_sending_timeout {
+                        return Err(Error::Validate(
+                            validation_errors!({"email": ["email_timeout" => "Can not send email more often then 30 seconds"]}),
+                        )
+                        .into());
+                    }
+                }
+
+                debug!("Generating reactivation token for user {}.", user.id);
+                let t = reset_repo
+                    .upsert(user.email, ResetTokenType::Reactivation, Some(uuid))
+                    .map_err(|e| e.context("Can not create reactivation token"))?;
+                Ok(t.token)
+            })
+        });
+
+        Box::new(fut.map_err(|e: FailureError| e.context("Service users, reactivation_request endpoint error occured.").into()))
+    }
+
+    /// Reactivates account by the token and cancels its pending deletion
+    fn reactivation_apply(&self, token_arg: String) -> ServiceFuture<User> {
+        if let Err(e) = forbid_impersonation(&self.dynamic_context, "reactivate accounts") {
+            return Box::new(future::err(e));
+        }
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service = self.clone();
+        let reset_expiration_s = self.static_context.config.tokens.reset_expiration_s;
+
+        debug!("Reactivating account for token {}.", &token_arg);
+
+        let fut = self
+            .spawn_on_pool(move |conn| {
+                let reset_repo = repo_factory.create_reset_token_repo(&conn);
+                let users_repo = repo_factory.create_users_repo_with_sys_acl(&conn);
+                let user_deletions_repo = repo_factory.create_user_deletions_repo(&conn);
+
+                conn.transaction::<(User, bool), FailureError, _>(move || {
+                    let reactivation_token = reset_repo
+                        .find_by_token(token_arg.clone(), ResetTokenType::Reactivation)
+                        .map_err(|e| e.context("Reactivation token by token search failure").context(Error::InvalidToken))?;
+
+                    debug!("Checking reactivation token's {:?} expiration", &reactivation_token);
+                    let elapsed = SystemTime::now()
+                        .duration_since(reactivation_token.updated_at)
+                        .map_err(|_| Error::InvalidToken)?;
+                    if elapsed.as_secs() >= reset_expiration_s {
+                        return Err(Error::InvalidToken
+                            .context(format!("Token {:?} has expired", &reactivation_token))
+                            .into());
+                    }
+                    // every token can be used once
+                    reset_repo.delete_by_token(token_arg, ResetTokenType::Reactivation)?;
+
+                    let user = users_repo
+                        .find_by_email(reactivation_token.email.clone())?
+                        .ok_or_else(|| Error::InvalidToken.context(format!("User {} not found", reactivation_token.email)))?;
+                    let user_id = user.id;
+                    // account may have been reactivated by admin since the token was sent
+                    if user.is_active {
+                        return Ok((user, false));
+                    }
+                    // or deactivated by admin after the user had cancelled deletion
+                    check_self_deactivated(&*user_deletions_repo, user_id)?;
+                    reactivate_user(&*users_repo, &*user_deletions_repo, user_id).map(|user| (user, true))
                 })
+                .map_err(|e: FailureError| e.context("Service users, reactivation_apply endpoint error occured.").into())
+            })
+            .and_then(move |(user, reactivated)| -> ServiceFuture<User> {
+                if !reactivated {
+                    return Box::new(future::ok(user));
+                }
+                let event = SecurityEvent::new(SecurityEventType::AccountReactivated, user.id, None);
+                Box::new(service.emit_security_event(event).map(|_| user))
             });
 
         Box::new(fut)
@@ -562,6 +900,21 @@ impl<
         })
     }
 
+    /// Search users page by page using opaque cursors
+    fn search_page(&self, term: UsersSearchTerms, page: UsersSearchPage) -> ServiceFuture<UsersSearchPageResults> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Searching for users (page: {:?}) with payload: {:?}", page, term);
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+            users_repo
+                .search_page(term, page)
+                .map_err(|e: FailureError| e.context("Service `users`, `search_page` endpoint error occured.").into())
+        })
+    }
+
     /// Fuzzy search users by email
     fn fuzzy_search_by_email(&self, term_email: String) -> ServiceFuture<Vec<User>> {
         let current_uid = self.dynamic_context.user_id;
@@ -577,15 +930,57 @@ impl<
         })
     }
 
+    /// Fuzzy search users by email, names and phone ranked by similarity
+    fn fuzzy_search(&self, query: String, limit: i64) -> ServiceFuture<Vec<UserFuzzyMatch>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Fuzzy searching for {} users similar to {}", limit, query);
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+            users_repo
+                .fuzzy_search(query, limit)
+                .map_err(|e: FailureError| e.context("Service users, fuzzy_search endpoint error occured.").into())
+        })
+    }
+
+    /// Returns signups made in `period` grouped by signup attribute
+    fn acquisition_report(&self, group_by: AcquisitionGroupBy, period: DateRange) -> ServiceFuture<AcquisitionReport> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let users_repo = repo_factory.create_users_repo(&conn, current_uid);
+            users_repo
+                .acquisition_stats(group_by, period.clone())
+                .map(|rows| AcquisitionReport::new(group_by, period, rows))
+                .map_err(|e: FailureError| e.context("Service users, acquisition_report endpoint error occured.").into())
+        })
+    }
+
     /// Revoke all tokens for user
     fn revoke_tokens(&self, user_id: UserId, provider: Provider) -> ServiceFuture<String> {
+        let event = SecurityEvent::new(SecurityEventType::TokensRevoked, user_id, None);
+        self.revoke_tokens_with_event(user_id, provider, event)
+    }
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > Service<T, M, F>
+{
+    /// Revokes all tokens for user and emits the single security event, describing the reason of revocation
+    fn revoke_tokens_with_event(&self, user_id: UserId, provider: Provider, event: SecurityEvent) -> ServiceFuture<String> {
         let current_uid = self.dynamic_context.user_id;
         let repo_factory = self.static_context.repo_factory.clone();
         let jwt_expiration_s = self.static_context.config.tokens.jwt_expiration_s;
-        let secret = self.static_context.jwt_private_key.clone();
         // revoking all tokens given before current date
         // expiration date of tokens must be later than now + jwt_exp
         let revoke_before = SystemTime::now() + Duration::from_secs(jwt_expiration_s);
+        let service = self.clone();
 
         debug!("Revoking all tokens for user {}", user_id);
 
@@ -596,27 +991,64 @@ impl<
                     .revoke_tokens(user_id, revoke_before)
                     .map_err(|e: FailureError| e.context("Service users, revoke_tokens endpoint error occured.").into())
             })
+            .and_then({
+                let service = service.clone();
+                move |_| service.emit_security_event(event)
+            })
             .and_then(move |_| {
                 let exp = Utc::now().timestamp() + jwt_expiration_s as i64;
-                let tokenpayload = JWTPayload::new(user_id, exp, provider);
-                encode(&Header::new(Algorithm::RS256), &tokenpayload, secret.as_ref())
-                    .map_err(|e| {
-                        format_err!("{}", e)
-                            .context(Error::Parse)
-                            .context(format!("Couldn't encode jwt: {:?}.", tokenpayload))
-                            .into()
-                    })
-                    .into_future()
-                    .map(move |token| {
-                        debug!("Token {} created successfully for user_id {:?}", token, user_id);
-                        token
-                    })
+                service.create_jwt(user_id, exp, provider)
             }),
         )
     }
 }
 
-fn check_referal(users_repo: &UsersRepo, new_user: &mut NewUser) -> Result<(), FailureError> {
+/// Runs background job, that lifts expired temporary blocks, so that `is_blocked` of users stays actual
+pub fn spawn_expired_blocks_job<T, M, F>(static_context: StaticContext<T, M, F>, handle: &Handle) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let interval = Duration::from_secs(static_context.config.user_blocks.job_interval_s);
+    let http_timeout = Duration::from_millis(static_context.config.client.http_timeout_ms);
+    let time_limited_http_client = TimeLimitedHttpClient::new(static_context.client_handle.clone(), http_timeout);
+    let DynamicContextServices {
+        google_provider_service,
+        facebook_provider_service,
+    } = static_context.dynamic_context_services(time_limited_http_client.clone());
+    let dynamic_context = DynamicContext::new(
+        None,
+        "expired_blocks_job".to_string(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        time_limited_http_client,
+        google_provider_service,
+        facebook_provider_service,
+    );
+    let service = Service::new(static_context, dynamic_context);
+
+    let job = Interval::new(interval, handle)?
+        .map_err(|e| error!("Expired blocks job timer error: {}", e))
+        .for_each(move |_| {
+            service.lift_expired_blocks().then(|result| {
+                match result {
+                    Ok(ref user_ids) if !user_ids.is_empty() => info!("Lifted expired blocks of users {:?}", user_ids),
+                    Ok(_) => {}
+                    Err(e) => error!("Expired blocks job error: {}", e),
+                }
+                Ok(())
+            })
+        });
+    handle.spawn(job);
+
+    Ok(())
+}
+
+pub fn check_referal(users_repo: &UsersRepo, new_user: &mut NewUser) -> Result<(), FailureError> {
     if let Some(referal) = new_user.referal {
         if users_repo.find(referal)?.is_none() {
             new_user.referal = None;
@@ -625,7 +1057,103 @@ fn check_referal(users_repo: &UsersRepo, new_user: &mut NewUser) -> Result<(), F
     Ok(())
 }
 
-fn set_email_verified_social(users_repo: &UsersRepo, user_id: UserId, provider: Provider) -> Result<Option<User>, FailureError> {
+/// Fails with validation error, if the password is the current password of identity or one of
+/// its previous passwords within `history_size` latest ones. Zero `history_size` disables the check.
+pub fn check_password_history(
+    password_history_repo: &PasswordHistoryRepo,
+    identity: &Identity,
+    password: &str,
+    history_size: usize,
+    field: &'static str,
+) -> Result<(), FailureError> {
+    if history_size == 0 {
+        return Ok(());
+    }
+    let previous = password_history_repo.list_latest(identity.user_id, history_size as i64 - 1)?;
+    for db_hash in identity.password.iter().chain(previous.iter().map(|entry| &entry.password)) {
+        if password_verify(db_hash, password.to_string())? {
+            let message = format!("Password must differ from {} latest passwords", history_size);
+            return Err(Error::Validate(validation_errors!({field: ["password_reused" => message]})).into());
+        }
+    }
+    Ok(())
+}
+
+/// Moves the current password of identity to history, that is pruned to `history_size` latest passwords
+pub fn update_password_history(
+    password_history_repo: &PasswordHistoryRepo,
+    identity: &Identity,
+    history_size: usize,
+) -> Result<(), FailureError> {
+    if history_size == 0 {
+        return Ok(());
+    }
+    if let Some(ref password) = identity.password {
+        password_history_repo.add(NewPasswordHistoryEntry {
+            user_id: identity.user_id,
+            password: password.clone(),
+        })?;
+    }
+    password_history_repo.prune(identity.user_id, history_size as i64 - 1)
+}
+
+/// Checks that the user has deactivated own account by requesting its deletion, that is still pending
+fn check_self_deactivated(user_deletions_repo: &UserDeletionsRepo, user_id: UserId) -> Result<(), FailureError> {
+    match user_deletions_repo.find_by_user_id(user_id)? {
+        Some(ref user_deletion) if user_deletion.completed_at.is_none() => Ok(()),
+        Some(_) => Err(Error::Validate(validation_errors!({"email": ["deleted" => "Account is deleted"]})).into()),
+        None => Err(Error::DeactivatedByAdmin
+            .context(format!("Account of user {} can be reactivated only by administrator", user_id))
+            .into()),
+    }
+}
+
+/// Activates deactivated user and cancels its pending deletion. Anonymized accounts can't be reactivated.
+fn reactivate_user(users_repo: &UsersRepo, user_deletions_repo: &UserDeletionsRepo, user_id: UserId) -> Result<User, FailureError> {
+    if let Some(user_deletion) = user_deletions_repo.find_by_user_id(user_id)? {
+        if user_deletion.completed_at.is_some() {
+            return Err(Error::Validate(validation_errors!({"user": ["deleted" => "Account is deleted"]})).into());
+        }
+    }
+    if user_deletions_repo.cancel_by_user_id(user_id)?.is_some() {
+        debug!("Cancelled pending deletion of user {}", user_id);
+    }
+    users_repo.activate(user_id)
+}
+
+fn check_saga_replay(identity: &Identity, user: &User, payload: &NewIdentity, user_payload: Option<&NewUser>) -> Result<(), FailureError> {
+    let same_password = match (&identity.password, &payload.password) {
+        (Some(db_hash), Some(password)) => password_verify(db_hash, password.clone())?,
+        (None, None) => true,
+        _ => false,
+    };
+    let same_identity = identity.email == payload.email && identity.provider == payload.provider && same_password;
+    let same_user = match user_payload {
+        // referal of unknown user is dropped on creation, see `check_referal`
+        Some(new_user) => {
+            user.email == new_user.email
+                && user.phone == new_user.phone
+                && user.first_name == new_user.first_name
+                && user.last_name == new_user.last_name
+                && user.middle_name == new_user.middle_name
+                && user.gender == new_user.gender
+                && user.birthdate == new_user.birthdate
+                && (user.referal == new_user.referal || user.referal.is_none())
+                && user.utm_marks == new_user.utm_marks
+                && user.country == new_user.country
+                && user.referer == new_user.referer
+        }
+        None => user.email == payload.email,
+    };
+
+    if same_identity && same_user {
+        Ok(())
+    } else {
+        Err(Error::Validate(validation_errors!({"saga_id": ["conflict" => "Saga id has already been used with different payload"]})).into())
+    }
+}
+
+pub fn set_email_verified_social(users_repo: &UsersRepo, user_id: UserId, provider: Provider) -> Result<Option<User>, FailureError> {
     match provider {
         Provider::Facebook | Provider::Google => {
             let update = UpdateUser {
@@ -646,14 +1174,19 @@ fn set_email_verified_social(users_repo: &UsersRepo, user_id: UserId, provider:
 pub mod tests {
 
     use std::sync::Arc;
+    use std::time::SystemTime;
 
+    use chrono::{Duration, Utc};
     use tokio_core::reactor::Core;
+    use uuid::Uuid;
 
     use stq_static_resources::Provider;
     use stq_types::UserId;
 
+    use errors::Error;
+    use models::{AcquisitionGroupBy, BlockUser, DateRange, NewUser, UsersCursor, UsersSearchPage, UsersSearchTerms, UsersSortField};
     use repos::repo_factory::tests::*;
-    use services::users::UsersService;
+    use services::users::{check_self_deactivated, UsersService};
 
     #[test]
     fn test_get_user() {
@@ -665,6 +1198,64 @@ pub mod tests {
         assert_eq!(result.unwrap().id, UserId(1));
     }
 
+    #[test]
+    fn test_get_batch() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.get_batch(vec![UserId(1), UserId(MOCK_MISSING_USER_ID)]);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.users.len(), 1);
+        assert_eq!(result.missing, vec![UserId(MOCK_MISSING_USER_ID)]);
+        assert!(result.forbidden.is_empty());
+    }
+
+    #[test]
+    fn test_search_page_with_cursor() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let page = UsersSearchPage {
+            count: 5,
+            sort: UsersSortField::Email,
+            with_total_count: false,
+            ..Default::default()
+        };
+        let work = service.search_page(UsersSearchTerms::default(), page);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.users.len(), 5);
+        assert_eq!(result.total_count, None);
+
+        let cursor = UsersCursor::decode(&result.next_cursor.unwrap()).unwrap();
+        assert_eq!(cursor.id, result.users[4].id);
+        assert_eq!(cursor.sort, UsersSortField::Email);
+        assert_eq!(cursor.text().unwrap(), MOCK_EMAIL.to_string());
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.fuzzy_search("jhon".to_string(), 3);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 3);
+        assert!(result[0].similarity >= result[1].similarity);
+    }
+
+    #[test]
+    fn test_acquisition_report() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.acquisition_report(AcquisitionGroupBy::UtmSource, DateRange::default());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.rows.len(), 2);
+        assert_eq!(result.signups, 3);
+        assert_eq!(result.verified, 2);
+        assert_eq!(result.active, 2);
+    }
+
     #[test]
     fn test_current_user() {
         let mut core = Core::new().unwrap();
@@ -727,18 +1318,166 @@ pub mod tests {
         assert_eq!(result.email, "new_user@mail.com".to_string());
     }
 
+    #[test]
+    fn test_create_user_lowercases_email() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_ident = create_new_identity(
+            "New_User@Mail.com".to_string(),
+            MOCK_PASSWORD.to_string(),
+            Provider::Email,
+            MOCK_SAGA_ID.to_string(),
+        );
+        let work = service.create(new_ident, None);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.email, "new_user@mail.com".to_string());
+    }
+
+    #[test]
+    fn test_create_user_with_weak_password() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_ident = create_new_identity(
+            "new_user@mail.com".to_string(),
+            "new_user".to_string(),
+            Provider::Email,
+            MOCK_SAGA_ID.to_string(),
+        );
+        let work = service.create(new_ident, None);
+        let error = core.run(work).unwrap_err();
+        match error.causes().filter_map(|cause| cause.downcast_ref::<Error>()).next() {
+            Some(Error::Validate(errors)) => assert!(errors.clone().inner().contains_key("password")),
+            _ => panic!("Weak password is not reported as validation error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_change_password_to_recent_password() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        for new_password in vec![MOCK_PASSWORD.to_string(), format!("{}0", MOCK_PASSWORD)] {
+            let payload = ChangeIdentityPassword {
+                old_password: MOCK_PASSWORD.to_string(),
+                new_password,
+            };
+            let work = service.change_password(payload);
+            let error = core.run(work).unwrap_err();
+            match error.causes().filter_map(|cause| cause.downcast_ref::<Error>()).next() {
+                Some(Error::Validate(errors)) => assert_eq!(errors.clone().inner()["new_password"][0].code, "password_reused"),
+                _ => panic!("Reused password is not reported as validation error: {}", error),
+            }
+        }
+    }
+
+    #[test]
+    fn test_change_password_to_old_password() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        // history keeps the current password and `history_size - 1` previous ones
+        let history_size = service.static_context.config.password_policy.history_size;
+        assert!(history_size > 1);
+        let payload = ChangeIdentityPassword {
+            old_password: MOCK_PASSWORD.to_string(),
+            new_password: format!("{}{}", MOCK_PASSWORD, history_size - 1),
+        };
+        let work = service.change_password(payload);
+        let result = core.run(work);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_create_replayed_by_saga() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_ident = create_new_identity(
+            MOCK_EMAIL.to_string(),
+            MOCK_PASSWORD.to_string(),
+            Provider::Email,
+            MOCK_COMPLETED_SAGA_ID.to_string(),
+        );
+        let work = service.create(new_ident, None);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, UserId(1));
+    }
+
+    #[test]
+    fn test_create_replayed_by_saga_with_conflicting_payload() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_ident = create_new_identity(
+            "new_user@mail.com".to_string(),
+            MOCK_PASSWORD.to_string(),
+            Provider::Email,
+            MOCK_COMPLETED_SAGA_ID.to_string(),
+        );
+        let work = service.create(new_ident, None);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_create_replayed_by_saga_with_conflicting_user_payload() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_ident = create_new_identity(
+            MOCK_EMAIL.to_string(),
+            MOCK_PASSWORD.to_string(),
+            Provider::Email,
+            MOCK_COMPLETED_SAGA_ID.to_string(),
+        );
+        let new_user = NewUser {
+            first_name: Some("Another".to_string()),
+            ..NewUser::from(new_ident.clone())
+        };
+        let work = service.create(new_ident, Some(new_user));
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
     #[test]
     fn test_update() {
         let mut core = Core::new().unwrap();
         let handle = Arc::new(core.handle());
         let service = create_service(Some(UserId(1)), handle);
         let new_user = create_update_user(MOCK_EMAIL.to_string());
-        let work = service.update(UserId(1), new_user);
+        let work = service.update(UserId(1), new_user, None);
         let result = core.run(work).unwrap();
         assert_eq!(result.id, UserId(1));
         assert_eq!(result.email, MOCK_EMAIL.to_string());
     }
 
+    #[test]
+    fn test_update_with_current_entity_tag() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let mut user = create_user(UserId(1), MOCK_EMAIL.to_string());
+        // sign in is not an edit of the user
+        user.last_login_at = SystemTime::now();
+        let new_user = create_update_user(MOCK_EMAIL.to_string());
+        let work = service.update(UserId(1), new_user, Some(vec![user.entity_tag()]));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, UserId(1));
+    }
+
+    #[test]
+    fn test_update_with_stale_entity_tag() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_user = create_update_user(MOCK_EMAIL.to_string());
+        let work = service.update(UserId(1), new_user, Some(vec!["0".to_string()]));
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
     #[test]
     fn test_deactivate() {
         let mut core = Core::new().unwrap();
@@ -749,4 +1488,137 @@ pub mod tests {
         assert_eq!(result.id, UserId(1));
         assert_eq!(result.is_active, false);
     }
+
+    #[test]
+    fn test_update_deactivated_user() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let new_user = create_update_user(MOCK_EMAIL.to_string());
+        let work = service.update(UserId(MOCK_DEACTIVATED_USER_ID), new_user, None);
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_reactivate() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.reactivate(UserId(MOCK_DEACTIVATED_USER_ID));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, UserId(MOCK_DEACTIVATED_USER_ID));
+        assert_eq!(result.is_active, true);
+        let work = service.reactivate(UserId(1));
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_reactivate_deleted_user() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.reactivate(UserId(MOCK_DELETED_USER_ID));
+        let error = core.run(work).unwrap_err();
+        match error.causes().filter_map(|cause| cause.downcast_ref::<Error>()).next() {
+            Some(Error::Validate(errors)) => assert_eq!(errors.clone().inner()["user"][0].code, "deleted"),
+            _ => panic!("Reactivation of deleted user is not reported as validation error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_get_reactivation_token() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_reactivation_token(MOCK_DEACTIVATED_EMAIL.to_string(), Uuid::new_v4());
+        let result = core.run(work).unwrap();
+        assert!(!result.is_empty());
+    }
+
+    #[test]
+    fn test_get_reactivation_token_for_active_user() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.get_reactivation_token(MOCK_EMAIL.to_string(), Uuid::new_v4());
+        let result = core.run(work);
+        assert_eq!(result.is_err(), true);
+    }
+
+    #[test]
+    fn test_reactivation_apply() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.reactivation_apply(MOCK_TOKEN.to_string());
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, UserId(MOCK_DEACTIVATED_USER_ID));
+        assert_eq!(result.email, MOCK_DEACTIVATED_EMAIL.to_string());
+        assert_eq!(result.is_active, true);
+        let work = service.reactivation_apply("unknown".to_string());
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_check_deactivated_by_admin() {
+        assert!(check_self_deactivated(&UserDeletionsRepoMock, UserId(MOCK_DEACTIVATED_USER_ID)).is_ok());
+        let error = check_self_deactivated(&UserDeletionsRepoMock, UserId(1)).unwrap_err();
+        match error.causes().filter_map(|cause| cause.downcast_ref::<Error>()).next() {
+            Some(Error::DeactivatedByAdmin) => (),
+            _ => panic!("Account deactivated by administrator is not reported as such: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_block_temporarily() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = BlockUser {
+            reason: Some("Spam".to_string()),
+            blocked_until: Some(Utc::now() + Duration::days(1)),
+        };
+        let blocked_until = payload.blocked_until.map(SystemTime::from);
+        let work = service.block(UserId(1), payload, None);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, UserId(1));
+        assert!(result.is_blocked);
+        assert_eq!(result.blocked_until, blocked_until);
+    }
+
+    #[test]
+    fn test_block_until_past() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = BlockUser {
+            reason: None,
+            blocked_until: Some(Utc::now() - Duration::days(1)),
+        };
+        let work = service.block(UserId(1), payload, None);
+        assert!(core.run(work).is_err());
+    }
+
+    #[test]
+    fn test_list_blocks() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.list_blocks(UserId(1));
+        let result = core.run(work).unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(!result[0].is_active_at(SystemTime::now()));
+    }
+
+    #[test]
+    fn test_lift_expired_blocks() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(None, handle);
+        let work = service.lift_expired_blocks();
+        let result = core.run(work).unwrap();
+        assert!(result.is_empty());
+    }
 }
@@ -0,0 +1,340 @@
+//! Users export Services, streams users search results as CSV or JSON Lines
+//! and records every export in audit log. Users are read page by page, as the client
+//! consumes the export, through a cursor of single read only transaction, so that
+//! the whole export is a consistent snapshot.
+
+use diesel::connection::{AnsiTransactionManager, SimpleConnection};
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::{stream, Future, Stream};
+use futures_cpupool::CpuPool;
+use r2d2::{ManageConnection, PooledConnection};
+use serde_json;
+
+use stq_types::UserId;
+
+use errors::Error;
+use models::{AuditAction, NewAuditLogEntry, User, UsersExport, UsersExportAudit, UsersSearchTerms};
+use repos::ReposFactory;
+use services::types::{ServiceFuture, ServiceStream};
+use services::Service;
+
+/// Number of users fetched from db at a time
+const EXPORT_PAGE_SIZE: i64 = 1000;
+
+pub trait UsersExportService {
+    /// Exports users matching search terms. Future resolves as soon as the first chunk of export is ready,
+    /// so that errors occured before anything was exported could be reported with the proper status code.
+    fn export(&self, term: UsersSearchTerms, export: UsersExport) -> ServiceFuture<ServiceStream<String>>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > UsersExportService for Service<T, M, F>
+{
+    /// Exports users matching search terms. Future resolves as soon as the first chunk of export is ready,
+    /// so that errors occured before anything was exported could be reported with the proper status code.
+    fn export(&self, term: UsersSearchTerms, export: UsersExport) -> ServiceFuture<ServiceStream<String>> {
+        let service = self.clone();
+
+        debug!("Exporting users ({:?}) with payload: {:?}", export, term);
+
+        let progress = ExportProgress {
+            cursor: None,
+            header: export.header(),
+            audit: ExportAudit {
+                service: self.clone(),
+                term: term.clone(),
+                export: export.clone(),
+                rows: 0,
+                completed: false,
+            },
+        };
+
+        let chunks = stream::unfold(Some(progress), move |progress| {
+            let mut progress = progress?;
+            let current_uid = service.dynamic_context.user_id;
+            let repo_factory = service.static_context.repo_factory.clone();
+            let export = export.clone();
+
+            // cursor is declared along with the first page, its connection is held until the export is over
+            let page: ServiceFuture<(Vec<User>, Option<ExportCursor<M>>)> = match progress.cursor.take() {
+                Some(cursor) => Box::new(
+                    service
+                        .static_context
+                        .cpu_pool
+                        .spawn_fn(move || cursor.fetch(&repo_factory, current_uid)),
+                ),
+                None => {
+                    let term = term.clone();
+                    let cpu_pool = service.static_context.cpu_pool.clone();
+                    service.spawn_on_pool(move |conn| {
+                        ExportCursor::declare(conn, cpu_pool, &repo_factory, current_uid, term)?.fetch(&repo_factory, current_uid)
+                    })
+                }
+            };
+
+            Some(page.then(move |page| {
+                let (page, cursor) = page.map_err(|e| e.context("Service users_export, export endpoint error occured."))?;
+
+                let mut chunk = progress.header.take().unwrap_or_default();
+                for user in &page {
+                    chunk.push_str(&export.row(user));
+                }
+                progress.audit.rows += page.len();
+
+                // cursor is closed after the last page, that may carry only the header of empty export
+                match cursor {
+                    Some(cursor) => {
+                        progress.cursor = Some(cursor);
+                        Ok::<_, FailureError>((chunk, Some(progress)))
+                    }
+                    None => {
+                        progress.audit.completed = true;
+                        Ok((chunk, None))
+                    }
+                }
+            }))
+        })
+        .filter(|chunk| !chunk.is_empty());
+
+        let fut = chunks
+            .into_future()
+            .map(|(first, rest)| Box::new(stream::iter_ok(first).chain(rest)) as ServiceStream<String>)
+            .map_err(|(e, _)| e);
+
+        Box::new(fut)
+    }
+}
+
+/// State of export between pages
+struct ExportProgress<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    /// Cursor of export, once the first page is read
+    cursor: Option<ExportCursor<M>>,
+    /// Header, that is not sent yet
+    header: Option<String>,
+    audit: ExportAudit<T, M, F>,
+}
+
+/// Db connection with open transaction, where cursor of users export is declared.
+/// The transaction is rolled back, if export is failed or cancelled by client.
+struct ExportCursor<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager>,
+{
+    conn: Option<PooledConnection<M>>,
+    cpu_pool: CpuPool,
+}
+
+impl<M> ExportCursor<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager>,
+{
+    /// Opens transaction and declares cursor over users matching search terms
+    fn declare<F: ReposFactory<M::Connection>>(
+        conn: PooledConnection<M>,
+        cpu_pool: CpuPool,
+        repo_factory: &F,
+        current_uid: Option<UserId>,
+        term: UsersSearchTerms,
+    ) -> Result<Self, FailureError> {
+        conn.batch_execute("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")?;
+        let cursor = Self {
+            conn: Some(conn),
+            cpu_pool,
+        };
+        repo_factory
+            .create_users_repo(cursor.conn(), current_uid)
+            .declare_export_cursor(term)?;
+        Ok(cursor)
+    }
+
+    /// Fetches next page of users, cursor is closed and not returned after the last page
+    fn fetch<F: ReposFactory<M::Connection>>(
+        self,
+        repo_factory: &F,
+        current_uid: Option<UserId>,
+    ) -> Result<(Vec<User>, Option<Self>), FailureError> {
+        let page = repo_factory
+            .create_users_repo(self.conn(), current_uid)
+            .fetch_export_cursor(EXPORT_PAGE_SIZE)?;
+        if (page.len() as i64) < EXPORT_PAGE_SIZE {
+            self.close()?;
+            Ok((page, None))
+        } else {
+            Ok((page, Some(self)))
+        }
+    }
+
+    fn conn(&self) -> &M::Connection {
+        self.conn.as_ref().expect("Export cursor is used after it was closed")
+    }
+
+    /// Commits the transaction, that closes the cursor, connection is returned to the pool
+    fn close(mut self) -> Result<(), FailureError> {
+        match self.conn.take() {
+            Some(conn) => conn.batch_execute("COMMIT").map_err(From::from),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<M> Drop for ExportCursor<M>
+where
+    M: ManageConnection,
+    M::Connection: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager>,
+{
+    fn drop(&mut self) {
+        let conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        // the cursor may be dropped along with the response, so rolling back must not block the reactor
+        self.cpu_pool
+            .spawn_fn(move || {
+                if let Err(e) = conn.batch_execute("ROLLBACK") {
+                    error!("Users export rollback failed: {}", e);
+                }
+                Ok::<(), ()>(())
+            })
+            .forget();
+    }
+}
+
+/// Records export in audit log, when it is completed, failed or cancelled by client.
+/// Only exports that have actually started are recorded.
+struct ExportAudit<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    service: Service<T, M, F>,
+    term: UsersSearchTerms,
+    export: UsersExport,
+    rows: usize,
+    completed: bool,
+}
+
+impl<T, M, F> Drop for ExportAudit<T, M, F>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    fn drop(&mut self) {
+        if !self.completed && self.rows == 0 {
+            return;
+        }
+
+        let payload = match serde_json::to_value(UsersExportAudit {
+            terms: &self.term,
+            format: self.export.format,
+            columns: &self.export.columns,
+            rows: self.rows,
+            completed: self.completed,
+        }) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Users export audit failed: {}", e);
+                return;
+            }
+        };
+        let entry = NewAuditLogEntry {
+            user_id: self.service.dynamic_context.user_id,
+            action: AuditAction::UsersExported,
+            payload,
+        };
+        let db_pool = self.service.static_context.db_pool.clone();
+        let repo_factory = self.service.static_context.repo_factory.clone();
+
+        // the export may be dropped along with the response, so recording must not depend on polling
+        self.service
+            .static_context
+            .cpu_pool
+            .spawn_fn(move || {
+                let result = db_pool
+                    .get()
+                    .map_err(|e| e.context(Error::Connection).into())
+                    .and_then(|conn| repo_factory.create_audit_log_repo_with_sys_acl(&*conn).create(entry));
+                if let Err(e) = result {
+                    error!("Users export audit failed: {}", e);
+                }
+                Ok::<(), ()>(())
+            })
+            .forget();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use futures::{Future, Stream};
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::{UsersExport, UsersExportColumn, UsersExportColumns, UsersFileFormat, UsersSearchTerms};
+    use repos::repo_factory::tests::*;
+    use services::users_export::UsersExportService;
+
+    #[test]
+    fn test_export_csv() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let export = UsersExport {
+            format: UsersFileFormat::Csv,
+            columns: UsersExportColumns(vec![UsersExportColumn::Id, UsersExportColumn::Email]),
+        };
+        let work = service
+            .export(UsersSearchTerms::default(), export)
+            .and_then(|chunks| chunks.collect().map(|chunks| chunks.concat()));
+        let result = core.run(work).unwrap();
+        let mut lines = result.lines();
+        assert_eq!(lines.next(), Some("id,email"));
+        assert_eq!(lines.next(), Some(format!("1,{}", MOCK_EMAIL).as_str()));
+    }
+
+    #[test]
+    fn test_export_jsonl() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let export = UsersExport {
+            format: UsersFileFormat::Jsonl,
+            columns: UsersExportColumns(vec![UsersExportColumn::Email]),
+        };
+        let work = service
+            .export(UsersSearchTerms::default(), export)
+            .and_then(|chunks| chunks.collect().map(|chunks| chunks.concat()));
+        let result = core.run(work).unwrap();
+        let first = result.lines().next().unwrap();
+        assert_eq!(first, format!("{{\"email\":\"{}\"}}", MOCK_EMAIL));
+    }
+
+    #[test]
+    fn test_export_csv_escapes_formulas() {
+        let export = UsersExport {
+            format: UsersFileFormat::Csv,
+            columns: UsersExportColumns(vec![UsersExportColumn::FirstName, UsersExportColumn::LastName]),
+        };
+        let mut user = create_user(UserId(2), MOCK_EMAIL.to_string());
+        user.first_name = Some("=HYPERLINK(\"http://example.com\")".to_string());
+        user.last_name = Some("@SUM(A1)".to_string());
+        assert_eq!(export.row(&user), "\"'=HYPERLINK(\"\"http://example.com\"\")\",'@SUM(A1)\r\n");
+    }
+}
@@ -0,0 +1,445 @@
+//! Users import Services, validates and creates users in bulk from CSV or JSON Lines files
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDate;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use r2d2::ManageConnection;
+use serde::de::DeserializeOwned;
+use serde_json;
+use uuid::Uuid;
+use validator::{Validate, ValidationErrors};
+
+use stq_static_resources::Provider;
+use stq_types::{UserId, UsersRole};
+
+use super::types::ServiceFuture;
+use super::users::{check_referal, set_email_verified_social};
+use super::util::password_create;
+use errors::Error;
+use models::*;
+use repos::repo_factory::ReposFactory;
+use repos::{IdentitiesRepo, ResetTokenRepo, UsersRepo};
+use services::password_policy::PasswordPolicy;
+use services::Service;
+
+pub trait UsersImportService {
+    /// Validates users from CSV or JSON Lines file and, unless it is a dry run,
+    /// creates valid ones in batches. Returns result for every row.
+    fn import(&self, body: String, import: UsersImport) -> ServiceFuture<UsersImportReport>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > UsersImportService for Service<T, M, F>
+{
+    /// Validates users from CSV or JSON Lines file and, unless it is a dry run,
+    /// creates valid ones in batches. Returns result for every row.
+    fn import(&self, body: String, import: UsersImport) -> ServiceFuture<UsersImportReport> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+        let password_policy = self.static_context.password_policy.clone();
+
+        debug!("Importing users with parameters: {:?}", import);
+
+        self.spawn_on_pool(move |conn| {
+            let user_roles_repo = repo_factory.create_user_roles_repo_with_sys_acl(&*conn);
+            let is_superuser = match current_uid {
+                Some(current_uid) => user_roles_repo.list_for_user(current_uid)?.contains(&UsersRole::Superuser),
+                None => false,
+            };
+            if !is_superuser {
+                return Err(Error::Forbidden
+                    .context(format!("User {:?} is not allowed to import users", current_uid))
+                    .into());
+            }
+
+            let users_repo = repo_factory.create_users_repo(&*conn, current_uid);
+            let users_repo_with_sys_acl = repo_factory.create_users_repo_with_sys_acl(&*conn);
+            let ident_repo = repo_factory.create_identities_repo(&*conn);
+            let reset_repo = repo_factory.create_reset_token_repo(&*conn);
+
+            let rows = parse_rows(&body, import.format)?;
+
+            let mut results = vec![];
+            let mut valid = vec![];
+            let mut emails = HashSet::new();
+            for (index, row) in rows.into_iter().enumerate() {
+                match validate_row(&*ident_repo, &password_policy, &mut emails, row)? {
+                    Ok(profile) => {
+                        let status = if import.dry_run {
+                            UsersImportRowStatus::Valid
+                        } else {
+                            UsersImportRowStatus::Failed
+                        };
+                        results.push(UsersImportRowResult::new(index + 1, Some(profile.identity.email.clone()), status));
+                        valid.push((index, profile));
+                    }
+                    Err((email, errors)) => results.push(UsersImportRowResult {
+                        errors: Some(errors),
+                        ..UsersImportRowResult::new(index + 1, email, UsersImportRowStatus::Invalid)
+                    }),
+                }
+            }
+
+            if import.dry_run {
+                return Ok(UsersImportReport::new(true, results));
+            }
+
+            for batch in valid.chunks(import.batch_size.max(1)) {
+                let batch_result = conn.transaction::<_, FailureError, _>(|| {
+                    let mut created = vec![];
+                    for &(index, ref profile) in batch {
+                        // every row is created within its own savepoint, so that failed row
+                        // does not roll back the rest of the batch
+                        let row_result = conn.transaction::<_, FailureError, _>(|| {
+                            import_user(
+                                &*users_repo,
+                                &*users_repo_with_sys_acl,
+                                &*ident_repo,
+                                &*reset_repo,
+                                profile.clone(),
+                                import.email_verification,
+                            )
+                        });
+                        created.push((index, row_result));
+                    }
+                    Ok(created)
+                });
+
+                match batch_result {
+                    Ok(created) => {
+                        for (index, row_result) in created {
+                            let result = &mut results[index];
+                            match row_result {
+                                Ok(user) => {
+                                    result.status = UsersImportRowStatus::Created;
+                                    result.user_id = Some(user.id);
+                                }
+                                Err(e) => {
+                                    error!("Import of row {} failed: {}", result.row, e);
+                                    result.errors = Some(row_failed());
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Import of batch failed: {}", e);
+                        for &(index, _) in batch {
+                            results[index].errors = Some(row_failed());
+                        }
+                    }
+                }
+            }
+
+            Ok(UsersImportReport::new(false, results))
+        })
+    }
+}
+
+/// Creates user with identity the same way `UsersService::create` does. Email verification
+/// token is only stored, it is sent to user by notifications the same way as for signup.
+fn import_user(
+    users_repo: &UsersRepo,
+    users_repo_with_sys_acl: &UsersRepo,
+    ident_repo: &IdentitiesRepo,
+    reset_repo: &ResetTokenRepo,
+    profile: SagaCreateProfile,
+    email_verification: bool,
+) -> Result<User, FailureError> {
+    let SagaCreateProfile { identity, user } = profile;
+
+    let mut new_user = user.unwrap_or_else(|| NewUser::from(identity.clone()));
+    check_referal(users_repo, &mut new_user)?;
+    let user = users_repo.create(new_user)?;
+    ident_repo.create(
+        identity.email.clone(),
+        identity.password.map(password_create),
+        identity.provider,
+        user.id,
+        identity.saga_id,
+    )?;
+    let user = set_email_verified_social(users_repo_with_sys_acl, user.id, identity.provider)?.unwrap_or(user);
+
+    if email_verification && identity.provider == Provider::Email {
+        reset_repo.upsert(identity.email, ResetTokenType::EmailVerify, None)?;
+    }
+
+    Ok(user)
+}
+
+/// Validates row with the same rules as `UsersService::create` and checks that email is unique
+/// both in db and in the file. Returns email of invalid row along with validation errors.
+fn validate_row(
+    ident_repo: &IdentitiesRepo,
+    password_policy: &PasswordPolicy,
+    emails: &mut HashSet<String>,
+    row: Result<SagaCreateProfile, String>,
+) -> Result<Result<SagaCreateProfile, (Option<String>, ValidationErrors)>, FailureError> {
+    let mut profile = match row {
+        Ok(profile) => profile,
+        Err(message) => return Ok(Err((None, row_error("parse", message)))),
+    };
+
+    let email = profile.identity.email.to_lowercase();
+    profile.identity.email = email.clone();
+    if let Some(ref mut user) = profile.user {
+        user.email = email.clone();
+        user.saga_id = profile.identity.saga_id.clone();
+    }
+
+    let mut errors = None;
+    if let Err(e) = profile.identity.validate() {
+        merge_errors(&mut errors, e);
+    }
+    if let (Provider::Email, Some(password)) = (profile.identity.provider, profile.identity.password.as_ref()) {
+        if let Err(e) = password_policy.check("password", password, Some(&email)) {
+            merge_errors(&mut errors, e);
+        }
+    }
+    if let Some(Err(e)) = profile.user.as_ref().map(|user| user.validate()) {
+        merge_errors(&mut errors, e);
+    }
+    if !emails.insert(email.clone()) {
+        merge_errors(
+            &mut errors,
+            validation_errors!({"email": ["duplicate" => "Email is duplicated in the file"]}),
+        );
+    } else if ident_repo.email_exists(email.clone())? {
+        merge_errors(&mut errors, validation_errors!({"email": ["exists" => "Email already exists"]}));
+    }
+
+    match errors {
+        None => Ok(Ok(profile)),
+        Some(errors) => Ok(Err((Some(email), errors))),
+    }
+}
+
+fn merge_errors(errors: &mut Option<ValidationErrors>, other: ValidationErrors) {
+    let errors = errors.get_or_insert_with(ValidationErrors::new);
+    for (field, field_errors) in other.inner() {
+        for error in field_errors {
+            errors.add(field, error);
+        }
+    }
+}
+
+fn row_error(code: &'static str, message: String) -> ValidationErrors {
+    validation_errors!({"row": [code => message]})
+}
+
+/// Error of row that is valid but could not be created, details are logged, not reported
+fn row_failed() -> ValidationErrors {
+    row_error("failed", "User could not be created".to_string())
+}
+
+/// Splits file into rows, each row is either parsed profile or parse error message
+fn parse_rows(body: &str, format: UsersFileFormat) -> Result<Vec<Result<SagaCreateProfile, String>>, FailureError> {
+    match format {
+        UsersFileFormat::Jsonl => Ok(body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str::<SagaCreateProfile>(line).map_err(|e| e.to_string()))
+            .collect()),
+        UsersFileFormat::Csv => {
+            let mut records = parse_csv(body).into_iter();
+            let header = records
+                .next()
+                .ok_or_else(|| format_err!("CSV file is empty").context(Error::Parse))?;
+            if !header.iter().any(|column| column == "email") {
+                return Err(format_err!("CSV file has no email column").context(Error::Parse).into());
+            }
+
+            Ok(records
+                .filter(|record| record.iter().any(|field| !field.is_empty()))
+                .map(|record| {
+                    let fields = header.iter().cloned().zip(record.into_iter()).collect::<HashMap<_, _>>();
+                    profile_from_csv(&fields)
+                })
+                .collect())
+        }
+    }
+}
+
+/// Builds profile from CSV record. Only `email` column is required, `provider` defaults to email
+/// and `saga_id` is generated if missing.
+fn profile_from_csv(fields: &HashMap<String, String>) -> Result<SagaCreateProfile, String> {
+    let field = |name: &str| {
+        fields
+            .get(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let email = field("email").ok_or_else(|| "Email is missing".to_string())?;
+    let provider = match field("provider") {
+        Some(provider) => from_csv_value(&provider).map_err(|_| format!("Unknown provider {}", provider))?,
+        None => Provider::Email,
+    };
+    let saga_id = field("saga_id").unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let identity = NewIdentity {
+        email,
+        password: field("password"),
+        provider,
+        saga_id,
+    };
+
+    let mut user = NewUser::from(identity.clone());
+    user.phone = field("phone");
+    user.first_name = field("first_name");
+    user.last_name = field("last_name");
+    user.middle_name = field("middle_name");
+    user.referer = field("referer");
+    if let Some(gender) = field("gender") {
+        user.gender = Some(from_csv_value(&gender).map_err(|_| format!("Unknown gender {}", gender))?);
+    }
+    if let Some(birthdate) = field("birthdate") {
+        user.birthdate = Some(
+            birthdate
+                .parse::<NaiveDate>()
+                .map_err(|_| format!("Invalid birthdate {}", birthdate))?,
+        );
+    }
+    if let Some(country) = field("country") {
+        user.country = Some(from_csv_value(&country).map_err(|_| format!("Invalid country {}", country))?);
+    }
+    if let Some(referal) = field("referal") {
+        user.referal = Some(UserId(referal.parse().map_err(|_| format!("Invalid referal {}", referal))?));
+    }
+
+    Ok(SagaCreateProfile {
+        identity,
+        user: Some(user),
+    })
+}
+
+/// Deserializes enum or newtype from plain CSV string
+fn from_csv_value<V: DeserializeOwned>(value: &str) -> Result<V, serde_json::Error> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+}
+
+/// Parses CSV into records. Fields may be quoted, quotes inside quoted fields are doubled,
+/// quoted fields may contain separators and line breaks.
+fn parse_csv(body: &str) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(::std::mem::replace(&mut field, String::new())),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(::std::mem::replace(&mut field, String::new()));
+                records.push(::std::mem::replace(&mut record, vec![]));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use tokio_core::reactor::Core;
+
+    use stq_types::UserId;
+
+    use models::{UsersFileFormat, UsersImport, UsersImportRowStatus};
+    use repos::repo_factory::tests::*;
+    use services::users_import::{parse_csv, UsersImportService};
+
+    #[test]
+    fn test_parse_csv() {
+        let records = parse_csv("email,first_name\r\na@mail.com,\"Doe, \"\"John\"\"\"\nb@mail.com,\"Multi\nline\"\n");
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1], vec!["a@mail.com".to_string(), "Doe, \"John\"".to_string()]);
+        assert_eq!(records[2], vec!["b@mail.com".to_string(), "Multi\nline".to_string()]);
+    }
+
+    #[test]
+    fn test_import_dry_run() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let body = [
+            "email,password,first_name".to_string(),
+            "new@mail.com,password123,John".to_string(),
+            format!("{},password123,Jane", MOCK_EMAIL),
+            "not_an_email,password123,".to_string(),
+            "NEW@mail.com,password123,".to_string(),
+        ]
+        .join("\n");
+        let import = UsersImport {
+            format: UsersFileFormat::Csv,
+            dry_run: true,
+            ..Default::default()
+        };
+        let work = service.import(body, import);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.total, 4);
+        assert_eq!(result.valid, 1);
+        assert_eq!(result.invalid, 3);
+        assert_eq!(result.created, 0);
+        assert_eq!(result.rows[0].status, UsersImportRowStatus::Valid);
+        assert_eq!(result.rows[3].email, Some("new@mail.com".to_string()));
+    }
+
+    #[test]
+    fn test_import_jsonl() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let body = r#"{"identity": {"email": "new@mail.com", "password": "password123", "provider": "Email", "saga_id": "import"}}"#;
+        let import = UsersImport {
+            format: UsersFileFormat::Jsonl,
+            email_verification: true,
+            ..Default::default()
+        };
+        let work = service.import(body.to_string(), import);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.created, 1);
+        assert_eq!(result.rows[0].status, UsersImportRowStatus::Created);
+        assert_eq!(result.rows[0].email, Some("new@mail.com".to_string()));
+        assert!(result.rows[0].errors.is_none());
+    }
+
+    #[test]
+    fn test_import_forbidden() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(2)), handle);
+        let body = r#"{"identity": {"email": "new@mail.com", "password": "password123", "provider": "Email", "saga_id": "import"}}"#;
+        let import = UsersImport {
+            format: UsersFileFormat::Jsonl,
+            ..Default::default()
+        };
+        let work = service.import(body.to_string(), import);
+        let result = core.run(work);
+        assert!(result.is_err());
+    }
+}
@@ -1,10 +1,19 @@
+use std::time::SystemTime;
+
 use base64::{decode, encode};
 use rand;
 use rand::Rng;
 use sha3::{Digest, Sha3_256};
 
+use failure::Fail;
+use serde_json;
+
+use stq_types::UserId;
+
 use errors::Error;
+use models::User;
 use repos::types::RepoResult;
+use repos::{UserBlocksRepo, UsersRepo};
 
 pub fn password_create(clear_password: String) -> String {
     let salt = rand::thread_rng().gen_ascii_chars().take(10).collect::<String>();
@@ -31,3 +40,55 @@ pub fn password_verify(db_hash: &str, clear_password: String) -> RepoResult<bool
             .map_err(|_| Error::Validate(validation_errors!({"password": ["password" => "Password in db has wrong format"]})).into())
     }
 }
+
+/// Locks user until the end of transaction and checks that it still matches
+/// one of entity tags from `If-Match` header. `None` means that request has no preconditions.
+pub fn check_entity_tag(users_repo: &UsersRepo, user_id: UserId, if_match: &Option<Vec<String>>) -> RepoResult<User> {
+    let user = users_repo
+        .find_for_update(user_id)?
+        .ok_or_else(|| Error::NotFound.context(format!("User {} not found", user_id)))?;
+
+    match *if_match {
+        Some(ref tags) if !tags.contains(&user.entity_tag()) => {
+            let current = serde_json::to_value(&user)?;
+            Err(Error::PreconditionFailed(current)
+                .context(format!("User {} has been modified", user_id))
+                .into())
+        }
+        _ => Ok(user),
+    }
+}
+
+/// Fails with `email.blocked` error, carrying reason and end of the block, if the user is blocked.
+/// Expired temporary block is lifted on the way, so that the user is able to sign in again.
+pub fn check_user_block(users_repo: &UsersRepo, user_blocks_repo: &UserBlocksRepo, user: &User) -> RepoResult<()> {
+    if !user.is_blocked {
+        return Ok(());
+    }
+
+    match user_blocks_repo.find_current(user.id)? {
+        Some(ref block) if !block.is_active_at(SystemTime::now()) => {
+            debug!("Block of user {} has expired, lifting it.", user.id);
+            user_blocks_repo.lift(user.id, None)?;
+            users_repo.set_block_status(user.id, false, None).map(|_| ())
+        }
+        Some(block) => {
+            error!("User {} is blocked.", user.id);
+            Err(Error::Validate(block.validation_errors()).into())
+        }
+        None => {
+            error!("User {} is blocked.", user.id);
+            Err(Error::Validate(validation_errors!({"email": ["blocked" => "Email is blocked"]})).into())
+        }
+    }
+}
+
+/// Deactivated users can't sign in or be updated until they are reactivated
+pub fn check_user_active(user: &User) -> RepoResult<()> {
+    if user.is_active {
+        Ok(())
+    } else {
+        error!("User {} is deactivated.", user.id);
+        Err(Error::Validate(validation_errors!({"email": ["deactivated" => "Account is deactivated"]})).into())
+    }
+}
@@ -0,0 +1,438 @@
+//! Webhooks Services, presents CRUD operations with webhooks and
+//! delivery of signed security events to subscribers. Events are queued
+//! and sent by background job.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use diesel::connection::AnsiTransactionManager;
+use diesel::pg::Pg;
+use diesel::Connection;
+use failure::Error as FailureError;
+use failure::Fail;
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use hmac::{Hmac, Mac};
+use hyper::client::{Client, HttpConnector, Request};
+use hyper::header::ContentType;
+use hyper::{Method, Uri};
+use hyper_tls::HttpsConnector;
+use r2d2::ManageConnection;
+use serde_json;
+use sha2::Sha256;
+use tokio_core::reactor::{Handle, Interval, Timeout};
+use uuid::Uuid;
+
+use stq_http::client::TimeLimitedHttpClient;
+
+use controller::context::{DynamicContext, DynamicContextServices, StaticContext};
+use errors::Error;
+use models::{NewWebhook, NewWebhookDelivery, SecurityEvent, Webhook, WebhookDelivery};
+use repos::ReposFactory;
+use services::types::ServiceFuture;
+use services::Service;
+
+pub trait WebhooksService {
+    /// Returns list of webhooks
+    fn list_webhooks(&self) -> ServiceFuture<Vec<Webhook>>;
+    /// Creates new webhook
+    fn create_webhook(&self, payload: NewWebhook) -> ServiceFuture<Webhook>;
+    /// Deletes specific webhook
+    fn delete_webhook(&self, id: Uuid) -> ServiceFuture<Webhook>;
+    /// Returns latest webhook deliveries
+    fn list_webhook_deliveries(&self, failed_only: bool, count: i64) -> ServiceFuture<Vec<WebhookDelivery>>;
+    /// Puts failed delivery back to the queue, it is sent once again by the delivery job
+    fn replay_webhook_delivery(&self, id: Uuid) -> ServiceFuture<WebhookDelivery>;
+    /// Queues security event for all subscribed webhooks. Errors are only logged.
+    fn emit_security_event(&self, event: SecurityEvent) -> ServiceFuture<()>;
+    /// Sends up to `count` queued deliveries and records results of attempts.
+    /// Returns number of sent deliveries.
+    fn send_pending_webhook_deliveries(&self, sender: WebhookSender, count: i64) -> ServiceFuture<usize>;
+}
+
+impl<
+        T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+        M: ManageConnection<Connection = T>,
+        F: ReposFactory<T>,
+    > WebhooksService for Service<T, M, F>
+{
+    /// Returns list of webhooks
+    fn list_webhooks(&self) -> ServiceFuture<Vec<Webhook>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let webhooks_repo = repo_factory.create_webhooks_repo(&*conn, current_uid);
+            webhooks_repo
+                .list()
+                .map_err(|e: FailureError| e.context("Service webhooks, list endpoint error occured.").into())
+        })
+    }
+
+    /// Creates new webhook
+    fn create_webhook(&self, payload: NewWebhook) -> ServiceFuture<Webhook> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let webhooks_repo = repo_factory.create_webhooks_repo(&*conn, current_uid);
+            webhooks_repo
+                .create(payload)
+                .map_err(|e: FailureError| e.context("Service webhooks, create endpoint error occured.").into())
+        })
+    }
+
+    /// Deletes specific webhook
+    fn delete_webhook(&self, id: Uuid) -> ServiceFuture<Webhook> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let webhooks_repo = repo_factory.create_webhooks_repo(&*conn, current_uid);
+            webhooks_repo
+                .delete(id)
+                .map_err(|e: FailureError| e.context("Service webhooks, delete endpoint error occured.").into())
+        })
+    }
+
+    /// Returns latest webhook deliveries
+    fn list_webhook_deliveries(&self, failed_only: bool, count: i64) -> ServiceFuture<Vec<WebhookDelivery>> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        self.spawn_on_pool(move |conn| {
+            let deliveries_repo = repo_factory.create_webhook_deliveries_repo(&*conn, current_uid);
+            deliveries_repo
+                .list(failed_only, count)
+                .map_err(|e: FailureError| e.context("Service webhooks, list_deliveries endpoint error occured.").into())
+        })
+    }
+
+    /// Puts failed delivery back to the queue, it is sent once again by the delivery job
+    fn replay_webhook_delivery(&self, id: Uuid) -> ServiceFuture<WebhookDelivery> {
+        let current_uid = self.dynamic_context.user_id;
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Replaying webhook delivery {}", id);
+
+        self.spawn_on_pool(move |conn| {
+            let deliveries_repo = repo_factory.create_webhook_deliveries_repo(&*conn, current_uid);
+
+            deliveries_repo
+                .find(id)
+                .and_then(|delivery| {
+                    let delivery = delivery.ok_or_else(|| Error::NotFound.context(format!("Webhook delivery {} not found", id)))?;
+                    if delivery.succeeded {
+                        return Err(Error::Validate(
+                            validation_errors!({"delivery": ["succeeded" => "Webhook delivery has already succeeded"]}),
+                        )
+                        .into());
+                    }
+                    deliveries_repo.requeue(id)
+                })
+                .map_err(|e: FailureError| e.context("Service webhooks, replay_delivery endpoint error occured.").into())
+        })
+    }
+
+    /// Queues security event for all subscribed webhooks. Errors are only logged.
+    fn emit_security_event(&self, event: SecurityEvent) -> ServiceFuture<()> {
+        let repo_factory = self.static_context.repo_factory.clone();
+
+        debug!("Emitting security event {:?}", event);
+
+        let fut = self
+            .spawn_on_pool(move |conn| {
+                let webhooks_repo = repo_factory.create_webhooks_repo_with_sys_acl(&*conn);
+                let deliveries_repo = repo_factory.create_webhook_deliveries_repo_with_sys_acl(&*conn);
+                let payload = serde_json::to_value(&event)?;
+
+                for webhook in webhooks_repo.list_subscribed(event.event_type)? {
+                    deliveries_repo.create(NewWebhookDelivery {
+                        webhook_id: webhook.id,
+                        event_type: event.event_type,
+                        payload: payload.clone(),
+                    })?;
+                }
+                Ok(())
+            })
+            .then(|res| {
+                if let Err(e) = res {
+                    error!("Security event queueing failed: {}", e);
+                }
+                Ok::<(), FailureError>(())
+            });
+
+        Box::new(fut)
+    }
+
+    /// Sends up to `count` queued deliveries and records results of attempts.
+    /// Returns number of sent deliveries.
+    fn send_pending_webhook_deliveries(&self, sender: WebhookSender, count: i64) -> ServiceFuture<usize> {
+        let repo_factory = self.static_context.repo_factory.clone();
+        let service = self.clone();
+
+        let fut = self
+            .spawn_on_pool({
+                let repo_factory = repo_factory.clone();
+                move |conn| {
+                    let webhooks_repo = repo_factory.create_webhooks_repo_with_sys_acl(&*conn);
+                    let deliveries_repo = repo_factory.create_webhook_deliveries_repo_with_sys_acl(&*conn);
+
+                    let mut claimed = vec![];
+                    for delivery in deliveries_repo.claim_pending(count)? {
+                        if let Some(webhook) = webhooks_repo.find(delivery.webhook_id)? {
+                            claimed.push((webhook, delivery));
+                        }
+                    }
+                    Ok(claimed)
+                }
+            })
+            .and_then(move |claimed| {
+                future::join_all(
+                    claimed
+                        .into_iter()
+                        .map(move |(webhook, delivery)| {
+                            let delivery_id = delivery.id;
+                            sender
+                                .send(&webhook, &delivery)
+                                .map(move |(response_code, error)| (delivery_id, response_code, error))
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .and_then(move |results| {
+                service.spawn_on_pool(move |conn| {
+                    let deliveries_repo = repo_factory.create_webhook_deliveries_repo_with_sys_acl(&*conn);
+                    for (delivery_id, response_code, error) in &results {
+                        deliveries_repo.record_attempt(*delivery_id, *response_code, error.clone())?;
+                    }
+                    Ok(results.len())
+                })
+            })
+            .map_err(|e: FailureError| {
+                e.context("Service webhooks, send_pending_deliveries endpoint error occured.")
+                    .into()
+            });
+
+        Box::new(fut)
+    }
+}
+
+/// Posts signed deliveries to webhook urls. Only response status is taken
+/// into account, the body is never read.
+#[derive(Clone)]
+pub struct WebhookSender {
+    client: Client<HttpsConnector<HttpConnector>>,
+    handle: Handle,
+    timeout: Duration,
+}
+
+impl WebhookSender {
+    pub fn new(handle: &Handle, dns_worker_thread_count: usize, timeout: Duration) -> Result<Self, FailureError> {
+        let connector = HttpsConnector::new(dns_worker_thread_count, handle)?;
+        let client = Client::configure().connector(connector).build(handle);
+        Ok(Self {
+            client,
+            handle: handle.clone(),
+            timeout,
+        })
+    }
+
+    /// Posts delivery payload to webhook url, resolves to response code and error of the attempt
+    pub fn send(
+        &self,
+        webhook: &Webhook,
+        delivery: &WebhookDelivery,
+    ) -> Box<Future<Item = (Option<i32>, Option<String>), Error = FailureError>> {
+        let uri = match webhook.url.parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(e) => return Box::new(future::ok((None, Some(format!("Invalid url: {}", e))))),
+        };
+        let timeout = match Timeout::new(self.timeout, &self.handle) {
+            Ok(timeout) => timeout,
+            Err(e) => return Box::new(future::err(e.into())),
+        };
+        let timestamp = Utc::now().timestamp();
+        let body = delivery.payload.to_string();
+
+        let mut request = Request::new(Method::Post, uri);
+        {
+            let headers = request.headers_mut();
+            headers.set(ContentType::json());
+            headers.set_raw("X-Stq-Event", delivery.event_type.to_string());
+            headers.set_raw("X-Stq-Delivery", delivery.id.to_string());
+            headers.set_raw("X-Stq-Timestamp", timestamp.to_string());
+            headers.set_raw(
+                "X-Stq-Signature",
+                format!("sha256={}", sign_payload(&webhook.secret, timestamp, &body)),
+            );
+        }
+        request.set_body(body);
+
+        debug!("Delivering {} event to webhook {}", delivery.event_type, webhook.url);
+
+        let fut = self.client.request(request).select2(timeout).then(|res| {
+            let result = match res {
+                Ok(Either::A((response, _))) => {
+                    let status = response.status();
+                    let error = if status.is_success() {
+                        None
+                    } else {
+                        Some(format!("Responded with {}", status))
+                    };
+                    (Some(i32::from(status.as_u16())), error)
+                }
+                Ok(Either::B(_)) => (None, Some("Timed out".to_string())),
+                Err(Either::A((e, _))) => (None, Some(format!("{}", e))),
+                Err(Either::B((e, _))) => (None, Some(format!("{}", e))),
+            };
+            Ok::<_, FailureError>(result)
+        });
+
+        Box::new(fut)
+    }
+}
+
+/// Runs `send_pending_webhook_deliveries` every `Config.webhooks.job_interval_s` on the reactor
+pub fn spawn_webhook_delivery_job<T, M, F>(static_context: StaticContext<T, M, F>, handle: &Handle) -> Result<(), FailureError>
+where
+    T: Connection<Backend = Pg, TransactionManager = AnsiTransactionManager> + 'static,
+    M: ManageConnection<Connection = T>,
+    F: ReposFactory<T>,
+{
+    let interval = Duration::from_secs(static_context.config.webhooks.job_interval_s);
+    let batch_size = static_context.config.webhooks.job_batch_size;
+    let http_timeout = Duration::from_millis(static_context.config.client.http_timeout_ms);
+    let sender = WebhookSender::new(handle, static_context.config.client.dns_worker_thread_count, http_timeout)?;
+    let time_limited_http_client = TimeLimitedHttpClient::new(static_context.client_handle.clone(), http_timeout);
+    let DynamicContextServices {
+        google_provider_service,
+        facebook_provider_service,
+    } = static_context.dynamic_context_services(time_limited_http_client.clone());
+    let dynamic_context = DynamicContext::new(
+        None,
+        "webhook_delivery_job".to_string(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        time_limited_http_client,
+        google_provider_service,
+        facebook_provider_service,
+    );
+    let service = Service::new(static_context, dynamic_context);
+
+    let job = Interval::new(interval, handle)?
+        .map_err(|e| error!("Webhook delivery job timer error: {}", e))
+        .for_each(move |_| {
+            service.send_pending_webhook_deliveries(sender.clone(), batch_size).then(|result| {
+                match result {
+                    Ok(sent) if sent > 0 => debug!("Sent {} webhook deliveries", sent),
+                    Ok(_) => {}
+                    Err(e) => error!("Webhook delivery job error: {}", e),
+                }
+                Ok(())
+            })
+        });
+    handle.spawn(job);
+
+    Ok(())
+}
+
+/// Computes hex encoded HMAC-SHA256 signature of `"{timestamp}.{body}"` with webhook secret
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.input(format!("{}.{}", timestamp, body).as_bytes());
+    mac.result().code().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    use stq_types::UserId;
+
+    use errors::Error;
+    use models::{NewWebhook, SecurityEvent, SecurityEventType};
+    use repos::repo_factory::tests::*;
+    use services::webhooks::{sign_payload, WebhookSender, WebhooksService};
+
+    #[test]
+    fn test_sign_payload() {
+        let signature = sign_payload("webhook_secret_key", 1550000000, "{}");
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, sign_payload("webhook_secret_key", 1550000000, "{}"));
+        assert_ne!(signature, sign_payload("webhook_secret_key", 1550000001, "{}"));
+        assert_ne!(signature, sign_payload("another_secret_key", 1550000000, "{}"));
+    }
+
+    #[test]
+    fn test_create_webhook() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let payload = NewWebhook {
+            url: "http://localhost/webhook".to_string(),
+            secret: "webhook_secret_key".to_string(),
+            event_types: vec![SecurityEventType::AccountBlocked],
+        };
+        let work = service.create_webhook(payload);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.event_types, vec![SecurityEventType::AccountBlocked]);
+    }
+
+    #[test]
+    fn test_emit_security_event_without_subscribers() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let event = SecurityEvent::new(SecurityEventType::PasswordChanged, UserId(1), None);
+        let work = service.emit_security_event(event);
+        assert!(core.run(work).is_ok());
+    }
+
+    #[test]
+    fn test_replay_failed_webhook_delivery() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let id = Uuid::new_v4();
+        let failed = create_webhook_delivery(id);
+        assert!(!failed.succeeded && !failed.pending && failed.error.is_some());
+
+        let work = service.replay_webhook_delivery(id);
+        let result = core.run(work).unwrap();
+        assert_eq!(result.id, id);
+        assert!(result.pending);
+        assert!(result.error.is_none());
+    }
+
+    #[test]
+    fn test_replay_missing_webhook_delivery() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let service = create_service(Some(UserId(1)), handle);
+        let work = service.replay_webhook_delivery(Uuid::nil());
+        let error = core.run(work).unwrap_err();
+        match error.causes().filter_map(|cause| cause.downcast_ref::<Error>()).next() {
+            Some(Error::NotFound) => {}
+            _ => panic!("Missing delivery is not reported as not found: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_send_pending_webhook_deliveries_without_queue() {
+        let mut core = Core::new().unwrap();
+        let handle = Arc::new(core.handle());
+        let sender = WebhookSender::new(&core.handle(), 1, Duration::from_secs(1)).unwrap();
+        let service = create_service(None, handle);
+        let work = service.send_pending_webhook_deliveries(sender, 10);
+        let result = core.run(work).unwrap();
+        assert_eq!(result, 0);
+    }
+}